@@ -0,0 +1,225 @@
+use crate::dpad::stick_to_dpad;
+use crate::gamepad::{Button, Buttons, GamepadState, StickPosition};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OneHanded {
+    Off,
+    // the right stick presses X, A, B and Y by direction and reads centered
+    RightStickToFaceButtons,
+    // the right stick presses the d-pad and reads centered
+    RightStickToDpad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessibilityConfig {
+    // one press latches these, the next press lets go
+    pub toggle: Buttons,
+    // these stay held after a tap until the next other button is released
+    pub sticky: Buttons,
+    pub one_handed: OneHanded,
+}
+
+impl Default for AccessibilityConfig {
+    fn default() -> Self {
+        Self {
+            toggle: Buttons::NONE,
+            sticky: Buttons::NONE,
+            one_handed: OneHanded::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accessibility {
+    pub config: AccessibilityConfig,
+    held: Buttons,
+    latched: Buttons,
+    armed: Buttons,
+    // an other button was pressed while modifiers were armed
+    chorded: bool,
+}
+
+impl Accessibility {
+    pub fn new(config: AccessibilityConfig) -> Self {
+        Self {
+            config,
+            held: Buttons::NONE,
+            latched: Buttons::NONE,
+            armed: Buttons::NONE,
+            chorded: false,
+        }
+    }
+
+    pub fn process(&mut self, mut state: GamepadState) -> GamepadState {
+        match self.config.one_handed {
+            OneHanded::Off => (),
+            OneHanded::RightStickToDpad => {
+                state.buttons = state.buttons | stick_to_dpad(state.right_stick);
+                state.right_stick = StickPosition::CENTER;
+            }
+            OneHanded::RightStickToFaceButtons => {
+                let dpad = stick_to_dpad(state.right_stick);
+                for (direction, face) in [
+                    (Button::Up, Button::X),
+                    (Button::Right, Button::A),
+                    (Button::Down, Button::B),
+                    (Button::Left, Button::Y),
+                ] {
+                    if dpad.is_pressed(direction) {
+                        state.buttons.press(face)
+                    }
+                }
+                state.right_stick = StickPosition::CENTER;
+            }
+        }
+
+        let buttons = state.buttons;
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+
+        let toggle = self.config.toggle;
+        self.latched = self.latched ^ (pressed & toggle);
+
+        let sticky = self.config.sticky;
+        // tapping an armed modifier again cancels it
+        self.armed = self.armed ^ (pressed & sticky);
+        let others = !(toggle | sticky);
+        if !self.armed.is_empty() && !(pressed & others).is_empty() {
+            self.chorded = true
+        }
+        if self.chorded && (buttons & others).is_empty() {
+            self.armed = Buttons::NONE;
+            self.chorded = false;
+        }
+
+        state.buttons = (buttons & !toggle) | self.latched | self.armed;
+        state
+    }
+}
+
+impl Default for Accessibility {
+    fn default() -> Self {
+        Self::new(AccessibilityConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(buttons: &[Button]) -> GamepadState {
+        let mut state = GamepadState::NEUTRAL;
+        for &button in buttons {
+            state.buttons.press(button)
+        }
+        state
+    }
+
+    // feeds the held buttons one report each and returns the buttons sent on
+    fn replay(accessibility: &mut Accessibility, reports: &[&[Button]]) -> Vec<Buttons> {
+        reports
+            .iter()
+            .map(|held| accessibility.process(pressed(held)).buttons)
+            .collect()
+    }
+
+    fn buttons(pressed: &[Button]) -> Buttons {
+        let mut buttons = Buttons::NONE;
+        for &button in pressed {
+            buttons.press(button)
+        }
+        buttons
+    }
+
+    #[test]
+    fn a_toggle_latches_until_pressed_again() {
+        let mut accessibility = Accessibility::new(AccessibilityConfig {
+            toggle: Button::ZR.into(),
+            ..AccessibilityConfig::default()
+        });
+        let zr = buttons(&[Button::ZR]);
+        assert_eq!(
+            replay(
+                &mut accessibility,
+                &[&[Button::ZR], &[], &[Button::A], &[Button::ZR], &[]]
+            ),
+            [zr, zr, zr | Button::A.into(), Buttons::NONE, Buttons::NONE]
+        );
+    }
+
+    #[test]
+    fn a_sticky_button_holds_until_the_next_press_is_released() {
+        let mut accessibility = Accessibility::new(AccessibilityConfig {
+            sticky: Button::L.into(),
+            ..AccessibilityConfig::default()
+        });
+        let l = buttons(&[Button::L]);
+        assert_eq!(
+            replay(
+                &mut accessibility,
+                &[
+                    &[Button::L],
+                    &[],
+                    &[Button::A],
+                    &[Button::A, Button::B],
+                    &[Button::B],
+                    &[],
+                    &[Button::A]
+                ]
+            ),
+            [
+                l,
+                l,
+                l | Button::A.into(),
+                buttons(&[Button::L, Button::A, Button::B]),
+                l | Button::B.into(),
+                Buttons::NONE,
+                buttons(&[Button::A]),
+            ]
+        );
+    }
+
+    #[test]
+    fn tapping_a_sticky_button_again_lets_go() {
+        let mut accessibility = Accessibility::new(AccessibilityConfig {
+            sticky: Button::L.into(),
+            ..AccessibilityConfig::default()
+        });
+        let l = buttons(&[Button::L]);
+        assert_eq!(
+            replay(&mut accessibility, &[&[Button::L], &[], &[Button::L], &[]]),
+            [l, l, l, Buttons::NONE]
+        );
+    }
+
+    #[test]
+    fn one_handed_modes_read_the_right_stick_as_buttons() {
+        let up = GamepadState {
+            right_stick: StickPosition::new(StickPosition::CENTER.x, StickPosition::MAX),
+            ..GamepadState::NEUTRAL
+        };
+        let right = GamepadState {
+            right_stick: StickPosition::new(StickPosition::MAX, StickPosition::CENTER.y),
+            ..GamepadState::NEUTRAL
+        };
+        for (one_handed, state, expected) in [
+            (OneHanded::RightStickToFaceButtons, up, Button::X),
+            (OneHanded::RightStickToFaceButtons, right, Button::A),
+            (OneHanded::RightStickToDpad, up, Button::Up),
+            (OneHanded::RightStickToDpad, right, Button::Right),
+        ] {
+            let mut accessibility = Accessibility::new(AccessibilityConfig {
+                one_handed,
+                ..AccessibilityConfig::default()
+            });
+            let output = accessibility.process(state);
+            assert_eq!(output.buttons, expected.into());
+            assert_eq!(output.right_stick, StickPosition::CENTER);
+        }
+        // off leaves the stick alone
+        assert_eq!(Accessibility::default().process(up), up);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::input::{XboxButton, XboxButtons, XboxState};
+use embassy_time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+// a full NTAG215 dump, as written by common amiibo tools
+pub const NTAG215_SIZE: usize = 540;
+pub const NTAG215_PAGES: u8 = (NTAG215_SIZE / 4) as u8;
+// pages 0 to 2 hold the uid and the static lock bytes
+pub const FIRST_WRITABLE_PAGE: u8 = 3;
+pub const AMIIBO_MODIFIER: XboxButton = XboxButton::View;
+// how long a scanned amiibo stays on the virtual reader
+pub const TAG_PRESENT_MS: u64 = 3000;
+// the first uid byte of every real NTAG215
+const NXP_MANUFACTURER: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AmiiboConfig {
+    // Scans get a fresh uid each time, for games that hand out one reward per tag a day.
+    // The dump is not re-signed for the new uid, the adapter has no keys for that, so
+    // games that check the signature refuse the tag as damaged. Writes are saved back
+    // with the slot's own uid.
+    pub random_uid: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amiibo {
+    data: [u8; NTAG215_SIZE],
+}
+
+impl Amiibo {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            data: bytes.try_into().ok()?,
+        })
+    }
+
+    pub fn bytes(&self) -> &[u8; NTAG215_SIZE] {
+        &self.data
+    }
+
+    // the 7 byte uid, stored around the first check byte of page 0
+    pub fn uid(&self) -> [u8; 7] {
+        let d = &self.data;
+        [d[0], d[1], d[2], d[4], d[5], d[6], d[7]]
+    }
+
+    // writes the uid and both of its check bytes
+    pub fn set_uid(&mut self, uid: [u8; 7]) {
+        let d = &mut self.data;
+        d[..3].copy_from_slice(&uid[..3]);
+        d[3] = 0x88 ^ uid[0] ^ uid[1] ^ uid[2];
+        d[4..8].copy_from_slice(&uid[3..]);
+        d[8] = uid[3] ^ uid[4] ^ uid[5] ^ uid[6];
+    }
+
+    // returns false for pages a real tag would refuse to write
+    pub fn write_page(&mut self, page: u8, data: [u8; 4]) -> bool {
+        if !(FIRST_WRITABLE_PAGE..NTAG215_PAGES).contains(&page) {
+            return false;
+        }
+        let offset = page as usize * 4;
+        self.data[offset..offset + 4].copy_from_slice(&data);
+        true
+    }
+}
+
+// an amiibo lying on the reader until `until`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag {
+    // the amiibo slot this was loaded from
+    pub slot: u8,
+    pub amiibo: Amiibo,
+    pub until: Instant,
+    // the uid stored in the slot, the amiibo may show a random one
+    stored_uid: [u8; 7],
+}
+
+impl Tag {
+    pub fn place(slot: u8, amiibo: Amiibo, now: Instant) -> Self {
+        Self {
+            slot,
+            amiibo,
+            until: now + Duration::from_millis(TAG_PRESENT_MS),
+            stored_uid: amiibo.uid(),
+        }
+    }
+
+    // shows the switch a uid made from `random` instead of the stored one
+    pub fn with_random_uid(mut self, mut random: [u8; 7]) -> Self {
+        random[0] = NXP_MANUFACTURER;
+        self.amiibo.set_uid(random);
+        self
+    }
+
+    // the amiibo as it should be saved, with the slot's own uid
+    pub fn stored(&self) -> Amiibo {
+        let mut amiibo = self.amiibo;
+        amiibo.set_uid(self.stored_uid);
+        amiibo
+    }
+
+    pub fn present(&self, now: Instant) -> bool {
+        now < self.until
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AmiiboAction {
+    // put the active amiibo on the reader
+    Scan,
+    // make the next stored amiibo the active one
+    Cycle,
+}
+
+// watches for the amiibo combos and hides them from the rest of the pipeline
+#[derive(Debug, Default)]
+pub struct AmiiboTrigger {
+    held: XboxButtons,
+    consumed: XboxButtons,
+}
+
+impl AmiiboTrigger {
+    pub fn input(&mut self, xbox: &mut XboxState) -> Option<AmiiboAction> {
+        let buttons = xbox.buttons;
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+        self.consumed = self.consumed & buttons;
+
+        let mut action = None;
+        if buttons.is_pressed(AMIIBO_MODIFIER) {
+            for (button, combo) in [
+                (XboxButton::Up, AmiiboAction::Scan),
+                (XboxButton::Down, AmiiboAction::Cycle),
+            ] {
+                if pressed.is_pressed(button) {
+                    self.consumed = self.consumed | button.into() | AMIIBO_MODIFIER.into();
+                    action = Some(combo);
+                }
+            }
+        }
+
+        xbox.buttons = buttons & !self.consumed;
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_random_uid_looks_like_an_ntag215_and_is_not_stored() {
+        let data: Vec<u8> = (0..NTAG215_SIZE).map(|i| i as u8).collect();
+        let mut amiibo = Amiibo::from_bytes(&data).unwrap();
+        amiibo.set_uid([0x04, 1, 2, 3, 4, 5, 6]);
+        let tag = Tag::place(1, amiibo, Instant::from_millis(0))
+            .with_random_uid([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x11]);
+
+        assert_eq!(tag.amiibo.uid(), [0x04, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x11]);
+        let bytes = tag.amiibo.bytes();
+        // both check bytes follow the new uid
+        assert_eq!(bytes[3], 0x88 ^ 0x04 ^ 0xbb ^ 0xcc);
+        assert_eq!(bytes[8], 0xdd ^ 0xee ^ 0xff ^ 0x11);
+        assert_eq!(bytes[9..], data[9..]);
+        assert_eq!(tag.stored(), amiibo);
+    }
+}
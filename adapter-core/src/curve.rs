@@ -0,0 +1,263 @@
+use crate::gate::OctagonGate;
+use serde::{Deserialize, Serialize};
+
+// full deflection in xbox stick units
+const FULL: i32 = i16::MAX as i32;
+const CURVE_POINTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Curve {
+    Linear,
+    // raises the normalized deflection to `power`, 2 is quadratic
+    Exponential { power: u8 },
+    // piecewise linear through (0, 0), the (input, output) points and full deflection,
+    // points must be sorted by input and never decrease in output
+    Custom([(u16, u16); CURVE_POINTS]),
+}
+
+impl Curve {
+    // an exponential curve needs a power of at least 1 and custom points must stay
+    // inside full deflection without ever going back
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Curve::Linear => true,
+            Curve::Exponential { power } => *power > 0,
+            Curve::Custom(points) => {
+                let full = FULL as u16;
+                points.iter().all(|&(x, y)| x <= full && y <= full)
+                    && points
+                        .windows(2)
+                        .all(|pair| pair[0].0 <= pair[1].0 && pair[0].1 <= pair[1].1)
+            }
+        }
+    }
+
+    // maps 0..=FULL onto 0..=FULL
+    fn apply(&self, t: i32) -> i32 {
+        match self {
+            Curve::Linear => t,
+            Curve::Exponential { power } => {
+                let mut out = FULL;
+                for _ in 0..*power {
+                    out = out * t / FULL
+                }
+                out
+            }
+            Curve::Custom(points) => {
+                let (mut x0, mut y0) = (0, 0);
+                for &(x1, y1) in points.iter().chain(&[(FULL as u16, FULL as u16)]) {
+                    let (x1, y1) = (x1 as i32, y1 as i32);
+                    if t <= x1 {
+                        if x1 == x0 {
+                            return y1;
+                        }
+                        return y0 + (y1 - y0) * (t - x0) / (x1 - x0);
+                    }
+                    (x0, y0) = (x1, y1);
+                }
+                FULL
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StickConfig {
+    // radial deflection below which the stick reads centered
+    pub inner_deadzone: u16,
+    // radial deflection at which the stick reads fully pushed
+    pub outer_saturation: u16,
+    // smallest deflection sent once outside the deadzone, to cancel out a game's own deadzone
+    pub anti_deadzone: u16,
+    // stretches the corners of a square gate back onto a circle
+    pub square_to_circle: bool,
+    pub curve: Curve,
+    // applied last, after the curve
+    pub gate: Option<OctagonGate>,
+}
+
+impl StickConfig {
+    pub const RAW: StickConfig = StickConfig {
+        inner_deadzone: 0,
+        outer_saturation: i16::MAX as u16,
+        anti_deadzone: 0,
+        square_to_circle: false,
+        curve: Curve::Linear,
+        gate: None,
+    };
+
+    pub fn process(&self, x: i16, y: i16) -> (i16, i16) {
+        let (mut x, mut y) = (x.max(-i16::MAX) as i32, y.max(-i16::MAX) as i32);
+        if self.square_to_circle {
+            (x, y) = (
+                x * isqrt((FULL * FULL - y * y / 2) as u32) as i32 / FULL,
+                y * isqrt((FULL * FULL - x * x / 2) as u32) as i32 / FULL,
+            );
+        }
+
+        let magnitude = isqrt((x * x + y * y) as u32) as i32;
+        let inner = self.inner_deadzone as i32;
+        if magnitude <= inner {
+            return (0, 0);
+        }
+
+        let outer = (self.outer_saturation as i32).min(FULL);
+        let t = if outer > inner {
+            ((magnitude - inner) * FULL / (outer - inner)).min(FULL)
+        } else {
+            FULL
+        };
+        let anti = (self.anti_deadzone as i32).min(FULL);
+        let out = anti + self.curve.apply(t) * (FULL - anti) / FULL;
+
+        let scale = |value: i32| (value * out / magnitude).clamp(-FULL, FULL) as i16;
+        match self.gate {
+            Some(gate) => gate.process(scale(x), scale(y)),
+            None => (scale(x), scale(y)),
+        }
+    }
+}
+
+impl Default for StickConfig {
+    fn default() -> Self {
+        Self {
+            inner_deadzone: 2500,
+            outer_saturation: 32000,
+            ..Self::RAW
+        }
+    }
+}
+
+pub fn isqrt(value: u32) -> u32 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::{OctagonGate, MAX_SNAP_DEGREES};
+    use proptest::prelude::*;
+
+    fn curve() -> impl Strategy<Value = Curve> {
+        prop_oneof![
+            Just(Curve::Linear),
+            (1..=8u8).prop_map(|power| Curve::Exponential { power }),
+            (
+                proptest::array::uniform4(0..=FULL as u16),
+                proptest::array::uniform4(0..=FULL as u16)
+            )
+                .prop_map(|(mut xs, mut ys)| {
+                    xs.sort();
+                    ys.sort();
+                    Curve::Custom(core::array::from_fn(|idx| (xs[idx], ys[idx])))
+                }),
+        ]
+    }
+
+    fn config() -> impl Strategy<Value = StickConfig> {
+        (
+            0..=FULL as u16,
+            0..=u16::MAX,
+            0..=FULL as u16,
+            any::<bool>(),
+            curve(),
+            proptest::option::of(
+                (0..=MAX_SNAP_DEGREES).prop_map(|snap_degrees| OctagonGate { snap_degrees }),
+            ),
+        )
+            .prop_map(
+                |(
+                    inner_deadzone,
+                    outer_saturation,
+                    anti_deadzone,
+                    square_to_circle,
+                    curve,
+                    gate,
+                )| {
+                    StickConfig {
+                        inner_deadzone,
+                        outer_saturation,
+                        anti_deadzone,
+                        square_to_circle,
+                        curve,
+                        gate,
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn generated_curves_are_valid(curve in curve()) {
+            prop_assert!(curve.is_valid());
+        }
+
+        #[test]
+        fn valid_curves_stay_in_range_and_never_go_back(curve in curve(), t in 0..FULL) {
+            let (low, high) = (curve.apply(t), curve.apply(t + 1));
+            prop_assert!((0..=FULL).contains(&low));
+            prop_assert!(low <= high);
+            prop_assert_eq!(curve.apply(FULL), FULL);
+        }
+
+        #[test]
+        fn output_never_leaves_the_stick_range(config in config(), x in any::<i16>(), y in any::<i16>()) {
+            let (out_x, out_y) = config.process(x, y);
+            prop_assert!(out_x > i16::MIN && out_y > i16::MIN);
+        }
+
+        #[test]
+        fn deadzone_reads_centered(config in config(), x in any::<i16>(), y in any::<i16>()) {
+            let (x, y) = (x.max(-i16::MAX) as i32, y.max(-i16::MAX) as i32);
+            let config = StickConfig { square_to_circle: false, ..config };
+            prop_assume!(isqrt((x * x + y * y) as u32) <= config.inner_deadzone as u32);
+            prop_assert_eq!(config.process(x as i16, y as i16), (0, 0));
+        }
+
+        #[test]
+        fn direction_is_kept(config in config(), x in any::<i16>(), y in any::<i16>()) {
+            let config = StickConfig { gate: None, ..config };
+            let (out_x, out_y) = config.process(x, y);
+            prop_assert!(out_x == 0 || out_x.signum() == x.signum());
+            prop_assert!(out_y == 0 || out_y.signum() == y.signum());
+        }
+
+        #[test]
+        fn pushing_further_never_reads_less(config in config(), along in 0..=FULL, step in 1..1000i32) {
+            // along the x axis, where square to circle and the gate leave the magnitude be
+            let further = (along + step).min(FULL);
+            let (near, _) = config.process(along as i16, 0);
+            let (far, _) = config.process(further as i16, 0);
+            prop_assert!(near <= far, "{} read {} but {} read {}", along, near, further, far);
+        }
+    }
+
+    #[test]
+    fn zero_power_is_rejected() {
+        assert!(!Curve::Exponential { power: 0 }.is_valid());
+        assert!(Curve::Exponential { power: 1 }.is_valid());
+    }
+
+    #[test]
+    fn custom_points_must_not_go_back() {
+        let decreasing = Curve::Custom([(1000, 2000), (2000, 1000), (3000, 3000), (4000, 4000)]);
+        let unsorted = Curve::Custom([(2000, 1000), (1000, 2000), (3000, 3000), (4000, 4000)]);
+        let too_far = Curve::Custom([(1000, 1000), (2000, 2000), (3000, 3000), (40000, 40000)]);
+        let flat = Curve::Custom([(1000, 1000), (1000, 1000), (3000, 1000), (4000, 4000)]);
+        assert!(!decreasing.is_valid());
+        assert!(!unsorted.is_valid());
+        assert!(!too_far.is_valid());
+        assert!(flat.is_valid());
+    }
+}
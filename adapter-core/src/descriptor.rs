@@ -0,0 +1,287 @@
+// Report descriptors of the switch personalities. Besides the 0x30 input report they
+// declare the vendor reports of the protocol: 0x21 subcommand replies, the 0x31 report
+// carrying mcu data and 0x81 usb replies in, 0x01 and 0x10 rumble and subcommands and
+// 0x80 and 0x82 usb commands out.
+
+pub static HID_DESCRIPTOR: [u8; 214] = [
+    0x05, 0x01, // Usage Page (Generic Desktop Ctrls)
+    0x15, 0x00, // Logical Minimum (0)
+    0x09, 0x04, // Usage (Joystick)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x30, //   Report ID (48)
+    0x05, 0x01, //   Usage Page (Generic Desktop Ctrls)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x01, //   Usage Minimum (0x01)
+    0x29, 0x0A, //   Usage Maximum (0x0A)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x0A, //   Report Count (10)
+    0x55, 0x00, //   Unit Exponent (0)
+    0x65, 0x00, //   Unit (None)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x0B, //   Usage Minimum (0x0B)
+    0x29, 0x0E, //   Usage Maximum (0x0E)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x04, //   Report Count (4)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x02, //   Report Count (2)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x0B, 0x01, 0x00, 0x01, 0x00, //   Usage (0x010001)
+    0xA1, 0x00, //   Collection (Physical)
+    0x0B, 0x30, 0x00, 0x01, 0x00, //     Usage (0x010030)
+    0x0B, 0x31, 0x00, 0x01, 0x00, //     Usage (0x010031)
+    0x0B, 0x32, 0x00, 0x01, 0x00, //     Usage (0x010032)
+    0x0B, 0x35, 0x00, 0x01, 0x00, //     Usage (0x010035)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x27, 0xFF, 0xFF, 0x00, 0x00, //     Logical Maximum (65534)
+    0x75, 0x10, //     Report Size (16)
+    0x95, 0x04, //     Report Count (4)
+    0x81, 0x02, //     Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0xC0, //   End Collection
+    0x0B, 0x39, 0x00, 0x01, 0x00, //   Usage (0x010039)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x07, //   Logical Maximum (7)
+    0x35, 0x00, //   Physical Minimum (0)
+    0x46, 0x3B, 0x01, //   Physical Maximum (315)
+    0x65, 0x14, //   Unit (System: English Rotation, Length: Centimeter)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x0F, //   Usage Minimum (0x0F)
+    0x29, 0x12, //   Usage Maximum (0x12)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x04, //   Report Count (4)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x34, //   Report Count (52)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x06, 0x00, 0xFF, //   Usage Page (Vendor Defined 0xFF00)
+    0x85, 0x21, //   Report ID (33)
+    0x09, 0x01, //   Usage (0x01)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x85, 0x81, //   Report ID (-127)
+    0x09, 0x02, //   Usage (0x02)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x85, 0x31, //   Report ID (49)
+    0x09, 0x07, //   Usage (0x07)
+    0x75, 0x08, //   Report Size (8)
+    0x96, 0x69, 0x01, //   Report Count (361)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x85, 0x01, //   Report ID (1)
+    0x09, 0x03, //   Usage (0x03)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0x85, 0x10, //   Report ID (16)
+    0x09, 0x04, //   Usage (0x04)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0x85, 0x80, //   Report ID (-128)
+    0x09, 0x05, //   Usage (0x05)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0x85, 0x82, //   Report ID (-126)
+    0x09, 0x06, //   Usage (0x06)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0xC0, // End Collection
+];
+
+// Same vendor reports as the pro controller, with the 0x30 report cut down to the
+// buttons and single stick of one joy-con.
+pub static JOYCON_HID_DESCRIPTOR: [u8; 158] = [
+    0x05, 0x01, // Usage Page (Generic Desktop Ctrls)
+    0x15, 0x00, // Logical Minimum (0)
+    0x09, 0x04, // Usage (Joystick)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x30, //   Report ID (48)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x01, //   Usage Minimum (0x01)
+    0x29, 0x10, //   Usage Maximum (0x10)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x10, //   Report Count (16)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x0B, 0x39, 0x00, 0x01, 0x00, //   Usage (0x010039)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x07, //   Logical Maximum (7)
+    0x35, 0x00, //   Physical Minimum (0)
+    0x46, 0x3B, 0x01, //   Physical Maximum (315)
+    0x65, 0x14, //   Unit (System: English Rotation, Length: Centimeter)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x0B, 0x30, 0x00, 0x01, 0x00, //   Usage (0x010030)
+    0x0B, 0x31, 0x00, 0x01, 0x00, //   Usage (0x010031)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x27, 0xFF, 0xFF, 0x00, 0x00, //   Logical Maximum (65534)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x02, //   Report Count (2)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x38, //   Report Count (56)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x06, 0x00, 0xFF, //   Usage Page (Vendor Defined 0xFF00)
+    0x85, 0x21, //   Report ID (33)
+    0x09, 0x01, //   Usage (0x01)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x85, 0x81, //   Report ID (-127)
+    0x09, 0x02, //   Usage (0x02)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x85, 0x31, //   Report ID (49)
+    0x09, 0x07, //   Usage (0x07)
+    0x75, 0x08, //   Report Size (8)
+    0x96, 0x69, 0x01, //   Report Count (361)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x85, 0x01, //   Report ID (1)
+    0x09, 0x03, //   Usage (0x03)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0x85, 0x10, //   Report ID (16)
+    0x09, 0x04, //   Usage (0x04)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0x85, 0x80, //   Report ID (-128)
+    0x09, 0x05, //   Usage (0x05)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0x85, 0x82, //   Report ID (-126)
+    0x09, 0x06, //   Usage (0x06)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
+    0xC0, // End Collection
+];
+
+// The pc tool's interface, a vendor collection with only the host request and reply.
+// It is its own interface so every personality can carry it next to the pad.
+pub static HOST_HID_DESCRIPTOR: [u8; 33] = [
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01, // Usage (0x01)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0xA0, //   Report ID (-96)
+    0x09, 0x01, //   Usage (0x01)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x91,
+    0x02, //   Output (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Non-volatile)
+    0x85, 0xA1, //   Report ID (-95)
+    0x09, 0x02, //   Usage (0x02)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3F, //   Report Count (63)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0xC0, // End Collection
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::{HOST_PACKET_SIZE, HOST_REPLY, HOST_REQUEST};
+    use crate::report::{MCU_INPUT_LEN, REPORT_LEN};
+    use std::collections::BTreeMap;
+
+    // the length of every report the descriptor declares, id included, keyed by id and
+    // whether it is an input
+    fn reports(descriptor: &[u8]) -> BTreeMap<(u8, bool), usize> {
+        let mut bits = BTreeMap::new();
+        let (mut id, mut size, mut count, mut depth) = (0, 0, 0, 0);
+        let mut items = descriptor;
+        while let [prefix, rest @ ..] = items {
+            let len = match prefix & 0x03 {
+                3 => 4,
+                len => len as usize,
+            };
+            let data = rest[..len]
+                .iter()
+                .rev()
+                .fold(0, |data, &byte| data << 8 | byte as usize);
+            match prefix & 0xfc {
+                0x84 => id = data as u8,
+                0x74 => size = data,
+                0x94 => count = data,
+                0x80 => *bits.entry((id, true)).or_insert(0) += size * count,
+                0x90 => *bits.entry((id, false)).or_insert(0) += size * count,
+                0xa0 => depth += 1,
+                0xc0 => depth -= 1,
+                _ => {}
+            }
+            items = &rest[len..];
+        }
+        assert_eq!(depth, 0);
+        bits.into_iter()
+            .map(|(report, bits)| {
+                assert_eq!(bits % 8, 0);
+                (report, 1 + bits / 8)
+            })
+            .collect()
+    }
+
+    fn check(descriptor: &[u8]) {
+        let expected = BTreeMap::from([
+            ((0x01, false), REPORT_LEN),
+            ((0x10, false), REPORT_LEN),
+            ((0x80, false), REPORT_LEN),
+            ((0x82, false), REPORT_LEN),
+            ((0x21, true), REPORT_LEN),
+            ((0x30, true), REPORT_LEN),
+            ((0x31, true), MCU_INPUT_LEN),
+            ((0x81, true), REPORT_LEN),
+        ]);
+        assert_eq!(reports(descriptor), expected);
+    }
+
+    #[test]
+    fn pro_controller_declares_every_report_at_its_length() {
+        check(&HID_DESCRIPTOR)
+    }
+
+    #[test]
+    fn joycon_declares_every_report_at_its_length() {
+        check(&JOYCON_HID_DESCRIPTOR)
+    }
+
+    #[test]
+    fn host_interface_declares_the_request_and_reply() {
+        let expected = BTreeMap::from([
+            ((HOST_REQUEST, false), HOST_PACKET_SIZE),
+            ((HOST_REPLY, true), HOST_PACKET_SIZE),
+        ]);
+        assert_eq!(reports(&HOST_HID_DESCRIPTOR), expected);
+    }
+}
@@ -0,0 +1,311 @@
+use embassy_time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+// how long a configured host may take to show what it is. Switches with "Pro Controller
+// Wired Communication" turned off never start the handshake, pcs never poll an xinput
+// pad they have no driver for.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Windows asks for this string to find Microsoft OS descriptors. It only does so the
+// first time it sees a vendor and product id, so later attachments are told by how
+// they read the hid report descriptor.
+pub const MS_OS_STRING_INDEX: u8 = 0xee;
+pub const DESCRIPTOR_HID_REPORT: u8 = 0x22;
+// the first 0x80 command tells a switch, which asks for the status first, from the linux
+// hid-nintendo driver, which starts right at the handshake
+const SWITCH_FIRST_COMMAND: u8 = 0x01;
+const LINUX_FIRST_COMMAND: u8 = 0x02;
+
+// what the adapter enumerates as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UsbPersonality {
+    // the profile's nintendo controller, which needs the 0x80 handshake
+    #[default]
+    Switch = 0,
+    // a generic HORI-style wired pad, which works without any handshake
+    SimpleHid = 1,
+    // a wired xbox 360 controller for pcs
+    XInput = 2,
+}
+
+impl UsbPersonality {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(UsbPersonality::Switch),
+            1 => Some(UsbPersonality::SimpleHid),
+            2 => Some(UsbPersonality::XInput),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Host {
+    Switch = 0,
+    Windows = 1,
+    Linux = 2,
+}
+
+impl Host {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Host::Switch),
+            1 => Some(Host::Windows),
+            2 => Some(Host::Linux),
+            _ => None,
+        }
+    }
+
+    // hid-nintendo drives the pro controller fully, windows only has xinput built in
+    pub fn personality(self) -> UsbPersonality {
+        match self {
+            Host::Switch | Host::Linux => UsbPersonality::Switch,
+            Host::Windows => UsbPersonality::XInput,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostEvent {
+    // the host asked for a string descriptor the device doesn't have
+    UnknownString(u8),
+    // a hid report descriptor request, with the length the host asked for and the length
+    // of the descriptor. Windows asks for 64 bytes more, linux for exactly its length.
+    ReportDescriptor { requested: u16, length: u16 },
+    Configured,
+    // the command byte of a 0x80 packet
+    Handshake(u8),
+    // the host read an xinput report
+    Polled,
+    // nothing telling happened within HANDSHAKE_TIMEOUT of being configured
+    Timeout,
+}
+
+// Tells hosts apart by what they do while and right after enumerating. Fed the events of
+// one attachment in order, with the time they happened, it says when the adapter should
+// reattach as something else.
+#[derive(Debug)]
+pub struct HostDetector {
+    personality: UsbPersonality,
+    // false when the profile pins the personality, hosts are then only remembered
+    auto: bool,
+    host: Option<Host>,
+    deadline: Option<Instant>,
+    settled: bool,
+}
+
+impl HostDetector {
+    pub fn new(personality: UsbPersonality, auto: bool) -> Self {
+        Self {
+            personality,
+            auto,
+            host: None,
+            deadline: None,
+            settled: false,
+        }
+    }
+
+    pub fn host(&self) -> Option<Host> {
+        self.host
+    }
+
+    // when a Timeout is due, if one is
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    // Some(personality) asks for a reattach
+    pub fn observe(&mut self, event: HostEvent, now: Instant) -> Option<UsbPersonality> {
+        match event {
+            HostEvent::UnknownString(MS_OS_STRING_INDEX) => self.detect(Host::Windows),
+            HostEvent::UnknownString(_) => None,
+            // only counts while nothing else told the host yet
+            HostEvent::ReportDescriptor { requested, length }
+                if !self.settled && requested > length =>
+            {
+                self.detect(Host::Windows)
+            }
+            HostEvent::ReportDescriptor { .. } => None,
+            HostEvent::Configured => {
+                if !self.settled && self.deadline.is_none() {
+                    self.deadline = Some(now + HANDSHAKE_TIMEOUT);
+                }
+                None
+            }
+            HostEvent::Handshake(command) if !self.settled => match command {
+                SWITCH_FIRST_COMMAND => self.detect(Host::Switch),
+                LINUX_FIRST_COMMAND => self.detect(Host::Linux),
+                _ => self.settle(None),
+            },
+            HostEvent::Handshake(_) => None,
+            HostEvent::Polled => self.settle(None),
+            HostEvent::Timeout => {
+                self.deadline = None;
+                if self.settled {
+                    return None;
+                }
+                self.settled = true;
+                match self.personality {
+                    // keeps working on switches that skip the handshake
+                    UsbPersonality::Switch => Some(UsbPersonality::SimpleHid),
+                    UsbPersonality::XInput if self.auto => Some(UsbPersonality::Switch),
+                    UsbPersonality::XInput | UsbPersonality::SimpleHid => None,
+                }
+            }
+        }
+    }
+
+    fn detect(&mut self, host: Host) -> Option<UsbPersonality> {
+        if self.host.is_some() {
+            return None;
+        }
+        info!("host looks like {}", host);
+        self.host = Some(host);
+        let wanted = host.personality();
+        let reattach = self.auto && wanted != self.personality;
+        self.settle(reattach.then_some(wanted))
+    }
+
+    fn settle(&mut self, reattach: Option<UsbPersonality>) -> Option<UsbPersonality> {
+        self.settled = true;
+        self.deadline = None;
+        reattach
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT_DESCRIPTOR_LEN: u16 = 203;
+    // what Windows asks for on top of the descriptor's length
+    const WINDOWS_REPORT_SLACK: u16 = 0x40;
+
+    fn report_descriptor(requested: u16) -> HostEvent {
+        HostEvent::ReportDescriptor {
+            requested,
+            length: REPORT_DESCRIPTOR_LEN,
+        }
+    }
+
+    // what linux and the switch do before the handshake
+    fn plain() -> [HostEvent; 3] {
+        [
+            HostEvent::UnknownString(0x03),
+            HostEvent::Configured,
+            report_descriptor(REPORT_DESCRIPTOR_LEN),
+        ]
+    }
+
+    // the first time Windows sees the adapter
+    fn windows_first() -> [HostEvent; 3] {
+        [
+            HostEvent::UnknownString(MS_OS_STRING_INDEX),
+            HostEvent::Configured,
+            report_descriptor(REPORT_DESCRIPTOR_LEN + WINDOWS_REPORT_SLACK),
+        ]
+    }
+
+    // every later time, with the 0xee answer already cached
+    fn windows_again() -> [HostEvent; 2] {
+        [
+            HostEvent::Configured,
+            report_descriptor(REPORT_DESCRIPTOR_LEN + WINDOWS_REPORT_SLACK),
+        ]
+    }
+
+    // feeds a trace one millisecond apart, returning every reattach asked for
+    fn replay(detector: &mut HostDetector, trace: &[HostEvent]) -> Vec<UsbPersonality> {
+        trace
+            .iter()
+            .enumerate()
+            .filter_map(|(ms, event)| detector.observe(*event, Instant::from_millis(ms as u64)))
+            .collect()
+    }
+
+    #[test]
+    fn windows_gets_xinput() {
+        for trace in [&windows_first()[..], &windows_again()[..]] {
+            let mut detector = HostDetector::new(UsbPersonality::Switch, true);
+            assert_eq!(replay(&mut detector, trace), [UsbPersonality::XInput]);
+            assert_eq!(detector.host(), Some(Host::Windows));
+        }
+    }
+
+    #[test]
+    fn windows_is_only_remembered_when_pinned() {
+        let mut detector = HostDetector::new(UsbPersonality::Switch, false);
+        let mut trace = windows_first().to_vec();
+        trace.push(HostEvent::Timeout);
+        assert_eq!(replay(&mut detector, &trace), []);
+        assert_eq!(detector.host(), Some(Host::Windows));
+    }
+
+    #[test]
+    fn windows_keeps_xinput() {
+        let mut detector = HostDetector::new(UsbPersonality::XInput, true);
+        let mut trace = windows_first().to_vec();
+        trace.extend([HostEvent::Polled, HostEvent::Timeout]);
+        assert_eq!(replay(&mut detector, &trace), []);
+        assert_eq!(detector.host(), Some(Host::Windows));
+        assert_eq!(detector.deadline(), None);
+    }
+
+    #[test]
+    fn switch_and_linux_keep_the_switch_personality() {
+        for (command, host) in [
+            (SWITCH_FIRST_COMMAND, Host::Switch),
+            (LINUX_FIRST_COMMAND, Host::Linux),
+        ] {
+            let mut detector = HostDetector::new(UsbPersonality::Switch, true);
+            let mut trace = plain().to_vec();
+            trace.push(HostEvent::Handshake(command));
+            assert_eq!(replay(&mut detector, &trace), []);
+            assert_eq!(detector.host(), Some(host));
+            assert_eq!(detector.deadline(), None);
+        }
+    }
+
+    #[test]
+    fn linux_gets_the_switch_personality_back() {
+        let mut detector = HostDetector::new(UsbPersonality::XInput, true);
+        let mut trace = plain().to_vec();
+        trace.push(HostEvent::Timeout);
+        assert_eq!(replay(&mut detector, &trace), [UsbPersonality::Switch]);
+        assert_eq!(detector.host(), None);
+    }
+
+    #[test]
+    fn a_silent_switch_gets_the_simple_pad() {
+        let mut detector = HostDetector::new(UsbPersonality::Switch, true);
+        assert_eq!(replay(&mut detector, &plain()), []);
+        assert_eq!(
+            detector.deadline(),
+            Some(Instant::from_millis(1) + HANDSHAKE_TIMEOUT)
+        );
+        assert_eq!(
+            replay(&mut detector, &[HostEvent::Timeout]),
+            [UsbPersonality::SimpleHid]
+        );
+        // a late handshake changes nothing
+        assert_eq!(
+            replay(&mut detector, &[HostEvent::Handshake(SWITCH_FIRST_COMMAND)]),
+            []
+        );
+    }
+
+    #[test]
+    fn a_long_report_descriptor_request_after_the_handshake_changes_nothing() {
+        let mut detector = HostDetector::new(UsbPersonality::Switch, true);
+        let mut trace = plain().to_vec();
+        trace.extend([
+            HostEvent::Handshake(LINUX_FIRST_COMMAND),
+            report_descriptor(REPORT_DESCRIPTOR_LEN + WINDOWS_REPORT_SLACK),
+        ]);
+        assert_eq!(replay(&mut detector, &trace), []);
+        assert_eq!(detector.host(), Some(Host::Linux));
+    }
+}
@@ -0,0 +1,339 @@
+use crate::gamepad::{Button, Buttons, GamepadState, StickPosition};
+use crate::stick::AXIS_CALIBRATION;
+use serde::{Deserialize, Serialize};
+
+// cos(45°) in 1/1000ths, keeps d-pad diagonals on the stick's circle
+const DIAGONAL: u32 = 707;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DpadMode {
+    Normal,
+    // the left stick presses the d-pad and reads centered
+    StickToDpad,
+    // the d-pad fully pushes the left stick and reads released
+    DpadToStick,
+}
+
+// what to do when both directions of an axis are held at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Socd {
+    Off,
+    Neutral,
+    LastInputWins,
+    // up beats down, left and right cancel out
+    UpPriority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DpadConfig {
+    pub mode: DpadMode,
+    pub socd: Socd,
+}
+
+impl Default for DpadConfig {
+    fn default() -> Self {
+        Self {
+            mode: DpadMode::Normal,
+            socd: Socd::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dpad {
+    pub config: DpadConfig,
+    held: Buttons,
+    // most recently pressed direction of each axis, for last input wins
+    last_vertical: Button,
+    last_horizontal: Button,
+}
+
+impl Dpad {
+    pub fn new(config: DpadConfig) -> Self {
+        Self {
+            config,
+            held: Buttons::NONE,
+            last_vertical: Button::Up,
+            last_horizontal: Button::Left,
+        }
+    }
+
+    pub fn process(&mut self, mut state: GamepadState) -> GamepadState {
+        if self.config.mode == DpadMode::StickToDpad {
+            state.buttons = state.buttons | stick_to_dpad(state.left_stick);
+            state.left_stick = StickPosition::CENTER;
+        }
+
+        state.buttons = self.clean(state.buttons);
+
+        if self.config.mode == DpadMode::DpadToStick {
+            if let Some(position) = dpad_to_stick(state.buttons) {
+                state.left_stick = position
+            }
+            for button in [Button::Up, Button::Down, Button::Left, Button::Right] {
+                state.buttons.release(button)
+            }
+        }
+        state
+    }
+
+    fn clean(&mut self, mut buttons: Buttons) -> Buttons {
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+
+        for (a, b, last) in [
+            (Button::Up, Button::Down, &mut self.last_vertical),
+            (Button::Left, Button::Right, &mut self.last_horizontal),
+        ] {
+            if pressed.is_pressed(a) {
+                *last = a
+            } else if pressed.is_pressed(b) {
+                *last = b
+            }
+            if !(buttons.is_pressed(a) && buttons.is_pressed(b)) {
+                continue;
+            }
+
+            let keep = match self.config.socd {
+                Socd::Off => continue,
+                Socd::Neutral => None,
+                Socd::LastInputWins => Some(*last),
+                Socd::UpPriority if a == Button::Up => Some(Button::Up),
+                Socd::UpPriority => None,
+            };
+            for button in [a, b] {
+                if Some(button) != keep {
+                    buttons.release(button)
+                }
+            }
+        }
+        buttons
+    }
+}
+
+impl Default for Dpad {
+    fn default() -> Self {
+        Self::new(DpadConfig::default())
+    }
+}
+
+// presses the d-pad directions a stick is pushed at least halfway towards
+pub fn stick_to_dpad(stick: StickPosition) -> Buttons {
+    let threshold = AXIS_CALIBRATION.above / 2;
+    let center = AXIS_CALIBRATION.center;
+    let mut buttons = Buttons::NONE;
+    buttons.set(Button::Right, stick.x > center + threshold);
+    buttons.set(Button::Left, stick.x < center - threshold);
+    buttons.set(Button::Up, stick.y > center + threshold);
+    buttons.set(Button::Down, stick.y < center - threshold);
+    buttons
+}
+
+fn dpad_to_stick(buttons: Buttons) -> Option<StickPosition> {
+    let axis = |positive: Button, negative: Button| match (
+        buttons.is_pressed(positive),
+        buttons.is_pressed(negative),
+    ) {
+        (true, false) => 1,
+        (false, true) => -1,
+        _ => 0,
+    };
+    let (x, y): (i32, i32) = (
+        axis(Button::Right, Button::Left),
+        axis(Button::Up, Button::Down),
+    );
+    if x == 0 && y == 0 {
+        return None;
+    }
+
+    let cal = AXIS_CALIBRATION;
+    let scale = if x != 0 && y != 0 { DIAGONAL } else { 1000 };
+    let offset = |direction: i32| {
+        let range = (if direction > 0 { cal.above } else { cal.below }) as u32;
+        cal.center as i32 + direction * (range * scale / 1000) as i32
+    };
+    Some(StickPosition::new(offset(x) as u16, offset(y) as u16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [Socd; 4] = [
+        Socd::Off,
+        Socd::Neutral,
+        Socd::LastInputWins,
+        Socd::UpPriority,
+    ];
+
+    // One axis as two bits, the first direction (up or left) in bit 0 and the second
+    // (down or right) in bit 1. Indexed by [mode][held before][held now], for a fresh
+    // d-pad that saw nothing before.
+    const VERTICAL: [[[u8; 4]; 4]; 4] = [
+        // off
+        [[0, 1, 2, 3], [0, 1, 2, 3], [0, 1, 2, 3], [0, 1, 2, 3]],
+        // neutral
+        [[0, 1, 2, 0], [0, 1, 2, 0], [0, 1, 2, 0], [0, 1, 2, 0]],
+        // last input wins, both at once counts as the first direction
+        [[0, 1, 2, 1], [0, 1, 2, 2], [0, 1, 2, 1], [0, 1, 2, 1]],
+        // up priority
+        [[0, 1, 2, 1], [0, 1, 2, 1], [0, 1, 2, 1], [0, 1, 2, 1]],
+    ];
+    const HORIZONTAL: [[[u8; 4]; 4]; 4] = [
+        [[0, 1, 2, 3], [0, 1, 2, 3], [0, 1, 2, 3], [0, 1, 2, 3]],
+        [[0, 1, 2, 0], [0, 1, 2, 0], [0, 1, 2, 0], [0, 1, 2, 0]],
+        [[0, 1, 2, 1], [0, 1, 2, 2], [0, 1, 2, 1], [0, 1, 2, 1]],
+        // left and right cancel out under up priority
+        [[0, 1, 2, 0], [0, 1, 2, 0], [0, 1, 2, 0], [0, 1, 2, 0]],
+    ];
+
+    // the d-pad as four bits: up, down, left, right
+    fn dpad(bits: u8) -> Buttons {
+        let mut buttons = Buttons::NONE;
+        buttons.set(Button::Up, bits & 1 != 0);
+        buttons.set(Button::Down, bits & 2 != 0);
+        buttons.set(Button::Left, bits & 4 != 0);
+        buttons.set(Button::Right, bits & 8 != 0);
+        buttons
+    }
+
+    fn state(buttons: Buttons) -> GamepadState {
+        GamepadState {
+            buttons,
+            ..GamepadState::NEUTRAL
+        }
+    }
+
+    #[test]
+    fn socd_matches_the_truth_table() {
+        for (mode, socd) in MODES.into_iter().enumerate() {
+            for before in 0..16 {
+                for now in 0..16 {
+                    let mut pad = Dpad::new(DpadConfig {
+                        mode: DpadMode::Normal,
+                        socd,
+                    });
+                    // an unrelated button rides along and must come out untouched
+                    let extra: Buttons = Button::A.into();
+                    pad.process(state(dpad(before) | extra));
+                    let output = pad.process(state(dpad(now) | extra));
+
+                    let vertical = VERTICAL[mode][before as usize & 3][now as usize & 3];
+                    let horizontal = HORIZONTAL[mode][before as usize >> 2][now as usize >> 2];
+                    assert_eq!(
+                        output,
+                        state(dpad(vertical | horizontal << 2) | extra),
+                        "{:?}: {:04b} then {:04b}",
+                        socd,
+                        before,
+                        now
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn last_input_wins_follows_the_newest_press() {
+        let mut pad = Dpad::new(DpadConfig {
+            mode: DpadMode::Normal,
+            socd: Socd::LastInputWins,
+        });
+        let up: Buttons = Button::Up.into();
+        let down: Buttons = Button::Down.into();
+        assert_eq!(pad.process(state(down)).buttons, down);
+        assert_eq!(pad.process(state(down | up)).buttons, up);
+        // releasing the winner hands the axis back to the one still held
+        assert_eq!(pad.process(state(down)).buttons, down);
+        assert_eq!(pad.process(state(down | up)).buttons, up);
+        assert_eq!(pad.process(state(up)).buttons, up);
+        assert_eq!(pad.process(state(down | up)).buttons, down);
+    }
+
+    #[test]
+    fn dpad_to_stick_matches_the_truth_table() {
+        let cal = AXIS_CALIBRATION;
+        let full = |sign: i32, scale: u32| {
+            let range = if sign > 0 { cal.above } else { cal.below } as u32;
+            (cal.center as i32 + sign * (range * scale / 1000) as i32) as u16
+        };
+        // what the untouched left stick reads, kept when the d-pad rests
+        let resting = StickPosition::new(0x900, 0x700);
+        for bits in 0..16u8 {
+            let mut pad = Dpad::new(DpadConfig {
+                mode: DpadMode::DpadToStick,
+                socd: Socd::Off,
+            });
+            let output = pad.process(GamepadState {
+                buttons: dpad(bits),
+                left_stick: resting,
+                ..GamepadState::NEUTRAL
+            });
+            let axis = |positive: u8, negative: u8| match (bits & positive, bits & negative) {
+                (0, 0) => 0,
+                (_, 0) => 1,
+                (0, _) => -1,
+                _ => 0,
+            };
+            let (x, y) = (axis(8, 4), axis(1, 2));
+            let expected = match (x, y) {
+                (0, 0) => resting,
+                (x, 0) => StickPosition::new(full(x, 1000), cal.center),
+                (0, y) => StickPosition::new(cal.center, full(y, 1000)),
+                (x, y) => StickPosition::new(full(x, DIAGONAL), full(y, DIAGONAL)),
+            };
+            assert_eq!(output.left_stick, expected, "{:04b}", bits);
+            assert_eq!(output.buttons, Buttons::NONE, "{:04b}", bits);
+        }
+    }
+
+    #[test]
+    fn stick_to_dpad_matches_the_truth_table() {
+        let cal = AXIS_CALIBRATION;
+        let threshold = cal.above / 2;
+        // far below, just inside the threshold on either side, the center, far above, with
+        // the direction each reads as: 1 towards up or right, 2 towards down or left
+        let positions = [
+            (0, 2),
+            (cal.center - threshold - 1, 2),
+            (cal.center - threshold, 0),
+            (cal.center, 0),
+            (cal.center + threshold, 0),
+            (cal.center + threshold + 1, 1),
+            (StickPosition::MAX, 1),
+        ];
+        for (x, right_left) in positions {
+            for (y, up_down) in positions {
+                let mut pad = Dpad::new(DpadConfig {
+                    mode: DpadMode::StickToDpad,
+                    socd: Socd::Off,
+                });
+                let output = pad.process(GamepadState {
+                    left_stick: StickPosition::new(x, y),
+                    ..GamepadState::NEUTRAL
+                });
+                let horizontal = match right_left {
+                    1 => 8,
+                    2 => 4,
+                    _ => 0,
+                };
+                assert_eq!(output.buttons, dpad(up_down | horizontal), "{x:x} {y:x}");
+                assert_eq!(output.left_stick, StickPosition::CENTER);
+            }
+        }
+    }
+
+    #[test]
+    fn normal_mode_leaves_the_stick_alone() {
+        let mut pad = Dpad::default();
+        let state = GamepadState {
+            buttons: dpad(0b1111),
+            left_stick: StickPosition::new(0, StickPosition::MAX),
+            ..GamepadState::NEUTRAL
+        };
+        assert_eq!(pad.process(state), state);
+    }
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+
+// Switch buttons, numbered by their bit in the 3 byte `ButtonsStatus` (right, middle, left)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Button {
+    Y = 0,
+    X = 1,
+    B = 2,
+    A = 3,
+    RightSR = 4,
+    RightSL = 5,
+    R = 6,
+    ZR = 7,
+    Minus = 8,
+    Plus = 9,
+    RStick = 10,
+    LStick = 11,
+    Home = 12,
+    Capture = 13,
+    ChargingGrip = 15,
+    Down = 16,
+    Up = 17,
+    Right = 18,
+    Left = 19,
+    LeftSR = 20,
+    LeftSL = 21,
+    L = 22,
+    ZL = 23,
+}
+
+impl Button {
+    pub const ALL: [Button; 23] = [
+        Button::Y,
+        Button::X,
+        Button::B,
+        Button::A,
+        Button::RightSR,
+        Button::RightSL,
+        Button::R,
+        Button::ZR,
+        Button::Minus,
+        Button::Plus,
+        Button::RStick,
+        Button::LStick,
+        Button::Home,
+        Button::Capture,
+        Button::ChargingGrip,
+        Button::Down,
+        Button::Up,
+        Button::Right,
+        Button::Left,
+        Button::LeftSR,
+        Button::LeftSL,
+        Button::L,
+        Button::ZL,
+    ];
+
+    fn mask(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Buttons(u32);
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0);
+
+    pub fn is_pressed(self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.0 |= button.mask()
+        } else {
+            self.0 &= !button.mask()
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.set(button, true)
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.set(button, false)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Button> {
+        Button::ALL
+            .into_iter()
+            .filter(move |button| self.is_pressed(*button))
+    }
+
+    pub fn bytes(self) -> [u8; 3] {
+        let [right, middle, left, _] = self.0.to_le_bytes();
+        [right, middle, left]
+    }
+}
+
+impl core::ops::BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitAnd for Buttons {
+    type Output = Buttons;
+
+    fn bitand(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 & rhs.0)
+    }
+}
+
+impl core::ops::BitXor for Buttons {
+    type Output = Buttons;
+
+    fn bitxor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 ^ rhs.0)
+    }
+}
+
+impl core::ops::Not for Buttons {
+    type Output = Buttons;
+
+    fn not(self) -> Buttons {
+        Buttons(!self.0 & 0x00FF_FFFF)
+    }
+}
+
+impl From<Button> for Buttons {
+    fn from(button: Button) -> Self {
+        Buttons(button.mask())
+    }
+}
+
+// 12 bit stick axes as they are sent to the switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StickPosition {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl StickPosition {
+    pub const MAX: u16 = 0xFFF;
+    pub const CENTER: StickPosition = StickPosition { x: 0x800, y: 0x800 };
+
+    pub fn new(x: u16, y: u16) -> Self {
+        Self {
+            x: x.min(Self::MAX),
+            y: y.min(Self::MAX),
+        }
+    }
+
+    pub fn bytes(self) -> [u8; 3] {
+        pack_12bit(self.x, self.y)
+    }
+}
+
+// packs two 12 bit values the way sticks and stick calibration are laid out on the wire
+pub fn pack_12bit(x: u16, y: u16) -> [u8; 3] {
+    [
+        x as u8,
+        ((x >> 8) as u8 & 0x0F) | ((y as u8 & 0x0F) << 4),
+        (y >> 4) as u8,
+    ]
+}
+
+impl Default for StickPosition {
+    fn default() -> Self {
+        Self::CENTER
+    }
+}
+
+// an immutable copy of everything the switch can see from the controller
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GamepadState {
+    pub buttons: Buttons,
+    pub left_stick: StickPosition,
+    pub right_stick: StickPosition,
+}
+
+impl GamepadState {
+    pub const NEUTRAL: GamepadState = GamepadState {
+        buttons: Buttons::NONE,
+        left_stick: StickPosition::CENTER,
+        right_stick: StickPosition::CENTER,
+    };
+}
@@ -0,0 +1,218 @@
+use crate::curve::isqrt;
+use serde::{Deserialize, Serialize};
+
+// full deflection in xbox stick units
+const FULL: i32 = i16::MAX as i32;
+// cos(22.5°) in 1/1000ths, the distance from the center to an edge of the octagon
+const EDGE: i32 = 924;
+pub const MAX_SNAP_DEGREES: u8 = 22;
+
+// sin of 0..=22 degrees in 1/1000ths, a cos table this coarse would blur small windows
+const SIN: [i32; MAX_SNAP_DEGREES as usize + 1] = [
+    0, 17, 35, 52, 70, 87, 105, 122, 139, 156, 174, 191, 208, 225, 242, 259, 276, 292, 309, 326,
+    342, 358, 375,
+];
+
+// the notches of a nintendo gate, right then counterclockwise, in 1/1000ths
+const DIRECTIONS: [(i32, i32); 8] = [
+    (1000, 0),
+    (707, 707),
+    (0, 1000),
+    (-707, 707),
+    (-1000, 0),
+    (-707, -707),
+    (0, -1000),
+    (707, -707),
+];
+
+// outward normals of the edges between the notches, in 1/1000ths
+const EDGE_NORMALS: [(i32, i32); 8] = [
+    (924, 383),
+    (383, 924),
+    (-383, 924),
+    (-924, 383),
+    (-924, -383),
+    (-383, -924),
+    (383, -924),
+    (924, -383),
+];
+
+// emulates the notched octagonal gate of a nintendo stick on a round xbox stick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OctagonGate {
+    // vectors this close to a notch are pulled onto it, 0 disables snapping
+    pub snap_degrees: u8,
+}
+
+impl OctagonGate {
+    pub fn process(&self, x: i16, y: i16) -> (i16, i16) {
+        let (mut x, mut y) = (x.max(-i16::MAX) as i32, y.max(-i16::MAX) as i32);
+        let magnitude = isqrt((x * x + y * y) as u32) as i32;
+        if magnitude == 0 {
+            return (0, 0);
+        }
+
+        if self.snap_degrees > 0 {
+            let sin = SIN[self.snap_degrees.min(MAX_SNAP_DEGREES) as usize];
+            let (dx, dy) = DIRECTIONS
+                .into_iter()
+                .max_by_key(|(dx, dy)| x * dx + y * dy)
+                .unwrap();
+            // the nearest notch is at most 22.5° off, so the cross product alone tells
+            // whether the vector is inside the window
+            if (x * dy - y * dx).abs() <= magnitude * sin {
+                (x, y) = (dx * magnitude / 1000, dy * magnitude / 1000);
+            }
+        }
+
+        // the furthest the vector reaches past any edge decides how far it gets pulled in
+        let limit = FULL * EDGE / 1000;
+        let reach = EDGE_NORMALS
+            .into_iter()
+            .map(|(nx, ny)| (x * nx + y * ny) / 1000)
+            .max()
+            .unwrap();
+        if reach > limit {
+            (x, y) = (x * limit / reach, y * limit / reach);
+        }
+        (x as i16, y as i16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn degrees(x: i32, y: i32) -> f64 {
+        (y as f64).atan2(x as f64).to_degrees()
+    }
+
+    // how far apart two angles are, in degrees
+    fn between(a: f64, b: f64) -> f64 {
+        let d = (a - b).rem_euclid(360.0);
+        d.min(360.0 - d)
+    }
+
+    fn magnitude(x: i32, y: i32) -> f64 {
+        ((x * x + y * y) as f64).sqrt()
+    }
+
+    // a stick vector `magnitude` long at `angle` degrees
+    fn polar(
+        magnitude: core::ops::Range<f64>,
+        angle: impl Strategy<Value = f64>,
+    ) -> impl Strategy<Value = (i16, i16)> {
+        (magnitude, angle).prop_map(|(magnitude, angle): (f64, f64)| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let axis = |v: f64| v.round().clamp(-FULL as f64, FULL as f64) as i16;
+            (axis(magnitude * cos), axis(magnitude * sin))
+        })
+    }
+
+    // an angle `offset` degrees to either side of one of the notches
+    fn off_notch(offset: core::ops::Range<f64>) -> impl Strategy<Value = f64> {
+        (0..8, offset, any::<bool>()).prop_map(|(notch, offset, clockwise)| {
+            notch as f64 * 45.0 + if clockwise { -offset } else { offset }
+        })
+    }
+
+    fn gate() -> impl Strategy<Value = OctagonGate> {
+        (0..=MAX_SNAP_DEGREES + 5).prop_map(|snap_degrees| OctagonGate { snap_degrees })
+    }
+
+    proptest! {
+        #[test]
+        fn output_stays_inside_the_octagon(gate in gate(), x: i16, y: i16) {
+            let (ox, oy) = gate.process(x, y);
+            let limit = FULL * EDGE / 1000;
+            for (nx, ny) in EDGE_NORMALS {
+                prop_assert!((ox as i32 * nx + oy as i32 * ny) / 1000 <= limit + 1);
+            }
+        }
+
+        #[test]
+        fn magnitude_never_grows(gate in gate(), x: i16, y: i16) {
+            let (ox, oy) = gate.process(x, y);
+            let (x, y) = (x.max(-i16::MAX) as i32, y.max(-i16::MAX) as i32);
+            prop_assert!(magnitude(ox as i32, oy as i32) <= magnitude(x, y) + 1.0);
+        }
+
+        #[test]
+        fn vectors_inside_the_octagon_pass_unchanged(
+            (x, y) in polar(0.0..(FULL * EDGE / 1000 - 2) as f64, 0.0..360.0),
+        ) {
+            prop_assert_eq!(OctagonGate { snap_degrees: 0 }.process(x, y), (x, y));
+        }
+
+        #[test]
+        fn unsnapped_vectors_keep_their_angle(
+            (snap_degrees, (x, y)) in (0..MAX_SNAP_DEGREES).prop_flat_map(|snap| {
+                // at least half a degree past the snap window of the nearest notch
+                let from = if snap == 0 { 0.0 } else { snap as f64 + 0.5 };
+                (Just(snap), polar(2_000.0..FULL as f64, off_notch(from..22.5)))
+            }),
+        ) {
+            let (ox, oy) = OctagonGate { snap_degrees }.process(x, y);
+            let angle = degrees(x as i32, y as i32);
+            prop_assert!(between(degrees(ox as i32, oy as i32), angle) < 0.5);
+        }
+
+        #[test]
+        fn vectors_near_a_notch_snap_onto_it(
+            (snap_degrees, (x, y)) in (1..=MAX_SNAP_DEGREES).prop_flat_map(|snap| {
+                (Just(snap), polar(2_000.0..FULL as f64, off_notch(0.0..snap as f64 - 0.5)))
+            }),
+        ) {
+            let (ox, oy) = OctagonGate { snap_degrees }.process(x, y);
+            let angle = degrees(x as i32, y as i32);
+            let notch = (angle / 45.0).round() * 45.0;
+            prop_assert!(between(degrees(ox as i32, oy as i32), notch) < 0.5);
+        }
+
+        #[test]
+        fn mirrored_input_gives_mirrored_output(x: i16, y: i16) {
+            let (x, y) = (x.max(-i16::MAX), y.max(-i16::MAX));
+            let gate = OctagonGate { snap_degrees: 0 };
+            let (ox, oy) = gate.process(x, y);
+            prop_assert_eq!(gate.process(-x, -y), (-ox, -oy));
+            prop_assert_eq!(gate.process(-x, y), (-ox, oy));
+        }
+    }
+
+    #[test]
+    fn corners_reach_the_notches_and_edges_are_flat() {
+        let gate = OctagonGate { snap_degrees: 0 };
+        let limit = (FULL * EDGE / 1000) as i16;
+        // straight right and straight up reach the notch at full deflection
+        let (x, y) = gate.process(i16::MAX, 0);
+        assert!(x >= limit - 1 && y == 0);
+        let (x, y) = gate.process(0, i16::MAX);
+        assert!(x == 0 && y >= limit - 1);
+        // halfway between two notches the edge sits closest to the center, the normals
+        // rounded to 1/1000ths leave it a few counts short
+        let edge = gate.process(30_274, 12_540);
+        assert!((magnitude(edge.0 as i32, edge.1 as i32) - limit as f64).abs() < 16.0);
+    }
+
+    #[test]
+    fn snapping_past_the_table_is_clamped() {
+        let wide = OctagonGate {
+            snap_degrees: u8::MAX,
+        };
+        let max = OctagonGate {
+            snap_degrees: MAX_SNAP_DEGREES,
+        };
+        for (x, y) in [(20_000, 7_000), (-15_000, 9_000), (100, -3_000)] {
+            assert_eq!(wide.process(x, y), max.process(x, y));
+        }
+    }
+
+    #[test]
+    fn center_stays_centered() {
+        for snap_degrees in 0..=MAX_SNAP_DEGREES {
+            assert_eq!(OctagonGate { snap_degrees }.process(0, 0), (0, 0));
+        }
+    }
+}
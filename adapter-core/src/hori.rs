@@ -0,0 +1,156 @@
+use crate::gamepad::{Button, GamepadState, StickPosition};
+
+// buttons u16, hat, left x, left y, right x, right y, vendor byte
+pub const HORI_REPORT_LEN: usize = 8;
+
+// report bits of the buttons, in order
+const HORI_BUTTONS: [Button; 14] = [
+    Button::Y,
+    Button::B,
+    Button::A,
+    Button::X,
+    Button::L,
+    Button::R,
+    Button::ZL,
+    Button::ZR,
+    Button::Minus,
+    Button::Plus,
+    Button::LStick,
+    Button::RStick,
+    Button::Home,
+    Button::Capture,
+];
+
+// hat values start at up and turn clockwise in 45° steps
+const HAT_NEUTRAL: u8 = 0x08;
+
+// the input report for the pad state the switch would see
+pub fn report(state: &GamepadState) -> [u8; HORI_REPORT_LEN] {
+    let mut buttons = 0u16;
+    for (bit, button) in HORI_BUTTONS.iter().enumerate() {
+        if state.buttons.is_pressed(*button) {
+            buttons |= 1 << bit
+        }
+    }
+    let [buttons_lo, buttons_hi] = buttons.to_le_bytes();
+    let (left_x, left_y) = axes(state.left_stick);
+    let (right_x, right_y) = axes(state.right_stick);
+    [
+        buttons_lo,
+        buttons_hi,
+        hat(state),
+        left_x,
+        left_y,
+        right_x,
+        right_y,
+        0,
+    ]
+}
+
+// The hat can only point one way along each axis, so opposing directions cancel out
+// like `Socd::Neutral` would. With any other SOCD mode the d-pad stage has already
+// dropped one of the two.
+fn hat(state: &GamepadState) -> u8 {
+    let pressed = |button| state.buttons.is_pressed(button) as i8;
+    let x = pressed(Button::Right) - pressed(Button::Left);
+    let y = pressed(Button::Up) - pressed(Button::Down);
+    match (x, y) {
+        (0, 1) => 0,
+        (1, 1) => 1,
+        (1, 0) => 2,
+        (1, -1) => 3,
+        (0, -1) => 4,
+        (-1, -1) => 5,
+        (-1, 0) => 6,
+        (-1, 1) => 7,
+        _ => HAT_NEUTRAL,
+    }
+}
+
+// 12 bit switch axes to 8 bits, the hori y axis grows downwards
+fn axes(stick: StickPosition) -> (u8, u8) {
+    let x = (stick.x >> 4) as u8;
+    let y = ((StickPosition::MAX - stick.y) >> 4) as u8;
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(buttons: &[Button]) -> GamepadState {
+        let mut state = GamepadState::NEUTRAL;
+        for &button in buttons {
+            state.buttons.press(button)
+        }
+        state
+    }
+
+    #[test]
+    fn neutral_report() {
+        assert_eq!(
+            report(&GamepadState::NEUTRAL),
+            [0x00, 0x00, HAT_NEUTRAL, 0x80, 0x7f, 0x80, 0x7f, 0x00]
+        );
+    }
+
+    #[test]
+    fn buttons_take_their_bits_in_order() {
+        for (bit, button) in HORI_BUTTONS.into_iter().enumerate() {
+            let report = report(&pressed(&[button]));
+            assert_eq!(
+                u16::from_le_bytes([report[0], report[1]]),
+                1 << bit,
+                "{button:?}"
+            );
+            assert_eq!(report[2], HAT_NEUTRAL);
+        }
+        // the switch has no report bit for the grip button or the joy-con side buttons
+        let report = report(&pressed(&[Button::ChargingGrip, Button::LeftSL]));
+        assert_eq!(report[..2], [0, 0]);
+    }
+
+    #[test]
+    fn hat_goes_clockwise_from_up() {
+        let directions: [&[Button]; 8] = [
+            &[Button::Up],
+            &[Button::Up, Button::Right],
+            &[Button::Right],
+            &[Button::Down, Button::Right],
+            &[Button::Down],
+            &[Button::Down, Button::Left],
+            &[Button::Left],
+            &[Button::Up, Button::Left],
+        ];
+        for (value, directions) in directions.into_iter().enumerate() {
+            assert_eq!(report(&pressed(directions))[2], value as u8);
+        }
+    }
+
+    #[test]
+    fn opposing_directions_cancel_out() {
+        let cases: [(&[Button], u8); 5] = [
+            (&[Button::Up, Button::Down], HAT_NEUTRAL),
+            (&[Button::Left, Button::Right], HAT_NEUTRAL),
+            (&[Button::Up, Button::Down, Button::Right], 2),
+            (&[Button::Left, Button::Right, Button::Down], 4),
+            (
+                &[Button::Up, Button::Down, Button::Left, Button::Right],
+                HAT_NEUTRAL,
+            ),
+        ];
+        for (directions, hat) in cases {
+            assert_eq!(report(&pressed(directions))[2], hat, "{directions:?}");
+        }
+    }
+
+    #[test]
+    fn sticks_are_8_bit_with_y_growing_down() {
+        let state = GamepadState {
+            left_stick: StickPosition::new(0, StickPosition::MAX),
+            right_stick: StickPosition::new(StickPosition::MAX, 0),
+            ..GamepadState::NEUTRAL
+        };
+        assert_eq!(report(&state)[3..], [0x00, 0x00, 0xff, 0xff, 0x00]);
+    }
+}
@@ -0,0 +1,446 @@
+use crate::amiibo::NTAG215_SIZE;
+use crate::library::{self, AmiiboFile, LibraryError, AMIIBO_NAME_LEN};
+use crate::storage::{self, AMIIBO_SLOTS, PROFILES_SLOT};
+use embedded_storage::nor_flash::NorFlash;
+
+// Amiibo library and profile management from a pc, on a hid interface of its own that
+// every personality has. The host writes 64 byte output reports with id HOST_REQUEST
+// and gets one HOST_REPLY input report back for each:
+//
+//   request [HOST_REQUEST, command, slot, arguments..]
+//   reply   [HOST_REPLY, command, status, slot, results..]
+pub const HOST_REQUEST: u8 = 0xa0;
+pub const HOST_REPLY: u8 = 0xa1;
+pub const HOST_PACKET_SIZE: usize = 64;
+
+// [.., offset lo, offset hi, length, data..] stores part of a dump for `slot`
+const UPLOAD: u8 = 0x01;
+// [.., name length, name..] checks the uploaded dump and saves it to `slot`
+const COMMIT: u8 = 0x02;
+// replies [.., slot count, active, present, uid[7], name length, name..]
+const LIST: u8 = 0x03;
+// [.., name length, name..]
+const RENAME: u8 = 0x04;
+const DELETE: u8 = 0x05;
+const SELECT: u8 = 0x06;
+// [.., offset lo, offset hi, length, data..] stores part of the encoded profiles, the
+// slot is ignored
+const PROFILES_UPLOAD: u8 = 0x07;
+// [.., length lo, length hi] checks the uploaded profiles, saves and applies them
+const PROFILES_COMMIT: u8 = 0x08;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Status {
+    Ok = 0x00,
+    BadRequest = 0x01,
+    NoSuchSlot = 0x02,
+    EmptySlot = 0x03,
+    InvalidDump = 0x04,
+    FlashError = 0x05,
+    InvalidProfiles = 0x06,
+}
+
+impl<E> From<LibraryError<E>> for Status {
+    fn from(e: LibraryError<E>) -> Self {
+        match e {
+            LibraryError::Flash(_) => Status::FlashError,
+            LibraryError::NoSuchSlot => Status::NoSuchSlot,
+            LibraryError::EmptySlot => Status::EmptySlot,
+            LibraryError::InvalidDump => Status::InvalidDump,
+        }
+    }
+}
+
+// `PROFILES` is the size of the largest encoded profile list
+#[derive(Debug)]
+pub struct HostInterface<const PROFILES: usize> {
+    // the dump being uploaded and how many bytes of it arrived so far
+    upload: [u8; NTAG215_SIZE],
+    uploaded: usize,
+    upload_slot: u8,
+    profiles: [u8; PROFILES],
+    profiles_uploaded: usize,
+}
+
+impl<const PROFILES: usize> HostInterface<PROFILES> {
+    pub fn new() -> Self {
+        Self {
+            upload: [0; NTAG215_SIZE],
+            uploaded: 0,
+            upload_slot: 0,
+            profiles: [0; PROFILES],
+            profiles_uploaded: 0,
+        }
+    }
+
+    // Runs one request and returns the reply. `decode` checks uploaded profiles before
+    // they are saved, the decoded profiles come back once they are in flash.
+    pub async fn handle<F: NorFlash, P>(
+        &mut self,
+        flash: &mut F,
+        request: &[u8; HOST_PACKET_SIZE],
+        decode: impl FnOnce(&[u8]) -> Option<P>,
+    ) -> ([u8; HOST_PACKET_SIZE], Option<P>) {
+        let (command, slot, args) = (request[1], request[2], &request[3..]);
+        let mut reply = [0; HOST_PACKET_SIZE];
+        reply[0] = HOST_REPLY;
+        reply[1] = command;
+        reply[3] = slot;
+        let result = match command {
+            PROFILES_UPLOAD | PROFILES_COMMIT => self.profiles(flash, command, args, decode).await,
+            _ => self
+                .library(flash, command, slot, args, &mut reply[4..])
+                .await
+                .map(|()| None),
+        };
+        let (status, profiles) = match result {
+            Ok(profiles) => (Status::Ok, profiles),
+            Err(status) => {
+                warn!("host command {:x} failed: {}", command, status);
+                (status, None)
+            }
+        };
+        reply[2] = status as u8;
+        (reply, profiles)
+    }
+
+    async fn profiles<F: NorFlash, P>(
+        &mut self,
+        flash: &mut F,
+        command: u8,
+        args: &[u8],
+        decode: impl FnOnce(&[u8]) -> Option<P>,
+    ) -> Result<Option<P>, Status> {
+        if command == PROFILES_UPLOAD {
+            let (offset, data) = chunk(args, PROFILES)?;
+            if offset == 0 {
+                self.profiles_uploaded = 0;
+            }
+            if offset != self.profiles_uploaded {
+                return Err(Status::BadRequest);
+            }
+            self.profiles[offset..offset + data.len()].copy_from_slice(data);
+            self.profiles_uploaded += data.len();
+            return Ok(None);
+        }
+        let len = u16::from_le_bytes([args[0], args[1]]) as usize;
+        if len == 0 || len != self.profiles_uploaded {
+            return Err(Status::BadRequest);
+        }
+        let bytes = &self.profiles[..len];
+        let profiles = decode(bytes).ok_or(Status::InvalidProfiles)?;
+        storage::save_yielding(flash, PROFILES_SLOT, bytes)
+            .await
+            .map_err(|_| Status::FlashError)?;
+        self.profiles_uploaded = 0;
+        Ok(Some(profiles))
+    }
+
+    async fn library<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        command: u8,
+        slot: u8,
+        args: &[u8],
+        results: &mut [u8],
+    ) -> Result<(), Status> {
+        if slot >= AMIIBO_SLOTS {
+            return Err(Status::NoSuchSlot);
+        }
+        match command {
+            UPLOAD => {
+                let (offset, data) = chunk(args, NTAG215_SIZE)?;
+                let len = data.len();
+                if offset == 0 {
+                    self.uploaded = 0;
+                    self.upload_slot = slot;
+                }
+                // chunks have to arrive in order and for the same slot
+                if offset != self.uploaded || slot != self.upload_slot {
+                    return Err(Status::BadRequest);
+                }
+                self.upload[offset..offset + len].copy_from_slice(data);
+                self.uploaded += len;
+                Ok(())
+            }
+            COMMIT => {
+                let name = name(args)?;
+                if self.uploaded != NTAG215_SIZE || slot != self.upload_slot {
+                    return Err(Status::BadRequest);
+                }
+                let amiibo = library::validate(&self.upload).ok_or(Status::InvalidDump)?;
+                library::save_file(flash, slot, &AmiiboFile::new(name, amiibo)).await?;
+                self.uploaded = 0;
+                info!("amiibo {} uploaded", slot);
+                Ok(())
+            }
+            LIST => {
+                results[0] = AMIIBO_SLOTS;
+                results[1] = library::active(flash);
+                if let Some(file) = library::load_file(flash, slot)? {
+                    let name = file.name.as_bytes();
+                    results[2] = 1;
+                    results[3..10].copy_from_slice(&file.amiibo.uid());
+                    results[10] = name.len() as u8;
+                    results[11..11 + name.len()].copy_from_slice(name);
+                }
+                Ok(())
+            }
+            RENAME => Ok(library::rename(flash, slot, name(args)?).await?),
+            DELETE => Ok(library::delete(flash, slot).await?),
+            SELECT => Ok(library::select(flash, slot).await?),
+            _ => Err(Status::BadRequest),
+        }
+    }
+}
+
+impl<const PROFILES: usize> Default for HostInterface<PROFILES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// [offset lo, offset hi, length, data..] of an upload into a buffer of `size` bytes
+fn chunk(args: &[u8], size: usize) -> Result<(usize, &[u8]), Status> {
+    let offset = u16::from_le_bytes([args[0], args[1]]) as usize;
+    let len = args[2] as usize;
+    let data = args
+        .get(3..3 + len)
+        .filter(|_| offset + len <= size)
+        .ok_or(Status::BadRequest)?;
+    Ok((offset, data))
+}
+
+// a length prefixed utf-8 name
+fn name(args: &[u8]) -> Result<&str, Status> {
+    let len = args[0] as usize;
+    if len > AMIIBO_NAME_LEN {
+        return Err(Status::BadRequest);
+    }
+    let bytes = args.get(1..1 + len).ok_or(Status::BadRequest)?;
+    core::str::from_utf8(bytes).map_err(|_| Status::BadRequest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::dump;
+    use crate::storage::mock::{block_on, MockFlash};
+    use crate::storage::SECTOR_SIZE;
+
+    const PROFILES: usize = 256;
+    const CHUNK: usize = 48;
+
+    type Host = HostInterface<PROFILES>;
+
+    fn request(command: u8, slot: u8, args: &[u8]) -> [u8; HOST_PACKET_SIZE] {
+        let mut request = [0; HOST_PACKET_SIZE];
+        request[..3].copy_from_slice(&[HOST_REQUEST, command, slot]);
+        request[3..3 + args.len()].copy_from_slice(args);
+        request
+    }
+
+    // [offset lo, offset hi, length, data..]
+    fn chunk_args(offset: usize, data: &[u8]) -> Vec<u8> {
+        let mut args = (offset as u16).to_le_bytes().to_vec();
+        args.push(data.len() as u8);
+        args.extend(data);
+        args
+    }
+
+    fn name_args(name: &str) -> Vec<u8> {
+        let mut args = vec![name.len() as u8];
+        args.extend(name.as_bytes());
+        args
+    }
+
+    // the status of one request that carries no profiles
+    fn run(host: &mut Host, flash: &mut MockFlash, command: u8, slot: u8, args: &[u8]) -> Status {
+        let (reply, _) = run_reply(host, flash, command, slot, args);
+        status_of(&reply)
+    }
+
+    fn run_reply(
+        host: &mut Host,
+        flash: &mut MockFlash,
+        command: u8,
+        slot: u8,
+        args: &[u8],
+    ) -> ([u8; HOST_PACKET_SIZE], Option<()>) {
+        let (reply, profiles) =
+            block_on(host.handle(flash, &request(command, slot, args), |_| Some(())));
+        assert_eq!(reply[..2], [HOST_REPLY, command]);
+        assert_eq!(reply[3], slot);
+        (reply, profiles)
+    }
+
+    fn status_of(reply: &[u8]) -> Status {
+        [
+            Status::Ok,
+            Status::BadRequest,
+            Status::NoSuchSlot,
+            Status::EmptySlot,
+            Status::InvalidDump,
+            Status::FlashError,
+            Status::InvalidProfiles,
+        ]
+        .into_iter()
+        .find(|&status| status as u8 == reply[2])
+        .unwrap()
+    }
+
+    fn upload(host: &mut Host, flash: &mut MockFlash, slot: u8, data: &[u8]) {
+        for (i, piece) in data.chunks(CHUNK).enumerate() {
+            let args = chunk_args(i * CHUNK, piece);
+            assert_eq!(run(host, flash, UPLOAD, slot, &args), Status::Ok);
+        }
+    }
+
+    #[test]
+    fn an_uploaded_amiibo_shows_up_in_the_list() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::dirty());
+        let dump = dump(3);
+        upload(&mut host, &mut flash, 3, &dump);
+        let status = run(&mut host, &mut flash, COMMIT, 3, &name_args("Link"));
+        assert_eq!(status, Status::Ok);
+
+        let (reply, _) = run_reply(&mut host, &mut flash, LIST, 3, &[]);
+        assert_eq!(status_of(&reply), Status::Ok);
+        let results = &reply[4..];
+        assert_eq!(results[..3], [AMIIBO_SLOTS, 0, 1]);
+        let uid = [
+            dump[0], dump[1], dump[2], dump[4], dump[5], dump[6], dump[7],
+        ];
+        assert_eq!(results[3..10], uid);
+        assert_eq!(results[10..15], *b"\x04Link");
+
+        let (reply, _) = run_reply(&mut host, &mut flash, LIST, 4, &[]);
+        assert_eq!(reply[4..7], [AMIIBO_SLOTS, 0, 0]);
+    }
+
+    #[test]
+    fn uploads_have_to_arrive_in_order_for_one_slot() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::new());
+        let dump = dump(1);
+        let first = chunk_args(0, &dump[..CHUNK]);
+        assert_eq!(run(&mut host, &mut flash, UPLOAD, 1, &first), Status::Ok);
+        let skipped = chunk_args(2 * CHUNK, &dump[2 * CHUNK..3 * CHUNK]);
+        let status = run(&mut host, &mut flash, UPLOAD, 1, &skipped);
+        assert_eq!(status, Status::BadRequest);
+        let other_slot = chunk_args(CHUNK, &dump[CHUNK..2 * CHUNK]);
+        let status = run(&mut host, &mut flash, UPLOAD, 2, &other_slot);
+        assert_eq!(status, Status::BadRequest);
+        // and a commit needs the whole dump
+        let status = run(&mut host, &mut flash, COMMIT, 1, &name_args("Mario"));
+        assert_eq!(status, Status::BadRequest);
+        // chunks past the end of a dump are refused outright
+        let past = chunk_args(NTAG215_SIZE - 4, &[0; 8]);
+        let status = run(&mut host, &mut flash, UPLOAD, 1, &past);
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(flash.erases, 0);
+    }
+
+    #[test]
+    fn invalid_dumps_and_names_are_not_saved() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::new());
+        let mut bad = dump(2);
+        bad[3] ^= 0xff;
+        upload(&mut host, &mut flash, 2, &bad);
+        let status = run(&mut host, &mut flash, COMMIT, 2, &name_args("Bad"));
+        assert_eq!(status, Status::InvalidDump);
+
+        upload(&mut host, &mut flash, 2, &dump(2));
+        let long = "x".repeat(AMIIBO_NAME_LEN + 1);
+        let status = run(&mut host, &mut flash, COMMIT, 2, &name_args(&long));
+        assert_eq!(status, Status::BadRequest);
+        let status = run(&mut host, &mut flash, COMMIT, 2, &[2, 0xff, 0xfe]);
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(flash.erases, 0);
+    }
+
+    #[test]
+    fn slots_past_the_library_are_refused() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::new());
+        for command in [UPLOAD, COMMIT, LIST, RENAME, DELETE, SELECT] {
+            let status = run(&mut host, &mut flash, command, AMIIBO_SLOTS, &[]);
+            assert_eq!(status, Status::NoSuchSlot);
+        }
+        let status = run(&mut host, &mut flash, 0x7f, 0, &[]);
+        assert_eq!(status, Status::BadRequest);
+    }
+
+    #[test]
+    fn stored_amiibo_can_be_renamed_selected_and_deleted() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::new());
+        assert_eq!(
+            run(&mut host, &mut flash, SELECT, 5, &[]),
+            Status::EmptySlot
+        );
+        let status = run(&mut host, &mut flash, RENAME, 5, &name_args("Fox"));
+        assert_eq!(status, Status::EmptySlot);
+
+        upload(&mut host, &mut flash, 5, &dump(5));
+        run(&mut host, &mut flash, COMMIT, 5, &name_args("Falco"));
+        let status = run(&mut host, &mut flash, RENAME, 5, &name_args("Fox"));
+        assert_eq!(status, Status::Ok);
+        assert_eq!(run(&mut host, &mut flash, SELECT, 5, &[]), Status::Ok);
+        let (reply, _) = run_reply(&mut host, &mut flash, LIST, 5, &[]);
+        assert_eq!(reply[4..7], [AMIIBO_SLOTS, 5, 1]);
+        assert_eq!(reply[14..18], *b"\x03Fox");
+
+        assert_eq!(run(&mut host, &mut flash, DELETE, 5, &[]), Status::Ok);
+        let (reply, _) = run_reply(&mut host, &mut flash, LIST, 5, &[]);
+        assert_eq!(reply[6], 0);
+    }
+
+    fn upload_profiles(host: &mut Host, flash: &mut MockFlash, data: &[u8]) {
+        for (i, piece) in data.chunks(CHUNK).enumerate() {
+            let args = chunk_args(i * CHUNK, piece);
+            assert_eq!(run(host, flash, PROFILES_UPLOAD, 0, &args), Status::Ok);
+        }
+    }
+
+    #[test]
+    fn committed_profiles_are_saved_and_handed_back() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::dirty());
+        let data: Vec<u8> = (0..200).collect();
+        upload_profiles(&mut host, &mut flash, &data);
+        let commit = request(PROFILES_COMMIT, 0, &200u16.to_le_bytes());
+        let (reply, profiles) =
+            block_on(host.handle(&mut flash, &commit, |bytes| Some(bytes.to_vec())));
+        assert_eq!(status_of(&reply), Status::Ok);
+        assert_eq!(profiles, Some(data.clone()));
+        let mut buf = [0; SECTOR_SIZE as usize];
+        assert_eq!(
+            storage::load(&mut flash, PROFILES_SLOT, &mut buf).unwrap(),
+            Some(&data[..])
+        );
+
+        // the upload is used up by the commit
+        let (reply, profiles) = block_on(host.handle(&mut flash, &commit, |_| Some(())));
+        assert_eq!((status_of(&reply), profiles), (Status::BadRequest, None));
+    }
+
+    #[test]
+    fn profiles_that_do_not_decode_leave_flash_alone() {
+        let (mut host, mut flash) = (Host::new(), MockFlash::new());
+        upload_profiles(&mut host, &mut flash, &[1; 100]);
+        let commit = request(PROFILES_COMMIT, 0, &100u16.to_le_bytes());
+        let (reply, profiles) = block_on(host.handle(&mut flash, &commit, |_| None::<()>));
+        assert_eq!(
+            (status_of(&reply), profiles),
+            (Status::InvalidProfiles, None)
+        );
+        assert_eq!(flash.erases, 0);
+
+        // a length that does not match the upload is refused before decoding
+        let commit = request(PROFILES_COMMIT, 0, &99u16.to_le_bytes());
+        let (reply, _) = block_on(host.handle(&mut flash, &commit, |_| -> Option<()> { panic!() }));
+        assert_eq!(status_of(&reply), Status::BadRequest);
+        // and so are uploads larger than the profile buffer
+        let past = chunk_args(PROFILES - 4, &[0; 8]);
+        let status = run(&mut host, &mut flash, PROFILES_UPLOAD, 0, &past);
+        assert_eq!(status, Status::BadRequest);
+    }
+}
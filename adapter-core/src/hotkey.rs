@@ -0,0 +1,351 @@
+use crate::gamepad::{Button, Buttons};
+use crate::input::{XboxButton, XboxButtons, XboxState};
+use embassy_time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+pub const MAX_HOTKEYS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HotkeyTrigger {
+    // every button pressed within the chord window of each other
+    Chord(XboxButtons),
+    // one button held for `ms`, a shorter press still sends the button itself
+    Hold { button: XboxButton, ms: u16 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Hotkey {
+    pub trigger: HotkeyTrigger,
+    pub target: Button,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HotkeyConfig {
+    // how long the first button of a chord is held back waiting for the rest
+    pub chord_window_ms: u16,
+    // how long a held back button is sent for when it turns out not to be a hotkey
+    pub tap_ms: u16,
+    pub hotkeys: [Option<Hotkey>; MAX_HOTKEYS],
+}
+
+impl Default for HotkeyConfig {
+    // Guide and Share already map to Home and Capture, these cover pads without Share
+    fn default() -> Self {
+        let mut view_menu = XboxButtons::default();
+        view_menu.set(XboxButton::View, true);
+        view_menu.set(XboxButton::Menu, true);
+        Self {
+            chord_window_ms: 50,
+            tap_ms: 100,
+            hotkeys: [
+                Some(Hotkey {
+                    trigger: HotkeyTrigger::Chord(view_menu),
+                    target: Button::Capture,
+                }),
+                Some(Hotkey {
+                    trigger: HotkeyTrigger::Hold {
+                        button: XboxButton::Guide,
+                        ms: 500,
+                    },
+                    target: Button::Capture,
+                }),
+                None,
+                None,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    // held back while deciding whether this is a hotkey
+    Pending {
+        since: Instant,
+        seen: XboxButtons,
+    },
+    Active,
+    // not a hotkey after all, replaying the held back press
+    Tap {
+        until: Instant,
+        buttons: XboxButtons,
+    },
+    // not a hotkey and still held, passed through until released
+    Passthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkeys {
+    pub config: HotkeyConfig,
+    states: [State; MAX_HOTKEYS],
+    held: XboxButtons,
+}
+
+impl Hotkeys {
+    pub fn new(config: HotkeyConfig) -> Self {
+        Self {
+            config,
+            states: [State::Idle; MAX_HOTKEYS],
+            held: XboxButtons::default(),
+        }
+    }
+
+    // true while a decision depends on time passing rather than on new input
+    pub fn is_pending(&self) -> bool {
+        self.states
+            .iter()
+            .any(|state| matches!(state, State::Pending { .. } | State::Tap { .. }))
+    }
+
+    // hides hotkey buttons from `xbox` and returns the switch buttons they press
+    pub fn input(&mut self, xbox: &mut XboxState, now: Instant) -> Buttons {
+        let buttons = xbox.buttons;
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+
+        let chord_window = Duration::from_millis(self.config.chord_window_ms as u64);
+        let tap = Duration::from_millis(self.config.tap_ms as u64);
+        let mut hide = XboxButtons::default();
+        let mut inject = XboxButtons::default();
+        let mut output = Buttons::NONE;
+
+        for (hotkey, state) in self.config.hotkeys.iter().zip(self.states.iter_mut()) {
+            let Some(hotkey) = hotkey else {
+                *state = State::Idle;
+                continue;
+            };
+            let (members, complete, hold) = match hotkey.trigger {
+                HotkeyTrigger::Chord(chord) => (chord, (buttons & chord) == chord, chord_window),
+                HotkeyTrigger::Hold { button, ms } => {
+                    (button.into(), false, Duration::from_millis(ms as u64))
+                }
+            };
+            let held = buttons & members;
+
+            *state = match *state {
+                State::Idle | State::Tap { .. } if !(pressed & members).is_empty() => {
+                    if complete {
+                        State::Active
+                    } else {
+                        State::Pending {
+                            since: now,
+                            seen: held,
+                        }
+                    }
+                }
+                State::Tap { until, .. } if now >= until => State::Idle,
+                State::Pending { .. } if complete => State::Active,
+                State::Pending { seen, .. } if held.is_empty() => State::Tap {
+                    until: now + tap,
+                    buttons: seen,
+                },
+                State::Pending { since, seen } => {
+                    let elapsed = now.duration_since(since) >= hold;
+                    match hotkey.trigger {
+                        HotkeyTrigger::Hold { .. } if elapsed => State::Active,
+                        HotkeyTrigger::Chord(_) if elapsed => State::Passthrough,
+                        _ => State::Pending {
+                            since,
+                            seen: seen | held,
+                        },
+                    }
+                }
+                State::Active | State::Passthrough if held.is_empty() => State::Idle,
+                state => state,
+            };
+
+            match *state {
+                State::Pending { .. } => hide = hide | members,
+                State::Active => {
+                    hide = hide | members;
+                    output.press(hotkey.target);
+                }
+                State::Tap { buttons, .. } => inject = inject | buttons,
+                State::Idle | State::Passthrough => (),
+            }
+        }
+
+        xbox.buttons = (buttons & !hide) | inject;
+        output
+    }
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self::new(HotkeyConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buttons(pressed: &[XboxButton]) -> XboxButtons {
+        let mut buttons = XboxButtons::default();
+        for &button in pressed {
+            buttons.set(button, true);
+        }
+        buttons
+    }
+
+    // feeds `held` at `ms` and returns what is left of the xbox buttons and the switch
+    // buttons the hotkeys pressed
+    fn step(hotkeys: &mut Hotkeys, held: &[XboxButton], ms: u64) -> (XboxButtons, Buttons) {
+        let mut xbox = XboxState {
+            buttons: buttons(held),
+            ..XboxState::default()
+        };
+        let output = hotkeys.input(&mut xbox, Instant::from_millis(ms));
+        (xbox.buttons, output)
+    }
+
+    fn capture() -> Buttons {
+        Button::Capture.into()
+    }
+
+    #[test]
+    fn long_press_guide_sends_capture_once_the_hold_elapsed() {
+        let mut hotkeys = Hotkeys::default();
+        for ms in (0..500).step_by(8) {
+            assert_eq!(
+                step(&mut hotkeys, &[XboxButton::Guide], ms),
+                (buttons(&[]), Buttons::NONE)
+            );
+            assert!(hotkeys.is_pending());
+        }
+        for ms in (500..2_000).step_by(8) {
+            assert_eq!(
+                step(&mut hotkeys, &[XboxButton::Guide], ms),
+                (buttons(&[]), capture())
+            );
+        }
+        assert!(!hotkeys.is_pending());
+        assert_eq!(
+            step(&mut hotkeys, &[], 2_000),
+            (buttons(&[]), Buttons::NONE)
+        );
+    }
+
+    #[test]
+    fn short_guide_press_is_replayed_as_a_tap() {
+        let mut hotkeys = Hotkeys::default();
+        let tap = hotkeys.config.tap_ms as u64;
+        step(&mut hotkeys, &[XboxButton::Guide], 0);
+        step(&mut hotkeys, &[XboxButton::Guide], 120);
+        // released before the hold, the press goes out late but for tap_ms
+        for ms in (200..200 + tap).step_by(8) {
+            assert_eq!(
+                step(&mut hotkeys, &[], ms),
+                (buttons(&[XboxButton::Guide]), Buttons::NONE)
+            );
+        }
+        assert_eq!(
+            step(&mut hotkeys, &[], 200 + tap),
+            (buttons(&[]), Buttons::NONE)
+        );
+        assert!(!hotkeys.is_pending());
+    }
+
+    #[test]
+    fn chord_within_the_window_sends_capture_and_hides_its_buttons() {
+        let mut hotkeys = Hotkeys::default();
+        let window = hotkeys.config.chord_window_ms as u64;
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::View], 0),
+            (buttons(&[]), Buttons::NONE)
+        );
+        let chord = [XboxButton::View, XboxButton::Menu];
+        for ms in [window - 1, window + 100, 1_000] {
+            assert_eq!(step(&mut hotkeys, &chord, ms), (buttons(&[]), capture()));
+        }
+        // letting go of one button of the chord keeps the other hidden until both are up
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::Menu], 1_008),
+            (buttons(&[]), capture())
+        );
+        assert_eq!(
+            step(&mut hotkeys, &[], 1_016),
+            (buttons(&[]), Buttons::NONE)
+        );
+    }
+
+    #[test]
+    fn simultaneous_chord_fires_immediately() {
+        let mut hotkeys = Hotkeys::default();
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::View, XboxButton::Menu], 0),
+            (buttons(&[]), capture())
+        );
+    }
+
+    #[test]
+    fn chord_button_held_past_the_window_passes_through() {
+        let mut hotkeys = Hotkeys::default();
+        let window = hotkeys.config.chord_window_ms as u64;
+        step(&mut hotkeys, &[XboxButton::View], 0);
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::View], window - 1),
+            (buttons(&[]), Buttons::NONE)
+        );
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::View], window),
+            (buttons(&[XboxButton::View]), Buttons::NONE)
+        );
+        // the rest of the chord arriving late is just another button
+        let late = [XboxButton::View, XboxButton::Menu];
+        assert_eq!(
+            step(&mut hotkeys, &late, window + 100),
+            (buttons(&late), Buttons::NONE)
+        );
+    }
+
+    #[test]
+    fn other_buttons_are_never_held_back() {
+        let mut hotkeys = Hotkeys::default();
+        let held = [XboxButton::A, XboxButton::LB, XboxButton::Up];
+        for ms in (0..1_000).step_by(8) {
+            assert_eq!(
+                step(&mut hotkeys, &held, ms),
+                (buttons(&held), Buttons::NONE)
+            );
+        }
+        assert!(!hotkeys.is_pending());
+    }
+
+    #[test]
+    fn other_buttons_pass_while_a_hotkey_is_pending() {
+        let mut hotkeys = Hotkeys::default();
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::Guide, XboxButton::A], 0),
+            (buttons(&[XboxButton::A]), Buttons::NONE)
+        );
+    }
+
+    #[test]
+    fn custom_hold_targets_any_button() {
+        let config = HotkeyConfig {
+            hotkeys: [
+                Some(Hotkey {
+                    trigger: HotkeyTrigger::Hold {
+                        button: XboxButton::Menu,
+                        ms: 200,
+                    },
+                    target: Button::Home,
+                }),
+                None,
+                None,
+                None,
+            ],
+            ..HotkeyConfig::default()
+        };
+        let mut hotkeys = Hotkeys::new(config);
+        step(&mut hotkeys, &[XboxButton::Menu], 0);
+        assert_eq!(
+            step(&mut hotkeys, &[XboxButton::Menu], 200),
+            (buttons(&[]), Button::Home.into())
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+
+pub const XBOX_REPORT_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum XboxButton {
+    A = 0,
+    B = 1,
+    X = 2,
+    Y = 3,
+    LB = 4,
+    RB = 5,
+    View = 6,
+    Menu = 7,
+    Guide = 8,
+    Share = 9,
+    LS = 10,
+    RS = 11,
+    Up = 12,
+    Down = 13,
+    Left = 14,
+    Right = 15,
+}
+
+impl XboxButton {
+    pub const COUNT: usize = 16;
+
+    pub const ALL: [XboxButton; Self::COUNT] = [
+        XboxButton::A,
+        XboxButton::B,
+        XboxButton::X,
+        XboxButton::Y,
+        XboxButton::LB,
+        XboxButton::RB,
+        XboxButton::View,
+        XboxButton::Menu,
+        XboxButton::Guide,
+        XboxButton::Share,
+        XboxButton::LS,
+        XboxButton::RS,
+        XboxButton::Up,
+        XboxButton::Down,
+        XboxButton::Left,
+        XboxButton::Right,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct XboxButtons(u16);
+
+impl XboxButtons {
+    pub fn is_pressed(self, button: XboxButton) -> bool {
+        self.0 & (1 << button as u8) != 0
+    }
+
+    pub fn set(&mut self, button: XboxButton, pressed: bool) {
+        if pressed {
+            self.0 |= 1 << button as u8
+        } else {
+            self.0 &= !(1 << button as u8)
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl core::ops::BitOr for XboxButtons {
+    type Output = XboxButtons;
+
+    fn bitor(self, rhs: XboxButtons) -> XboxButtons {
+        XboxButtons(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitAnd for XboxButtons {
+    type Output = XboxButtons;
+
+    fn bitand(self, rhs: XboxButtons) -> XboxButtons {
+        XboxButtons(self.0 & rhs.0)
+    }
+}
+
+impl core::ops::Not for XboxButtons {
+    type Output = XboxButtons;
+
+    fn not(self) -> XboxButtons {
+        XboxButtons(!self.0)
+    }
+}
+
+impl From<XboxButton> for XboxButtons {
+    fn from(button: XboxButton) -> Self {
+        XboxButtons(1 << button as u8)
+    }
+}
+
+// sticks are signed with up and right positive, triggers are 10 bit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct XboxState {
+    pub buttons: XboxButtons,
+    pub left_x: i16,
+    pub left_y: i16,
+    pub right_x: i16,
+    pub right_y: i16,
+    pub left_trigger: u16,
+    pub right_trigger: u16,
+}
+
+impl XboxState {
+    pub const TRIGGER_MAX: u16 = 1023;
+
+    // parses the 16 byte input report (id 0x01) of the xbox wireless controller
+    pub fn parse(report: &[u8]) -> Option<Self> {
+        if report.len() < XBOX_REPORT_LEN {
+            return None;
+        }
+        let u16_at = |idx: usize| u16::from_le_bytes([report[idx], report[idx + 1]]);
+        // axes are unsigned with down positive on the wire
+        let axis = |idx: usize| (u16_at(idx) ^ 0x8000) as i16;
+        let inverted_axis = |idx: usize| (!u16_at(idx) ^ 0x8000) as i16;
+
+        let mut buttons = XboxButtons::default();
+        let bit = |byte: usize, bit: u8| report[byte] & (1 << bit) != 0;
+        buttons.set(XboxButton::A, bit(13, 0));
+        buttons.set(XboxButton::B, bit(13, 1));
+        buttons.set(XboxButton::X, bit(13, 3));
+        buttons.set(XboxButton::Y, bit(13, 4));
+        buttons.set(XboxButton::LB, bit(13, 6));
+        buttons.set(XboxButton::RB, bit(13, 7));
+        buttons.set(XboxButton::View, bit(14, 2));
+        buttons.set(XboxButton::Menu, bit(14, 3));
+        buttons.set(XboxButton::Guide, bit(14, 4));
+        buttons.set(XboxButton::LS, bit(14, 5));
+        buttons.set(XboxButton::RS, bit(14, 6));
+        buttons.set(XboxButton::Share, bit(15, 0));
+
+        // hat switch, 0 is released and 1..=8 go clockwise from up
+        let hat = report[12];
+        buttons.set(XboxButton::Up, matches!(hat, 1 | 2 | 8));
+        buttons.set(XboxButton::Right, matches!(hat, 2..=4));
+        buttons.set(XboxButton::Down, matches!(hat, 4..=6));
+        buttons.set(XboxButton::Left, matches!(hat, 6..=8));
+
+        Some(Self {
+            buttons,
+            left_x: axis(0),
+            left_y: inverted_axis(2),
+            right_x: axis(4),
+            right_y: inverted_axis(6),
+            left_trigger: u16_at(8).min(Self::TRIGGER_MAX),
+            right_trigger: u16_at(10).min(Self::TRIGGER_MAX),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(axes: [u16; 4], triggers: [u16; 2], hat: u8, buttons: [u8; 3]) -> [u8; 16] {
+        let mut report = [0; XBOX_REPORT_LEN];
+        for (idx, value) in axes.into_iter().chain(triggers).enumerate() {
+            report[idx * 2..idx * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        report[12] = hat;
+        report[13..16].copy_from_slice(&buttons);
+        report
+    }
+
+    #[test]
+    fn axes_are_signed_with_up_and_right_positive() {
+        let xbox = XboxState::parse(&report([0xFFFF, 0, 0, 0xFFFF], [0; 2], 0, [0; 3])).unwrap();
+        assert_eq!((xbox.left_x, xbox.left_y), (i16::MAX, i16::MAX));
+        assert_eq!((xbox.right_x, xbox.right_y), (i16::MIN, i16::MIN));
+        let xbox = XboxState::parse(&report([0x8000; 4], [0; 2], 0, [0; 3])).unwrap();
+        assert_eq!((xbox.left_x, xbox.right_x), (0, 0));
+    }
+
+    #[test]
+    fn triggers_are_capped_at_ten_bits() {
+        let xbox = XboxState::parse(&report([0x8000; 4], [0xFFFF, 512], 0, [0; 3])).unwrap();
+        assert_eq!(xbox.left_trigger, XboxState::TRIGGER_MAX);
+        assert_eq!(xbox.right_trigger, 512);
+    }
+
+    #[test]
+    fn buttons_come_from_their_bits() {
+        let xbox = XboxState::parse(&report([0x8000; 4], [0; 2], 0, [0xDB, 0x7C, 0x01])).unwrap();
+        for button in XboxButton::ALL {
+            let dpad = matches!(
+                button,
+                XboxButton::Up | XboxButton::Down | XboxButton::Left | XboxButton::Right
+            );
+            assert_eq!(xbox.buttons.is_pressed(button), !dpad, "{button:?}");
+        }
+    }
+
+    #[test]
+    fn hat_goes_clockwise_from_up() {
+        use XboxButton::*;
+        let expected: [&[XboxButton]; 9] = [
+            &[],
+            &[Up],
+            &[Up, Right],
+            &[Right],
+            &[Right, Down],
+            &[Down],
+            &[Down, Left],
+            &[Left],
+            &[Left, Up],
+        ];
+        for (hat, pressed) in expected.into_iter().enumerate() {
+            let xbox = XboxState::parse(&report([0x8000; 4], [0; 2], hat as u8, [0; 3])).unwrap();
+            let buttons = pressed
+                .iter()
+                .fold(XboxButtons::default(), |buttons, button| {
+                    buttons | (*button).into()
+                });
+            assert_eq!(xbox.buttons, buttons, "hat {hat}");
+        }
+    }
+
+    #[test]
+    fn short_reports_are_rejected() {
+        assert_eq!(XboxState::parse(&[0; XBOX_REPORT_LEN - 1]), None);
+    }
+}
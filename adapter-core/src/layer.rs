@@ -0,0 +1,385 @@
+use crate::gamepad::{Button, Buttons};
+use crate::input::{XboxButton, XboxButtons, XboxState};
+use crate::mapping::Mapping;
+use embassy_time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+pub const MAX_DUAL_FUNCTIONS: usize = 4;
+
+// sends `tap` when the button is tapped and `hold` while it is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DualFunction {
+    pub button: XboxButton,
+    pub tap: Button,
+    pub hold: Button,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LayerConfig {
+    // holding this switches to `mapping`, tapping it alone sends it as usual
+    pub shift: Option<XboxButton>,
+    pub mapping: Mapping,
+    pub dual: [Option<DualFunction>; MAX_DUAL_FUNCTIONS],
+    // presses longer than this are holds, never taps
+    pub hold_ms: u16,
+    // how long a resolved tap is sent for
+    pub tap_ms: u16,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        Self {
+            shift: None,
+            mapping: Mapping::default(),
+            dual: [None; MAX_DUAL_FUNCTIONS],
+            hold_ms: 200,
+            tap_ms: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Idle,
+    // pressed, not yet known to be a tap or a hold
+    Pending { since: Instant, interrupted: bool },
+    Held,
+    Tap { until: Instant },
+}
+
+// Resolves shift and dual function buttons. Only the order of presses and the time
+// between them decide the outcome, so the same input always resolves the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layers {
+    pub config: LayerConfig,
+    shift: Key,
+    dual: [Key; MAX_DUAL_FUNCTIONS],
+    held: XboxButtons,
+}
+
+impl Layers {
+    pub fn new(config: LayerConfig) -> Self {
+        Self {
+            config,
+            shift: Key::Idle,
+            dual: [Key::Idle; MAX_DUAL_FUNCTIONS],
+            held: XboxButtons::default(),
+        }
+    }
+
+    // true while the second layer's mapping should be used
+    pub fn shifted(&self) -> bool {
+        matches!(self.shift, Key::Pending { .. } | Key::Held)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        let waiting = |key: &Key| matches!(key, Key::Pending { .. } | Key::Tap { .. });
+        waiting(&self.shift) || self.dual.iter().any(waiting)
+    }
+
+    // hides shift and dual function buttons from `xbox` and returns what the dual
+    // function buttons press
+    pub fn input(&mut self, xbox: &mut XboxState, now: Instant) -> Buttons {
+        let buttons = xbox.buttons;
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+
+        let hold = Duration::from_millis(self.config.hold_ms as u64);
+        let tap = Duration::from_millis(self.config.tap_ms as u64);
+        let mut hide = XboxButtons::default();
+        let mut inject = XboxButtons::default();
+        let mut output = Buttons::NONE;
+
+        if let Some(shift) = self.config.shift {
+            // the layer is active for the whole press, only a tap sends shift itself
+            self.shift = resolve(self.shift, shift, buttons, pressed, now, hold, tap);
+            match self.shift {
+                Key::Tap { .. } => inject = inject | shift.into(),
+                _ => hide = hide | shift.into(),
+            }
+        } else {
+            self.shift = Key::Idle
+        }
+
+        for (dual, key) in self.config.dual.iter().zip(self.dual.iter_mut()) {
+            let Some(dual) = dual else {
+                *key = Key::Idle;
+                continue;
+            };
+            *key = resolve(*key, dual.button, buttons, pressed, now, hold, tap);
+            hide = hide | dual.button.into();
+            match *key {
+                Key::Held => output.press(dual.hold),
+                Key::Tap { .. } => output.press(dual.tap),
+                Key::Idle | Key::Pending { .. } => (),
+            }
+        }
+
+        xbox.buttons = (buttons & !hide) | inject;
+        output
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::new(LayerConfig::default())
+    }
+}
+
+// one step of the tap or hold decision for `button`
+fn resolve(
+    key: Key,
+    button: XboxButton,
+    buttons: XboxButtons,
+    pressed: XboxButtons,
+    now: Instant,
+    hold: Duration,
+    tap: Duration,
+) -> Key {
+    let down = buttons.is_pressed(button);
+    // any other button pressed meanwhile makes this a hold
+    let others = !(pressed & !XboxButtons::from(button)).is_empty();
+    match key {
+        Key::Idle | Key::Tap { .. } if pressed.is_pressed(button) => Key::Pending {
+            since: now,
+            interrupted: others,
+        },
+        Key::Tap { until } if now >= until => Key::Idle,
+        Key::Pending { since, interrupted } if !down => {
+            if interrupted || now.duration_since(since) >= hold {
+                Key::Idle
+            } else {
+                Key::Tap { until: now + tap }
+            }
+        }
+        Key::Pending { since, interrupted } => {
+            if interrupted || others || now.duration_since(since) >= hold {
+                Key::Held
+            } else {
+                Key::Pending { since, interrupted }
+            }
+        }
+        Key::Held if !down => Key::Idle,
+        key => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buttons(pressed: &[XboxButton]) -> XboxButtons {
+        let mut buttons = XboxButtons::default();
+        for &button in pressed {
+            buttons.set(button, true);
+        }
+        buttons
+    }
+
+    // feeds `held` at `ms` and returns what is left of the xbox buttons, the switch buttons
+    // the dual function buttons press and whether the second layer is in use
+    fn step(layers: &mut Layers, held: &[XboxButton], ms: u64) -> (XboxButtons, Buttons, bool) {
+        let mut xbox = XboxState {
+            buttons: buttons(held),
+            ..XboxState::default()
+        };
+        let output = layers.input(&mut xbox, Instant::from_millis(ms));
+        (xbox.buttons, output, layers.shifted())
+    }
+
+    fn shift() -> Layers {
+        Layers::new(LayerConfig {
+            shift: Some(XboxButton::LS),
+            ..LayerConfig::default()
+        })
+    }
+
+    fn dual() -> Layers {
+        Layers::new(LayerConfig {
+            dual: [
+                Some(DualFunction {
+                    button: XboxButton::RB,
+                    tap: Button::R,
+                    hold: Button::ZR,
+                }),
+                None,
+                None,
+                None,
+            ],
+            ..LayerConfig::default()
+        })
+    }
+
+    #[test]
+    fn shift_tap_alone_sends_the_button_after_release() {
+        let mut layers = shift();
+        let (hold, tap) = (layers.config.hold_ms as u64, layers.config.tap_ms as u64);
+        let none = buttons(&[]);
+        // the layer is already in use while it is unclear what the press is
+        assert_eq!(
+            step(&mut layers, &[XboxButton::LS], 0),
+            (none, Buttons::NONE, true)
+        );
+        assert_eq!(
+            step(&mut layers, &[XboxButton::LS], hold - 1),
+            (none, Buttons::NONE, true)
+        );
+        let released = hold - 1;
+        for ms in (released..released + tap).step_by(8) {
+            assert_eq!(
+                step(&mut layers, &[], ms),
+                (buttons(&[XboxButton::LS]), Buttons::NONE, false)
+            );
+        }
+        assert_eq!(
+            step(&mut layers, &[], released + tap),
+            (none, Buttons::NONE, false)
+        );
+        assert!(!layers.is_pending());
+    }
+
+    #[test]
+    fn shift_held_past_the_hold_time_never_taps() {
+        let mut layers = shift();
+        let hold = layers.config.hold_ms as u64;
+        step(&mut layers, &[XboxButton::LS], 0);
+        assert!(step(&mut layers, &[XboxButton::LS], hold).2);
+        assert!(!layers.is_pending());
+        assert_eq!(
+            step(&mut layers, &[], hold + 8),
+            (buttons(&[]), Buttons::NONE, false)
+        );
+    }
+
+    #[test]
+    fn shift_with_another_press_is_a_hold_however_quick() {
+        let mut layers = shift();
+        step(&mut layers, &[XboxButton::LS], 0);
+        assert_eq!(
+            step(&mut layers, &[XboxButton::LS, XboxButton::A], 10),
+            (buttons(&[XboxButton::A]), Buttons::NONE, true)
+        );
+        assert_eq!(
+            step(&mut layers, &[XboxButton::A], 20),
+            (buttons(&[XboxButton::A]), Buttons::NONE, false)
+        );
+        assert_eq!(
+            step(&mut layers, &[], 30),
+            (buttons(&[]), Buttons::NONE, false)
+        );
+    }
+
+    #[test]
+    fn shift_pressed_together_with_another_button_is_a_hold() {
+        let mut layers = shift();
+        step(&mut layers, &[XboxButton::LS, XboxButton::B], 0);
+        assert_eq!(
+            step(&mut layers, &[], 10),
+            (buttons(&[]), Buttons::NONE, false)
+        );
+    }
+
+    #[test]
+    fn dual_function_tap_and_hold() {
+        let mut layers = dual();
+        let (hold, tap) = (layers.config.hold_ms as u64, layers.config.tap_ms as u64);
+        let none = buttons(&[]);
+        let r: Buttons = Button::R.into();
+        let zr: Buttons = Button::ZR.into();
+
+        step(&mut layers, &[XboxButton::RB], 0);
+        assert_eq!(step(&mut layers, &[], 50), (none, r, false));
+        assert_eq!(step(&mut layers, &[], 50 + tap - 1), (none, r, false));
+        assert_eq!(
+            step(&mut layers, &[], 50 + tap),
+            (none, Buttons::NONE, false)
+        );
+
+        let start = 1_000;
+        step(&mut layers, &[XboxButton::RB], start);
+        assert_eq!(
+            step(&mut layers, &[XboxButton::RB], start + hold - 1),
+            (none, Buttons::NONE, false)
+        );
+        for ms in (start + hold..start + 2_000).step_by(8) {
+            assert_eq!(step(&mut layers, &[XboxButton::RB], ms), (none, zr, false));
+        }
+        assert_eq!(
+            step(&mut layers, &[], start + 2_000),
+            (none, Buttons::NONE, false)
+        );
+    }
+
+    #[test]
+    fn dual_function_interrupted_by_another_press_holds_at_once() {
+        let mut layers = dual();
+        step(&mut layers, &[XboxButton::RB], 0);
+        assert_eq!(
+            step(&mut layers, &[XboxButton::RB, XboxButton::A], 20),
+            (buttons(&[XboxButton::A]), Button::ZR.into(), false)
+        );
+    }
+
+    #[test]
+    fn a_tap_pressed_again_starts_over() {
+        let mut layers = dual();
+        step(&mut layers, &[XboxButton::RB], 0);
+        step(&mut layers, &[], 20);
+        // pressed again while the first tap is still being sent
+        assert_eq!(
+            step(&mut layers, &[XboxButton::RB], 40),
+            (buttons(&[]), Buttons::NONE, false)
+        );
+        let hold = layers.config.hold_ms as u64;
+        assert_eq!(
+            step(&mut layers, &[XboxButton::RB], 40 + hold),
+            (buttons(&[]), Button::ZR.into(), false)
+        );
+    }
+
+    #[test]
+    fn same_timeline_resolves_the_same_way() {
+        let config = LayerConfig {
+            shift: Some(XboxButton::LS),
+            ..dual().config
+        };
+        let pool = [XboxButton::LS, XboxButton::RB, XboxButton::A];
+        let mut seed = 0x2545_f491_u32;
+        let mut timeline = Vec::new();
+        let mut ms = 0;
+        for _ in 0..2_000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            ms += 1 + (seed % 120) as u64;
+            let held: Vec<_> = pool
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| (seed >> (8 + i)) & 1 != 0)
+                .map(|(_, &button)| button)
+                .collect();
+            timeline.push((ms, held));
+        }
+        let run = || {
+            let mut layers = Layers::new(config);
+            timeline
+                .iter()
+                .map(|(ms, held)| step(&mut layers, held, *ms))
+                .collect::<Vec<_>>()
+        };
+        let first = run();
+        assert_eq!(first, run());
+        // and something happened along the way
+        assert!(first
+            .iter()
+            .any(|(_, output, _)| output.is_pressed(Button::R)));
+        assert!(first
+            .iter()
+            .any(|(_, output, _)| output.is_pressed(Button::ZR)));
+        assert!(first
+            .iter()
+            .any(|(xbox, _, _)| xbox.is_pressed(XboxButton::LS)));
+    }
+}
@@ -0,0 +1,34 @@
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+mod fmt;
+
+pub mod accessibility;
+pub mod amiibo;
+pub mod curve;
+pub mod descriptor;
+pub mod detect;
+pub mod dpad;
+pub mod gamepad;
+pub mod gate;
+pub mod hori;
+pub mod host;
+pub mod hotkey;
+pub mod input;
+pub mod layer;
+pub mod library;
+pub mod link;
+pub mod macros;
+pub mod mapping;
+pub mod mcu;
+pub mod motion;
+pub mod pad;
+pub mod profile;
+pub mod report;
+pub mod retro;
+pub mod ringcon;
+pub mod stick;
+pub mod storage;
+pub mod trigger;
+pub mod turbo;
+pub mod xinput;
@@ -0,0 +1,289 @@
+use crate::amiibo::{Amiibo, NTAG215_SIZE};
+use crate::storage::{self, amiibo_slot, SaveError, ACTIVE_AMIIBO_SLOT, AMIIBO_SLOTS};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+// bump whenever the file layout below changes
+pub const AMIIBO_FILE_VERSION: u8 = 1;
+pub const AMIIBO_NAME_LEN: usize = 32;
+// version, name length, name padded to AMIIBO_NAME_LEN, then the dump
+pub const AMIIBO_FILE_BYTES: usize = 2 + AMIIBO_NAME_LEN + NTAG215_SIZE;
+
+// capability container every amiibo carries in page 3
+const AMIIBO_CC: [u8; 4] = [0xf1, 0x10, 0xff, 0xee];
+// first byte of page 4 on a formatted amiibo
+const AMIIBO_MAGIC: u8 = 0xa5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LibraryError<E> {
+    Flash(E),
+    NoSuchSlot,
+    EmptySlot,
+    InvalidDump,
+}
+
+impl<E> From<SaveError<E>> for LibraryError<E> {
+    fn from(e: SaveError<E>) -> Self {
+        match e {
+            SaveError::Flash(e) => LibraryError::Flash(e),
+            // every file has the same size and fits its slot, so this is a bad file
+            SaveError::TooLarge => LibraryError::InvalidDump,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmiiboFile {
+    pub name: heapless::String<AMIIBO_NAME_LEN>,
+    pub amiibo: Amiibo,
+}
+
+impl AmiiboFile {
+    pub fn new(name: &str, amiibo: Amiibo) -> Self {
+        Self {
+            name: truncate(name),
+            amiibo,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; AMIIBO_FILE_BYTES] {
+        let mut bytes = [0; AMIIBO_FILE_BYTES];
+        let name = self.name.as_bytes();
+        bytes[0] = AMIIBO_FILE_VERSION;
+        bytes[1] = name.len() as u8;
+        bytes[2..2 + name.len()].copy_from_slice(name);
+        bytes[2 + AMIIBO_NAME_LEN..].copy_from_slice(self.amiibo.bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != AMIIBO_FILE_BYTES {
+            return None;
+        }
+        if bytes[0] != AMIIBO_FILE_VERSION {
+            warn!("ignoring amiibo with format version {}", bytes[0]);
+            return None;
+        }
+        let name = bytes.get(2..2 + bytes[1] as usize)?;
+        Some(Self {
+            name: truncate(core::str::from_utf8(name).ok()?),
+            amiibo: Amiibo::from_bytes(&bytes[2 + AMIIBO_NAME_LEN..])?,
+        })
+    }
+}
+
+// checks that `dump` is a complete NTAG215 amiibo dump
+pub fn validate(dump: &[u8]) -> Option<Amiibo> {
+    if dump.len() != NTAG215_SIZE {
+        return None;
+    }
+    let d = dump;
+    let uid_checks = d[3] == 0x88 ^ d[0] ^ d[1] ^ d[2] && d[8] == d[4] ^ d[5] ^ d[6] ^ d[7];
+    if !uid_checks || d[12..16] != AMIIBO_CC || d[16] != AMIIBO_MAGIC {
+        return None;
+    }
+    Amiibo::from_bytes(dump)
+}
+
+pub fn load_file<F: ReadNorFlash>(
+    flash: &mut F,
+    slot: u8,
+) -> Result<Option<AmiiboFile>, LibraryError<F::Error>> {
+    check_slot(slot)?;
+    let mut buf = [0; AMIIBO_FILE_BYTES];
+    let file = storage::load(flash, amiibo_slot(slot), &mut buf).map_err(LibraryError::Flash)?;
+    Ok(file.and_then(AmiiboFile::decode))
+}
+
+pub async fn save_file<F: NorFlash>(
+    flash: &mut F,
+    slot: u8,
+    file: &AmiiboFile,
+) -> Result<(), LibraryError<F::Error>> {
+    check_slot(slot)?;
+    storage::save_yielding(flash, amiibo_slot(slot), &file.encode())
+        .await
+        .map_err(LibraryError::from)
+}
+
+pub async fn rename<F: NorFlash>(
+    flash: &mut F,
+    slot: u8,
+    name: &str,
+) -> Result<(), LibraryError<F::Error>> {
+    let mut file = load_file(flash, slot)?.ok_or(LibraryError::EmptySlot)?;
+    file.name = truncate(name);
+    save_file(flash, slot, &file).await
+}
+
+pub async fn delete<F: NorFlash>(flash: &mut F, slot: u8) -> Result<(), LibraryError<F::Error>> {
+    check_slot(slot)?;
+    storage::clear(flash, amiibo_slot(slot))
+        .await
+        .map_err(LibraryError::Flash)
+}
+
+pub fn active<F: ReadNorFlash>(flash: &mut F) -> u8 {
+    let mut active = [0; 1];
+    match storage::load(flash, ACTIVE_AMIIBO_SLOT, &mut active) {
+        Ok(Some(&[slot])) if slot < AMIIBO_SLOTS => slot,
+        _ => 0,
+    }
+}
+
+pub async fn select<F: NorFlash>(flash: &mut F, slot: u8) -> Result<(), LibraryError<F::Error>> {
+    if load_file(flash, slot)?.is_none() {
+        return Err(LibraryError::EmptySlot);
+    }
+    storage::save_yielding(flash, ACTIVE_AMIIBO_SLOT, &[slot])
+        .await
+        .map_err(LibraryError::from)
+}
+
+// selects the next slot holding an amiibo after the active one, if there is any
+pub async fn cycle<F: NorFlash>(flash: &mut F) -> Result<Option<u8>, LibraryError<F::Error>> {
+    let active = active(flash);
+    for step in 1..=AMIIBO_SLOTS {
+        let slot = (active + step) % AMIIBO_SLOTS;
+        if load_file(flash, slot)?.is_some() {
+            select(flash, slot).await?;
+            return Ok(Some(slot));
+        }
+    }
+    Ok(None)
+}
+
+fn check_slot<E>(slot: u8) -> Result<(), LibraryError<E>> {
+    if slot < AMIIBO_SLOTS {
+        Ok(())
+    } else {
+        Err(LibraryError::NoSuchSlot)
+    }
+}
+
+fn truncate(name: &str) -> heapless::String<AMIIBO_NAME_LEN> {
+    let mut truncated = heapless::String::new();
+    for c in name.chars() {
+        if truncated.push(c).is_err() {
+            break;
+        }
+    }
+    truncated
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::storage::mock::{block_on, MockFlash};
+
+    // a dump that passes `validate`, told apart by `id`
+    pub fn dump(id: u8) -> [u8; NTAG215_SIZE] {
+        let mut dump = [id; NTAG215_SIZE];
+        let mut amiibo = Amiibo::from_bytes(&dump).unwrap();
+        amiibo.set_uid([0x04, id, 0x10, 0x20, 0x30, 0x40, id]);
+        dump.copy_from_slice(amiibo.bytes());
+        dump[12..16].copy_from_slice(&AMIIBO_CC);
+        dump[16] = AMIIBO_MAGIC;
+        dump
+    }
+
+    fn store(flash: &mut MockFlash, slot: u8, name: &str) -> Amiibo {
+        let amiibo = validate(&dump(slot)).unwrap();
+        block_on(save_file(flash, slot, &AmiiboFile::new(name, amiibo))).unwrap();
+        amiibo
+    }
+
+    #[test]
+    fn files_round_trip_through_flash() {
+        let mut flash = MockFlash::dirty();
+        let amiibo = store(&mut flash, 3, "Zelda");
+        let file = load_file(&mut flash, 3).unwrap().unwrap();
+        assert_eq!(file, AmiiboFile::new("Zelda", amiibo));
+        assert_eq!(load_file(&mut flash, 4).unwrap(), None);
+    }
+
+    #[test]
+    fn long_names_are_cut_on_a_character_boundary() {
+        let name = "é".repeat(AMIIBO_NAME_LEN);
+        let file = AmiiboFile::new(&name, validate(&dump(0)).unwrap());
+        assert_eq!(file.name.as_str(), &name[..AMIIBO_NAME_LEN]);
+        assert_eq!(AmiiboFile::decode(&file.encode()), Some(file));
+    }
+
+    #[test]
+    fn files_of_another_version_are_ignored() {
+        let mut bytes = AmiiboFile::new("Mario", validate(&dump(1)).unwrap()).encode();
+        bytes[0] = AMIIBO_FILE_VERSION + 1;
+        assert_eq!(AmiiboFile::decode(&bytes), None);
+    }
+
+    #[test]
+    fn validate_checks_the_uid_and_the_amiibo_header() {
+        assert!(validate(&dump(5)).is_some());
+        assert!(validate(&dump(5)[..NTAG215_SIZE - 1]).is_none());
+        for byte in [3, 8, 12, 16] {
+            let mut bad = dump(5);
+            bad[byte] ^= 1;
+            assert!(validate(&bad).is_none(), "byte {byte}");
+        }
+    }
+
+    #[test]
+    fn slots_past_the_library_are_refused() {
+        let mut flash = MockFlash::new();
+        let file = AmiiboFile::new("Kirby", validate(&dump(2)).unwrap());
+        assert_eq!(
+            block_on(save_file(&mut flash, AMIIBO_SLOTS, &file)),
+            Err(LibraryError::NoSuchSlot)
+        );
+        assert_eq!(
+            load_file(&mut flash, AMIIBO_SLOTS),
+            Err(LibraryError::NoSuchSlot)
+        );
+    }
+
+    #[test]
+    fn rename_keeps_the_dump_and_delete_empties_the_slot() {
+        let mut flash = MockFlash::new();
+        let amiibo = store(&mut flash, 1, "Link");
+        block_on(rename(&mut flash, 1, "Toon Link")).unwrap();
+        let file = load_file(&mut flash, 1).unwrap().unwrap();
+        assert_eq!((file.name.as_str(), file.amiibo), ("Toon Link", amiibo));
+        block_on(delete(&mut flash, 1)).unwrap();
+        assert_eq!(load_file(&mut flash, 1).unwrap(), None);
+        assert_eq!(
+            block_on(rename(&mut flash, 1, "x")),
+            Err(LibraryError::EmptySlot)
+        );
+    }
+
+    #[test]
+    fn only_stored_amiibo_can_be_selected() {
+        let mut flash = MockFlash::dirty();
+        assert_eq!(active(&mut flash), 0);
+        assert_eq!(
+            block_on(select(&mut flash, 2)),
+            Err(LibraryError::EmptySlot)
+        );
+        store(&mut flash, 2, "Samus");
+        block_on(select(&mut flash, 2)).unwrap();
+        assert_eq!(active(&mut flash), 2);
+    }
+
+    #[test]
+    fn cycle_skips_empty_slots_and_wraps() {
+        let mut flash = MockFlash::new();
+        assert_eq!(block_on(cycle(&mut flash)), Ok(None));
+        for slot in [1, 4, 6] {
+            store(&mut flash, slot, "Pikachu");
+        }
+        let order: Vec<_> = (0..4)
+            .map(|_| block_on(cycle(&mut flash)).unwrap())
+            .collect();
+        assert_eq!(order, [Some(1), Some(4), Some(6), Some(1)]);
+        // with a single amiibo the cycle lands on it again
+        block_on(delete(&mut flash, 4)).unwrap();
+        block_on(delete(&mut flash, 6)).unwrap();
+        assert_eq!(block_on(cycle(&mut flash)), Ok(Some(1)));
+    }
+}
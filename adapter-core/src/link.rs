@@ -0,0 +1,261 @@
+use crate::input::XboxState;
+use embassy_time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkEvent {
+    Connected,
+    // the transport heard from the controller or confirmed the connection is still up
+    Alive,
+    Lost,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LinkConfig {
+    // how long the link may stay silent before all inputs are released
+    pub supervision_timeout: Duration,
+    // how often a connected transport has to confirm the link, well below the timeout
+    pub keepalive: Duration,
+    pub reconnect_min: Duration,
+    pub reconnect_max: Duration,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            supervision_timeout: Duration::from_millis(250),
+            keepalive: Duration::from_millis(100),
+            reconnect_min: Duration::from_millis(100),
+            reconnect_max: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LinkSupervisor {
+    connected: bool,
+    released: bool,
+}
+
+impl LinkSupervisor {
+    pub fn new() -> Self {
+        Self {
+            connected: false,
+            released: true,
+        }
+    }
+
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+
+    // `None` means the supervision timeout elapsed without any event.
+    // Returns true when the controller state has to be forced to neutral.
+    pub fn step(&mut self, event: Option<LinkEvent>) -> bool {
+        match event {
+            Some(LinkEvent::Connected) => {
+                self.connected = true;
+                false
+            }
+            Some(LinkEvent::Alive) => {
+                self.connected = true;
+                self.released = false;
+                false
+            }
+            Some(LinkEvent::Lost) => {
+                self.connected = false;
+                self.release()
+            }
+            None => self.release(),
+        }
+    }
+
+    fn release(&mut self) -> bool {
+        let release = !self.released;
+        self.released = true;
+        release
+    }
+}
+
+impl Default for LinkSupervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The controller input the pipeline runs on. The pipeline also runs without new input,
+// on hotkey and layer deadlines or a profile upload, so a dropped link has to forget the
+// last report or those wakeups would press its buttons again after the release.
+#[derive(Debug, Default)]
+pub struct LinkInput {
+    xbox: XboxState,
+}
+
+impl LinkInput {
+    pub fn report(&mut self, xbox: XboxState) {
+        self.xbox = xbox
+    }
+
+    pub fn current(&self) -> &XboxState {
+        &self.xbox
+    }
+
+    pub fn lost(&mut self) {
+        self.xbox = XboxState::default()
+    }
+}
+
+#[derive(Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::XboxButton;
+
+    // replays a timeline of (ms, event) against the supervisor the way the firmware task
+    // does, returning the times at which the pad was forced to neutral
+    fn releases(config: LinkConfig, events: &[(u64, LinkEvent)], until: u64) -> Vec<u64> {
+        let timeout = config.supervision_timeout.as_millis();
+        let mut supervisor = LinkSupervisor::new();
+        let mut released = Vec::new();
+        let mut now = 0;
+        let mut events = events.iter().peekable();
+        loop {
+            let deadline = now + timeout;
+            let (at, event) = match events.peek() {
+                Some(&&(at, event)) if at <= deadline => {
+                    events.next();
+                    (at, Some(event))
+                }
+                _ => (deadline, None),
+            };
+            if at > until {
+                break;
+            }
+            now = at;
+            if supervisor.step(event) {
+                released.push(now);
+            }
+        }
+        released
+    }
+
+    fn keepalives(from: u64, to: u64, every: u64) -> impl Iterator<Item = (u64, LinkEvent)> {
+        (from..to)
+            .step_by(every as usize)
+            .map(|at| (at, LinkEvent::Alive))
+    }
+
+    #[test]
+    fn held_press_survives_keepalives() {
+        let config = LinkConfig::default();
+        let keepalive = config.keepalive.as_millis();
+        // pressed at 10ms and held for two seconds, the controller sends nothing new
+        let mut events = vec![(0, LinkEvent::Connected), (10, LinkEvent::Alive)];
+        events.extend(keepalives(10 + keepalive, 2_000, keepalive));
+        assert_eq!(releases(config, &events, 2_000), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn silent_link_mid_press_releases_once() {
+        let config = LinkConfig::default();
+        let keepalive = config.keepalive.as_millis();
+        let mut events = vec![(0, LinkEvent::Connected), (10, LinkEvent::Alive)];
+        // the radio goes quiet at 500ms while the button is still held
+        events.extend(keepalives(10 + keepalive, 500, keepalive));
+        let last = events.last().unwrap().0;
+        assert_eq!(
+            releases(config, &events, 3_000),
+            vec![last + config.supervision_timeout.as_millis()]
+        );
+    }
+
+    #[test]
+    fn lost_link_mid_press_releases_immediately() {
+        let config = LinkConfig::default();
+        let events = [
+            (0, LinkEvent::Connected),
+            (10, LinkEvent::Alive),
+            (60, LinkEvent::Alive),
+            (80, LinkEvent::Lost),
+            // reconnected and pressed again, then dropped a second time
+            (400, LinkEvent::Connected),
+            (420, LinkEvent::Alive),
+            (430, LinkEvent::Lost),
+        ];
+        assert_eq!(releases(config, &events, 1_000), vec![80, 430]);
+    }
+
+    #[test]
+    fn connect_and_loss_without_input_release_nothing() {
+        let mut supervisor = LinkSupervisor::new();
+        assert!(!supervisor.step(Some(LinkEvent::Connected)));
+        assert!(supervisor.connected());
+        assert!(!supervisor.step(None));
+        assert!(!supervisor.step(Some(LinkEvent::Lost)));
+        assert!(!supervisor.connected());
+    }
+
+    #[test]
+    fn released_input_stays_released_through_later_wakeups() {
+        let mut supervisor = LinkSupervisor::new();
+        let mut input = LinkInput::default();
+        let mut held = XboxState {
+            left_x: i16::MAX,
+            right_trigger: XboxState::TRIGGER_MAX,
+            ..XboxState::default()
+        };
+        held.buttons.set(XboxButton::A, true);
+
+        supervisor.step(Some(LinkEvent::Connected));
+        input.report(held);
+        assert!(!supervisor.step(Some(LinkEvent::Alive)));
+        // the link drops mid-press, the input task is told along with the release
+        if supervisor.step(Some(LinkEvent::Lost)) {
+            input.lost()
+        }
+        // a hotkey deadline or a profile upload wakes the pipeline up later on
+        for _ in 0..3 {
+            assert!(!supervisor.step(None));
+            assert_eq!(*input.current(), XboxState::default());
+        }
+        // the next report after reconnecting is used as is
+        supervisor.step(Some(LinkEvent::Connected));
+        input.report(held);
+        assert_eq!(*input.current(), held);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay().as_millis(), 100);
+    }
+}
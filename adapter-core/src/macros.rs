@@ -0,0 +1,256 @@
+use crate::gamepad::{Buttons, GamepadState, StickPosition};
+use serde::{Deserialize, Serialize};
+
+pub const MACRO_FRAMES: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroFrame {
+    // reports the previous frame was held for before this one
+    pub delay: u16,
+    pub state: GamepadState,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub frames: heapless::Vec<MacroFrame, MACRO_FRAMES>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PlaybackMode {
+    // the macro replaces live input while it plays
+    Override,
+    // buttons from both are held, a deflected macro stick wins over the live one
+    Merge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MacroConfig {
+    // starts and stops recording, empty disables it
+    pub record_combo: Buttons,
+    // starts and stops playback, empty disables it
+    pub play_combo: Buttons,
+    pub mode: PlaybackMode,
+}
+
+impl Default for MacroConfig {
+    fn default() -> Self {
+        Self {
+            record_combo: Buttons::NONE,
+            play_combo: Buttons::NONE,
+            mode: PlaybackMode::Override,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Recording {
+        last: GamepadState,
+        since: u16,
+    },
+    Playing {
+        next: usize,
+        wait: u16,
+        current: GamepadState,
+    },
+}
+
+// records and replays input one report at a time, so playback is frame accurate
+#[derive(Debug)]
+pub struct MacroEngine {
+    pub config: MacroConfig,
+    recording: Macro,
+    state: State,
+    held: Buttons,
+    consumed: Buttons,
+    // a finished recording waiting to be saved
+    recorded: bool,
+}
+
+impl MacroEngine {
+    pub fn new(config: MacroConfig) -> Self {
+        Self {
+            config,
+            recording: Macro::default(),
+            state: State::Idle,
+            held: Buttons::NONE,
+            consumed: Buttons::NONE,
+            recorded: false,
+        }
+    }
+
+    pub fn recording(&self) -> &Macro {
+        &self.recording
+    }
+
+    pub fn load(&mut self, recording: Macro) {
+        self.state = State::Idle;
+        self.recording = recording;
+    }
+
+    // true once after a recording finished
+    pub fn take_recorded(&mut self) -> bool {
+        core::mem::take(&mut self.recorded)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, State::Playing { .. })
+    }
+
+    // handles the record and play combos and hides them from the live state
+    pub fn input(&mut self, mut state: GamepadState) -> GamepadState {
+        let pressed = state.buttons & !self.held;
+        self.held = state.buttons;
+        self.consumed = self.consumed & state.buttons;
+
+        let completes = |combo: Buttons| {
+            !combo.is_empty() && (state.buttons & combo) == combo && !(pressed & combo).is_empty()
+        };
+        let record = completes(self.config.record_combo);
+        let play = completes(self.config.play_combo);
+
+        if record {
+            self.consumed = self.consumed | self.config.record_combo;
+            state.buttons = state.buttons & !self.consumed;
+            match self.state {
+                State::Recording { .. } => self.stop_recording(),
+                _ => {
+                    info!("macro recording started");
+                    self.recording.frames.clear();
+                    let _ = self.recording.frames.push(MacroFrame { delay: 0, state });
+                    self.state = State::Recording {
+                        last: state,
+                        since: 0,
+                    };
+                }
+            }
+        } else if play {
+            self.consumed = self.consumed | self.config.play_combo;
+            match self.state {
+                State::Playing { .. } => self.state = State::Idle,
+                _ => self.play(),
+            }
+        }
+
+        state.buttons = state.buttons & !self.consumed;
+        state
+    }
+
+    pub fn play(&mut self) {
+        if let State::Recording { .. } = self.state {
+            self.stop_recording()
+        }
+        match self.recording.frames.first() {
+            Some(first) => {
+                info!("macro playback started");
+                self.state = State::Playing {
+                    next: 1,
+                    wait: self.delay_of(1),
+                    current: first.state,
+                }
+            }
+            None => warn!("no macro recorded"),
+        }
+    }
+
+    // stops playback and recording and forgets held combos
+    pub fn reset(&mut self) {
+        self.stop_recording();
+        self.state = State::Idle;
+        self.held = Buttons::NONE;
+        self.consumed = Buttons::NONE;
+    }
+
+    // what the switch should see in the current report
+    pub fn output(&self, live: GamepadState) -> GamepadState {
+        match (self.state, self.config.mode) {
+            (State::Playing { current, .. }, PlaybackMode::Override) => current,
+            (State::Playing { current, .. }, PlaybackMode::Merge) => GamepadState {
+                buttons: live.buttons | current.buttons,
+                left_stick: merge_stick(live.left_stick, current.left_stick),
+                right_stick: merge_stick(live.right_stick, current.right_stick),
+            },
+            _ => live,
+        }
+    }
+
+    // called once per 0x30 report with the live state that report was built from
+    pub fn advance(&mut self, live: GamepadState) {
+        match &mut self.state {
+            State::Idle => (),
+            State::Recording { last, since } => {
+                if live != *last || *since == u16::MAX {
+                    let frame = MacroFrame {
+                        delay: *since,
+                        state: live,
+                    };
+                    *last = live;
+                    *since = 0;
+                    if self.recording.frames.push(frame).is_err() {
+                        warn!("macro is full, stopping recording");
+                        self.stop_recording();
+                        return;
+                    }
+                }
+                *since += 1;
+            }
+            State::Playing { next, wait, .. } => {
+                *wait = wait.saturating_sub(1);
+                if *wait > 0 {
+                    return;
+                }
+                let next = *next;
+                self.state = match self.recording.frames.get(next) {
+                    Some(frame) => State::Playing {
+                        next: next + 1,
+                        wait: self.delay_of(next + 1),
+                        current: frame.state,
+                    },
+                    None => {
+                        info!("macro playback finished");
+                        State::Idle
+                    }
+                };
+            }
+        }
+    }
+
+    fn delay_of(&self, frame: usize) -> u16 {
+        self.recording
+            .frames
+            .get(frame)
+            .map_or(1, |frame| frame.delay.max(1))
+    }
+
+    fn stop_recording(&mut self) {
+        if let State::Recording { last, since } = self.state {
+            // end on a neutral frame so playback lets go of everything
+            if last != GamepadState::NEUTRAL {
+                let _ = self.recording.frames.push(MacroFrame {
+                    delay: since,
+                    state: GamepadState::NEUTRAL,
+                });
+            }
+            info!("macro recorded with {} frames", self.recording.frames.len());
+            self.state = State::Idle;
+            self.recorded = true;
+        }
+    }
+}
+
+impl Default for MacroEngine {
+    fn default() -> Self {
+        Self::new(MacroConfig::default())
+    }
+}
+
+fn merge_stick(live: StickPosition, recorded: StickPosition) -> StickPosition {
+    if recorded == StickPosition::CENTER {
+        live
+    } else {
+        recorded
+    }
+}
@@ -0,0 +1,262 @@
+use crate::gamepad::{Button, GamepadState};
+use crate::input::{XboxButton, XboxState};
+use crate::stick;
+use serde::{Deserialize, Serialize};
+
+const AXIS_BINDINGS: usize = 4;
+// half travel, axes bound to buttons press them past this point
+const DIGITAL_THRESHOLD: i16 = i16::MAX / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Axis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+impl Axis {
+    fn read(self, xbox: &XboxState) -> i16 {
+        match self {
+            Axis::LeftX => xbox.left_x,
+            Axis::LeftY => xbox.left_y,
+            Axis::RightX => xbox.right_x,
+            Axis::RightY => xbox.right_y,
+        }
+    }
+}
+
+// presses `button` while `axis` is pushed past half travel in the given direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxisBinding {
+    pub axis: Axis,
+    pub positive: bool,
+    pub button: Button,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerMode {
+    Digital(Button),
+    // drives one half of a switch stick axis, overriding the stick while pulled
+    Analog { axis: Axis, positive: bool },
+    Disabled,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StickOptions {
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Mapping {
+    pub buttons: [Option<Button>; XboxButton::COUNT],
+    pub axis_bindings: [Option<AxisBinding>; AXIS_BINDINGS],
+    pub left_trigger: TriggerMode,
+    pub right_trigger: TriggerMode,
+    pub swap_sticks: bool,
+    pub left_stick: StickOptions,
+    pub right_stick: StickOptions,
+}
+
+impl Mapping {
+    // buttons land where they physically are on a switch pad, xbox A presses switch B
+    pub const POSITIONAL: Mapping = Mapping::with_face_buttons([
+        Some(Button::B),
+        Some(Button::A),
+        Some(Button::Y),
+        Some(Button::X),
+    ]);
+
+    // buttons keep their printed letter, xbox A presses switch A
+    pub const BY_LABEL: Mapping = Mapping::with_face_buttons([
+        Some(Button::A),
+        Some(Button::B),
+        Some(Button::X),
+        Some(Button::Y),
+    ]);
+
+    // for `RetroController::N64`, which reports C up, down, left and right on Y, ZR, X
+    // and minus. The right stick and xbox B and Y press the C-buttons, X is the green B
+    // button, LT is Z and RT the extra ZR shoulder on the left stick click.
+    pub const N64: Mapping = Mapping {
+        buttons: [
+            Some(Button::A),
+            Some(Button::ZR),
+            Some(Button::B),
+            Some(Button::X),
+            Some(Button::L),
+            Some(Button::R),
+            None,
+            Some(Button::Plus),
+            Some(Button::Home),
+            Some(Button::Capture),
+            None,
+            None,
+            Some(Button::Up),
+            Some(Button::Down),
+            Some(Button::Left),
+            Some(Button::Right),
+        ],
+        axis_bindings: [
+            Some(AxisBinding {
+                axis: Axis::RightY,
+                positive: true,
+                button: Button::Y,
+            }),
+            Some(AxisBinding {
+                axis: Axis::RightY,
+                positive: false,
+                button: Button::ZR,
+            }),
+            Some(AxisBinding {
+                axis: Axis::RightX,
+                positive: false,
+                button: Button::X,
+            }),
+            Some(AxisBinding {
+                axis: Axis::RightX,
+                positive: true,
+                button: Button::Minus,
+            }),
+        ],
+        left_trigger: TriggerMode::Digital(Button::ZL),
+        right_trigger: TriggerMode::Digital(Button::LStick),
+        swap_sticks: false,
+        left_stick: StickOptions {
+            invert_x: false,
+            invert_y: false,
+        },
+        right_stick: StickOptions {
+            invert_x: false,
+            invert_y: false,
+        },
+    };
+
+    // face buttons are given in xbox A, B, X, Y order
+    const fn with_face_buttons(face: [Option<Button>; 4]) -> Mapping {
+        Mapping {
+            buttons: [
+                face[0],
+                face[1],
+                face[2],
+                face[3],
+                Some(Button::L),
+                Some(Button::R),
+                Some(Button::Minus),
+                Some(Button::Plus),
+                Some(Button::Home),
+                Some(Button::Capture),
+                Some(Button::LStick),
+                Some(Button::RStick),
+                Some(Button::Up),
+                Some(Button::Down),
+                Some(Button::Left),
+                Some(Button::Right),
+            ],
+            axis_bindings: [None; AXIS_BINDINGS],
+            left_trigger: TriggerMode::Digital(Button::ZL),
+            right_trigger: TriggerMode::Digital(Button::ZR),
+            swap_sticks: false,
+            left_stick: StickOptions {
+                invert_x: false,
+                invert_y: false,
+            },
+            right_stick: StickOptions {
+                invert_x: false,
+                invert_y: false,
+            },
+        }
+    }
+
+    pub fn bind(&mut self, source: XboxButton, target: Option<Button>) {
+        self.buttons[source as usize] = target
+    }
+
+    pub fn swap(&mut self, a: XboxButton, b: XboxButton) {
+        self.buttons.swap(a as usize, b as usize)
+    }
+
+    // `triggers` are the already debounced digital (left, right) trigger states
+    pub fn apply(&self, xbox: &XboxState, triggers: (bool, bool)) -> GamepadState {
+        let mut state = GamepadState::NEUTRAL;
+
+        for source in XboxButton::ALL {
+            if let Some(target) = self.buttons[source as usize] {
+                if xbox.buttons.is_pressed(source) {
+                    state.buttons.press(target)
+                }
+            }
+        }
+
+        for binding in self.axis_bindings.iter().flatten() {
+            let value = binding.axis.read(xbox);
+            let pushed = if binding.positive {
+                value > DIGITAL_THRESHOLD
+            } else {
+                value < -DIGITAL_THRESHOLD
+            };
+            if pushed {
+                state.buttons.press(binding.button)
+            }
+        }
+
+        let (mut left, mut right) = ((xbox.left_x, xbox.left_y), (xbox.right_x, xbox.right_y));
+        if self.swap_sticks {
+            core::mem::swap(&mut left, &mut right)
+        }
+        let mut left = invert(left, self.left_stick);
+        let mut right = invert(right, self.right_stick);
+
+        for (mode, value, pressed) in [
+            (self.left_trigger, xbox.left_trigger, triggers.0),
+            (self.right_trigger, xbox.right_trigger, triggers.1),
+        ] {
+            match mode {
+                TriggerMode::Digital(button) => {
+                    if pressed {
+                        state.buttons.press(button)
+                    }
+                }
+                TriggerMode::Analog { axis, positive } if value > 0 => {
+                    let travel =
+                        (value as i32 * i16::MAX as i32 / XboxState::TRIGGER_MAX as i32) as i16;
+                    let travel = if positive { travel } else { -travel };
+                    match axis {
+                        Axis::LeftX => left.0 = travel,
+                        Axis::LeftY => left.1 = travel,
+                        Axis::RightX => right.0 = travel,
+                        Axis::RightY => right.1 = travel,
+                    }
+                }
+                TriggerMode::Analog { .. } | TriggerMode::Disabled => (),
+            }
+        }
+
+        state.left_stick = stick::from_xbox(left.0, left.1);
+        state.right_stick = stick::from_xbox(right.0, right.1);
+        state
+    }
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self::POSITIONAL
+    }
+}
+
+fn invert((x, y): (i16, i16), options: StickOptions) -> (i16, i16) {
+    let flip = |value: i16, invert: bool| {
+        if invert {
+            value.saturating_neg()
+        } else {
+            value
+        }
+    };
+    (flip(x, options.invert_x), flip(y, options.invert_y))
+}
@@ -0,0 +1,834 @@
+use crate::amiibo::{Amiibo, Tag, NTAG215_PAGES, NTAG215_SIZE};
+use embassy_time::Instant;
+
+// size of the mcu block at the end of a 0x31 report and of a SetMCUConf reply
+pub const MCU_REPORT_LEN: usize = 313;
+pub type McuReport = [u8; MCU_REPORT_LEN];
+// a SetMCUConf reply only has room for the start of the block, its crc8 moves up
+pub const CONF_REPLY_LEN: usize = 34;
+
+// firmware version reported in status replies, big endian major then minor
+const FIRMWARE: [u8; 4] = [0x00, 0x08, 0x00, 0x1b];
+
+// mcu report types, the first byte of every mcu block
+const REPORT_STATUS: u8 = 0x01;
+const REPORT_IR_FRAGMENT: u8 = 0x03;
+const REPORT_IR_MODE: u8 = 0x0b;
+const REPORT_IR_STATUS: u8 = 0x13;
+const REPORT_EMPTY: u8 = 0xff;
+
+// SetMCUConf commands, [0x21, 0x00, mode] picks what the mcu runs and the 0x23 ones
+// set up the ir camera once it runs in ir mode
+const CONFIGURE_MCU: u8 = 0x21;
+const SET_MCU_MODE: u8 = 0x00;
+const CONFIGURE_IR: u8 = 0x23;
+// [0x23, 0x01, ir mode, last fragment number, ..]
+const SET_IR_MODE: u8 = 0x01;
+const WRITE_IR_REGISTERS: u8 = 0x04;
+pub const MCU_COMMAND_LEN: usize = 8;
+
+// ir commands, the byte after the GetIRData request id
+const IR_STREAM: u8 = 0x00;
+const IR_STATUS: u8 = 0x02;
+pub const IR_REQUEST_LEN: usize = 8;
+
+// nfc commands, the byte after the GetNFCData request id
+const NFC_START_POLLING: u8 = 0x01;
+const NFC_STOP_POLLING: u8 = 0x02;
+const NFC_STATUS: u8 = 0x04;
+const NFC_READ: u8 = 0x06;
+const NFC_WRITE: u8 = 0x08;
+
+// GetNFCData requests are [command, sequence, ack, flags, length, payload..], long
+// payloads continue over several requests until one is flagged as the last
+pub const NFC_REQUEST_LEN: usize = 37;
+const NFC_LAST_FRAGMENT: u8 = 0x08;
+// a write payload is the uid length, the uid and then [page, 4 data bytes] records
+const NFC_WRITE_MAX: usize = 1 + 7 + NTAG215_PAGES as usize * 5;
+
+// fixed parts of the nfc status (0x2a) and tag read (0x3a) replies, as captured from a
+// pro controller
+const NFC_STATUS_HEADER: [u8; 7] = [0x2a, 0x00, 0x05, 0x00, 0x00, 0x09, 0x31];
+// one NTAG215 with a 7 byte uid in the field, the uid follows
+const NFC_TAG_INFO: [u8; 7] = [0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x07];
+const NFC_READ_FIRST: [u8; 15] = [
+    0x3a, 0x00, 0x07, 0x01, 0x00, 0x01, 0x31, 0x02, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x07,
+];
+const NFC_READ_SECOND: [u8; 7] = [0x3a, 0x00, 0x07, 0x02, 0x00, 0x08, 0x27];
+// sent by real controllers between the uid and the first page of a read
+const NTAG_READ_HEADER: [u8; 36] = [
+    0x00, 0x00, 0x00, 0x7d, 0xfd, 0xf0, 0x79, 0x36, 0x51, 0xab, 0xd7, 0x46, 0x6e, 0x39, 0xc1, 0x91,
+    0xba, 0xbe, 0xb8, 0x56, 0x83, 0x5f, 0x25, 0x36, 0x1d, 0x64, 0x43, 0xa6, 0xaf, 0x79, 0x8a, 0x6c,
+    0x43, 0xcf, 0x1d, 0x37,
+];
+// a tag read is split over two reports, this much of the dump goes in the first
+const FIRST_FRAGMENT: usize = 245;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum McuMode {
+    Suspended = 0x00,
+    Standby = 0x01,
+    Nfc = 0x04,
+    Ir = 0x05,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum NfcState {
+    Idle = 0x00,
+    Polling = 0x01,
+    Reading = 0x02,
+    Writing = 0x04,
+    WriteDone = 0x05,
+    // the tag was read and is still on the reader
+    ReadDone = 0x09,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Reply {
+    Status,
+    NfcStatus,
+    NfcRead(u8),
+    IrStatus,
+    IrFragment(u8),
+}
+
+// Emulates the NFC/IR microcontroller well enough to scan amiibo and to show games an
+// ir camera that sees nothing. Requests queue a reply which goes out with the next 0x31
+// report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mcu {
+    mode: McuMode,
+    nfc: NfcState,
+    pending: Option<Reply>,
+    tag: Option<Tag>,
+    write: [u8; NFC_WRITE_MAX],
+    write_len: usize,
+    // the tag changed and should be saved
+    written: bool,
+    ir_mode: u8,
+    // fragments are numbered 0..=ir_last_fragment and sent in a loop
+    ir_last_fragment: u8,
+    ir_next_fragment: u8,
+}
+
+impl Mcu {
+    pub fn new() -> Self {
+        Self {
+            mode: McuMode::Suspended,
+            nfc: NfcState::Idle,
+            pending: None,
+            tag: None,
+            write: [0; NFC_WRITE_MAX],
+            write_len: 0,
+            written: false,
+            ir_mode: 0,
+            ir_last_fragment: 0,
+            ir_next_fragment: 0,
+        }
+    }
+
+    // true once the switch woke the mcu and expects 0x31 reports
+    pub fn is_active(&self) -> bool {
+        self.mode != McuMode::Suspended
+    }
+
+    pub fn place_tag(&mut self, tag: Tag) {
+        self.tag = Some(tag)
+    }
+
+    // the slot and new contents of a tag the switch wrote to, once per write
+    pub fn take_written(&mut self) -> Option<(u8, Amiibo)> {
+        let tag = self.tag.filter(|_| self.written)?;
+        self.written = false;
+        Some((tag.slot, tag.stored()))
+    }
+
+    // subcommand 0x22, 0 suspends and anything else resumes
+    pub fn set_state(&mut self, state: u8) {
+        self.mode = if state == 0 {
+            McuMode::Suspended
+        } else {
+            McuMode::Standby
+        };
+        self.nfc = NfcState::Idle;
+        self.pending = None;
+        info!("mcu state {}", self.mode);
+    }
+
+    // subcommand 0x21, answered right away with the status of the new mode
+    pub fn configure(&mut self, command: &[u8]) -> McuReport {
+        let mut report = [0; MCU_REPORT_LEN];
+        match *command {
+            [CONFIGURE_MCU, SET_MCU_MODE, mode, ..] => {
+                match mode {
+                    0x00 | 0x01 => self.mode = McuMode::Standby,
+                    0x04 => self.mode = McuMode::Nfc,
+                    0x05 => {
+                        self.mode = McuMode::Ir;
+                        self.ir_mode = 0;
+                    }
+                    mode => warn!("unsupported mcu mode {:x}", mode),
+                }
+                self.nfc = NfcState::Idle;
+                info!("mcu mode {}", self.mode);
+                report[..8].copy_from_slice(&self.status());
+                // the reply to SetMCUConf marks the byte after the report type
+                report[2] = 0xff;
+            }
+            [CONFIGURE_IR, SET_IR_MODE, mode, last_fragment, ..] => {
+                self.ir_mode = mode;
+                self.ir_last_fragment = last_fragment;
+                self.ir_next_fragment = 0;
+                info!(
+                    "ir mode {:x} with {} fragments",
+                    mode,
+                    last_fragment as u16 + 1
+                );
+                report[0] = REPORT_IR_MODE;
+            }
+            // registers only tune the camera, there is nothing to tune
+            [CONFIGURE_IR, WRITE_IR_REGISTERS, ..] => self.ir_status(&mut report),
+            _ => {
+                warn!("unhandled mcu command {:x}", command);
+                report[..8].copy_from_slice(&self.status());
+            }
+        }
+        finish(report)
+    }
+
+    // GetMCUStatus
+    pub fn request_status(&mut self) {
+        self.pending = Some(Reply::Status)
+    }
+
+    // GetNFCData, `request` starts with the nfc command
+    pub fn request_nfc(&mut self, request: &[u8], now: Instant) {
+        self.expire_tag(now);
+        let command = request.first().copied().unwrap_or(NFC_STATUS);
+        self.pending = Some(match command {
+            NFC_START_POLLING => {
+                self.nfc = NfcState::Polling;
+                Reply::NfcStatus
+            }
+            NFC_STOP_POLLING => {
+                self.nfc = NfcState::Idle;
+                Reply::NfcStatus
+            }
+            NFC_READ if self.tag.is_some() => {
+                self.nfc = NfcState::Reading;
+                Reply::NfcRead(1)
+            }
+            // the switch asks for the second half with a status request
+            NFC_STATUS if self.nfc == NfcState::Reading && self.tag.is_some() => Reply::NfcRead(2),
+            NFC_WRITE if self.tag.is_some() => {
+                self.receive_write(request);
+                Reply::NfcStatus
+            }
+            NFC_STATUS => Reply::NfcStatus,
+            command => {
+                warn!("unhandled nfc command {:x}", command);
+                Reply::NfcStatus
+            }
+        });
+    }
+
+    // GetIRData, `request` starts with the ir command, the fourth byte acknowledges the
+    // last fragment received
+    pub fn request_ir(&mut self, request: &[u8]) {
+        self.pending = Some(match *request {
+            [IR_STREAM, _, _, ack, ..] if self.mode == McuMode::Ir => {
+                self.ir_next_fragment = if ack >= self.ir_last_fragment {
+                    0
+                } else {
+                    ack + 1
+                };
+                Reply::IrFragment(self.ir_next_fragment)
+            }
+            [IR_STATUS, ..] => Reply::IrStatus,
+            _ => Reply::Status,
+        });
+    }
+
+    // the mcu block of the next 0x31 report
+    pub fn report(&mut self, now: Instant) -> McuReport {
+        self.expire_tag(now);
+        let mut report = [0; MCU_REPORT_LEN];
+        let reply = self.pending.take().or(match self.mode {
+            McuMode::Suspended => None,
+            McuMode::Standby => Some(Reply::Status),
+            McuMode::Nfc => Some(Reply::NfcStatus),
+            // the mode stays 0 until the camera is set up
+            McuMode::Ir if self.ir_mode == 0 => Some(Reply::Status),
+            // resend whatever the switch has not acknowledged yet
+            McuMode::Ir => Some(Reply::IrFragment(self.ir_next_fragment)),
+        });
+        match reply {
+            None => report[0] = REPORT_EMPTY,
+            Some(Reply::Status) => report[..8].copy_from_slice(&self.status()),
+            Some(Reply::NfcStatus) => self.nfc_status(&mut report),
+            Some(Reply::NfcRead(fragment)) => {
+                self.nfc_read(fragment, &mut report);
+                if fragment == 2 {
+                    self.nfc = NfcState::ReadDone
+                }
+            }
+            Some(Reply::IrStatus) => self.ir_status(&mut report),
+            Some(Reply::IrFragment(fragment)) => {
+                // the 300 image bytes from offset 10 stay black, the camera sees nothing
+                report[0] = REPORT_IR_FRAGMENT;
+                report[3] = fragment;
+            }
+        }
+        finish(report)
+    }
+
+    fn expire_tag(&mut self, now: Instant) {
+        if self.tag.is_some_and(|tag| !tag.present(now)) {
+            info!("amiibo removed from the reader");
+            self.tag = None;
+            if self.nfc != NfcState::Idle {
+                self.nfc = NfcState::Polling
+            }
+        }
+    }
+
+    fn receive_write(&mut self, request: &[u8]) {
+        let [_, sequence, _, flags, len, ref payload @ ..] = *request else {
+            return;
+        };
+        if self.nfc != NfcState::Writing || sequence <= 1 {
+            self.write_len = 0
+        }
+        self.nfc = NfcState::Writing;
+        let len = (len as usize)
+            .min(payload.len())
+            .min(NFC_WRITE_MAX - self.write_len);
+        self.write[self.write_len..self.write_len + len].copy_from_slice(&payload[..len]);
+        self.write_len += len;
+        if flags & NFC_LAST_FRAGMENT != 0 {
+            self.apply_write();
+            self.nfc = NfcState::WriteDone;
+        }
+    }
+
+    fn apply_write(&mut self) {
+        let Some(tag) = self.tag.as_mut() else {
+            return;
+        };
+        let write = &self.write[..self.write_len];
+        let Some((&7, rest)) = write.split_first() else {
+            warn!("unexpected nfc write header");
+            return;
+        };
+        let (uid, records) = rest.split_at(7.min(rest.len()));
+        if uid != tag.amiibo.uid() {
+            warn!("nfc write for a different tag");
+            return;
+        }
+        for &[page, a, b, c, d] in records.as_chunks::<5>().0 {
+            if !tag.amiibo.write_page(page, [a, b, c, d]) {
+                warn!("refusing nfc write to page {}", page);
+            }
+        }
+        info!("amiibo {:x} written", tag.amiibo.uid());
+        self.written = true;
+    }
+
+    fn ir_status(&self, report: &mut McuReport) {
+        report[..4].copy_from_slice(&[REPORT_IR_STATUS, 0x00, self.ir_mode, 0x00]);
+    }
+
+    fn status(&self) -> [u8; 8] {
+        let f = FIRMWARE;
+        [
+            REPORT_STATUS,
+            0x00,
+            0x00,
+            f[0],
+            f[1],
+            f[2],
+            f[3],
+            self.mode as u8,
+        ]
+    }
+
+    fn nfc_status(&self, report: &mut McuReport) {
+        report[..7].copy_from_slice(&NFC_STATUS_HEADER);
+        report[7] = self.nfc as u8;
+        let tag_state = matches!(
+            self.nfc,
+            NfcState::Polling | NfcState::ReadDone | NfcState::WriteDone
+        );
+        if let (true, Some(tag)) = (tag_state, self.tag) {
+            report[8..15].copy_from_slice(&NFC_TAG_INFO);
+            report[15..22].copy_from_slice(&tag.amiibo.uid());
+        }
+    }
+
+    fn nfc_read(&self, fragment: u8, report: &mut McuReport) {
+        let Some(tag) = self.tag else {
+            return self.nfc_status(report);
+        };
+        let data = tag.amiibo.bytes();
+        if fragment == 1 {
+            report[..15].copy_from_slice(&NFC_READ_FIRST);
+            report[15..22].copy_from_slice(&tag.amiibo.uid());
+            report[22..58].copy_from_slice(&NTAG_READ_HEADER);
+            report[58..58 + FIRST_FRAGMENT].copy_from_slice(&data[..FIRST_FRAGMENT]);
+        } else {
+            report[..7].copy_from_slice(&NFC_READ_SECOND);
+            report[7..7 + NTAG215_SIZE - FIRST_FRAGMENT].copy_from_slice(&data[FIRST_FRAGMENT..]);
+        }
+    }
+}
+
+impl Default for Mcu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the last byte of an mcu block is a crc8 of the rest
+pub(crate) fn finish(mut report: McuReport) -> McuReport {
+    report[MCU_REPORT_LEN - 1] = crc8(&report[..MCU_REPORT_LEN - 1]);
+    report
+}
+
+// the data of the 0x21 reply to SetMCUConf
+pub fn conf_reply(report: &McuReport) -> [u8; CONF_REPLY_LEN] {
+    let mut reply = [0; CONF_REPLY_LEN];
+    reply[..CONF_REPLY_LEN - 1].copy_from_slice(&report[..CONF_REPLY_LEN - 1]);
+    reply[CONF_REPLY_LEN - 1] = crc8(&reply[..CONF_REPLY_LEN - 1]);
+    reply
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amiibo::TAG_PRESENT_MS;
+    use crate::report::{args, OUTPUT_ARGS, OUTPUT_SUBCOMMAND};
+
+    const SLOT: u8 = 2;
+    const UID: [u8; 7] = [0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+    fn amiibo() -> Amiibo {
+        let data: Vec<u8> = (0..NTAG215_SIZE).map(|i| (i % 251) as u8).collect();
+        let mut amiibo = Amiibo::from_bytes(&data).unwrap();
+        amiibo.set_uid(UID);
+        amiibo
+    }
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    // the mcu block of the next report, checking its crc on the way
+    fn report(mcu: &mut Mcu, now: Instant) -> McuReport {
+        let report = mcu.report(now);
+        assert_eq!(
+            report[MCU_REPORT_LEN - 1],
+            crc8(&report[..MCU_REPORT_LEN - 1])
+        );
+        report
+    }
+
+    fn nfc_mcu() -> Mcu {
+        let mut mcu = Mcu::new();
+        mcu.set_state(1);
+        mcu.configure(&[CONFIGURE_MCU, SET_MCU_MODE, McuMode::Nfc as u8]);
+        mcu
+    }
+
+    // a GetNFCData request carrying one fragment of a write
+    fn write_request(sequence: u8, last: bool, payload: &[u8]) -> [u8; NFC_REQUEST_LEN] {
+        let mut request = [0; NFC_REQUEST_LEN];
+        request[0] = NFC_WRITE;
+        request[1] = sequence;
+        request[3] = if last { NFC_LAST_FRAGMENT } else { 0 };
+        request[4] = payload.len() as u8;
+        request[5..5 + payload.len()].copy_from_slice(payload);
+        request
+    }
+
+    #[test]
+    fn suspended_mcu_sends_an_empty_block() {
+        let mut mcu = Mcu::new();
+        assert!(!mcu.is_active());
+        assert_eq!(report(&mut mcu, at(0))[0], REPORT_EMPTY);
+        mcu.set_state(1);
+        mcu.set_state(0);
+        assert_eq!(report(&mut mcu, at(0))[0], REPORT_EMPTY);
+    }
+
+    #[test]
+    fn resumed_mcu_reports_its_firmware_and_mode() {
+        let mut mcu = Mcu::new();
+        mcu.set_state(1);
+        assert!(mcu.is_active());
+        assert_eq!(
+            report(&mut mcu, at(0))[..8],
+            [REPORT_STATUS, 0x00, 0x00, 0x00, 0x08, 0x00, 0x1b, 0x01]
+        );
+    }
+
+    #[test]
+    fn configure_replies_with_the_new_mode_and_a_short_crc() {
+        let mut mcu = Mcu::new();
+        mcu.set_state(1);
+        let block = mcu.configure(&[CONFIGURE_MCU, SET_MCU_MODE, McuMode::Nfc as u8]);
+        assert_eq!(
+            block[..8],
+            [REPORT_STATUS, 0x00, 0xff, 0x00, 0x08, 0x00, 0x1b, 0x04]
+        );
+        let reply = conf_reply(&block);
+        assert_eq!(reply[..CONF_REPLY_LEN - 1], block[..CONF_REPLY_LEN - 1]);
+        assert_eq!(
+            reply[CONF_REPLY_LEN - 1],
+            crc8(&reply[..CONF_REPLY_LEN - 1])
+        );
+    }
+
+    #[test]
+    fn a_read_without_a_tag_answers_with_the_status() {
+        let mut mcu = nfc_mcu();
+        mcu.request_nfc(&[NFC_START_POLLING], at(0));
+        report(&mut mcu, at(0));
+        mcu.request_nfc(&[NFC_READ], at(10));
+        let block = report(&mut mcu, at(10));
+        assert_eq!(block[..7], NFC_STATUS_HEADER);
+        assert_eq!(block[7], NfcState::Polling as u8);
+    }
+
+    #[test]
+    fn the_tag_leaves_the_reader_after_a_while() {
+        let mut mcu = nfc_mcu();
+        mcu.request_nfc(&[NFC_START_POLLING], at(0));
+        mcu.place_tag(Tag::place(SLOT, amiibo(), at(0)));
+        assert_eq!(report(&mut mcu, at(TAG_PRESENT_MS - 1))[15..22], UID);
+        let block = report(&mut mcu, at(TAG_PRESENT_MS));
+        assert_eq!(block[7], NfcState::Polling as u8);
+        assert!(block[8..22].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn writes_to_a_random_uid_are_saved_with_the_stored_one() {
+        let mut mcu = nfc_mcu();
+        mcu.request_nfc(&[NFC_START_POLLING], at(0));
+        let random = [0x04, 0x99, 0x98, 0x97, 0x96, 0x95, 0x94];
+        mcu.place_tag(Tag::place(SLOT, amiibo(), at(0)).with_random_uid(random));
+        assert_eq!(report(&mut mcu, at(0))[15..22], random);
+
+        let mut payload = vec![7];
+        payload.extend(random);
+        payload.extend([4, 1, 2, 3, 4]);
+        mcu.request_nfc(&write_request(1, true, &payload), at(10));
+        report(&mut mcu, at(10));
+        let mut expected = amiibo();
+        expected.write_page(4, [1, 2, 3, 4]);
+        assert_eq!(mcu.take_written(), Some((SLOT, expected)));
+    }
+
+    #[test]
+    fn writes_for_another_tag_are_ignored() {
+        let mut mcu = nfc_mcu();
+        mcu.request_nfc(&[NFC_START_POLLING], at(0));
+        mcu.place_tag(Tag::place(SLOT, amiibo(), at(0)));
+        let mut payload = vec![7, 0x04, 0, 0, 0, 0, 0, 0];
+        payload.extend([4, 1, 2, 3, 4]);
+        mcu.request_nfc(&write_request(1, true, &payload), at(10));
+        report(&mut mcu, at(10));
+        assert_eq!(mcu.take_written(), None);
+    }
+
+    #[test]
+    fn an_amiibo_scan_goes_status_read_and_stop() {
+        let mut mcu = Mcu::new();
+        let amiibo = amiibo();
+        let blocks = replay(&mut mcu, &SCAN_TRACE[..3], 0);
+        assert_eq!(
+            blocks[0][..8],
+            [REPORT_STATUS, 0x00, 0x00, 0x00, 0x08, 0x00, 0x1b, 0x01]
+        );
+        assert_eq!(
+            blocks[1][..8],
+            [REPORT_STATUS, 0x00, 0xff, 0x00, 0x08, 0x00, 0x1b, 0x04]
+        );
+        assert_eq!(blocks[2][..7], NFC_STATUS_HEADER);
+        assert_eq!(blocks[2][7], NfcState::Polling as u8);
+        assert!(blocks[2][8..22].iter().all(|&byte| byte == 0));
+
+        mcu.place_tag(Tag::place(SLOT, amiibo, at(25)));
+        let blocks = replay(&mut mcu, &SCAN_TRACE[3..], 3);
+        assert_eq!(blocks[0][7], NfcState::Polling as u8);
+        assert_eq!(blocks[0][8..15], NFC_TAG_INFO);
+        assert_eq!(blocks[0][15..22], UID);
+
+        assert_eq!(blocks[1][..15], NFC_READ_FIRST);
+        assert_eq!(blocks[1][15..22], UID);
+        assert_eq!(blocks[1][22..58], NTAG_READ_HEADER);
+        assert_eq!(
+            blocks[1][58..58 + FIRST_FRAGMENT],
+            amiibo.bytes()[..FIRST_FRAGMENT]
+        );
+
+        let rest = NTAG215_SIZE - FIRST_FRAGMENT;
+        assert_eq!(blocks[2][..7], NFC_READ_SECOND);
+        assert_eq!(blocks[2][7..7 + rest], amiibo.bytes()[FIRST_FRAGMENT..]);
+
+        // reports without a request keep showing the tag as read
+        assert_eq!(blocks[3][7], NfcState::ReadDone as u8);
+        assert_eq!(blocks[3][15..22], UID);
+
+        assert_eq!(blocks[4][7], NfcState::Idle as u8);
+        assert!(blocks[4][8..22].iter().all(|&byte| byte == 0));
+        assert_eq!(mcu.take_written(), None);
+    }
+
+    #[test]
+    fn writes_over_several_requests_change_the_tag_once() {
+        let mut mcu = Mcu::new();
+        mcu.place_tag(Tag::place(SLOT, amiibo(), at(0)));
+        let blocks = replay(&mut mcu, &WRITE_TRACE, 0);
+        assert_eq!(blocks[3][7], NfcState::Writing as u8);
+        assert_eq!(blocks[4][7], NfcState::WriteDone as u8);
+
+        // the uid pages are refused like a real tag would
+        let mut expected = amiibo();
+        for page in 4..12 {
+            expected.write_page(page, [page; 4]);
+        }
+        assert_eq!(mcu.take_written(), Some((SLOT, expected)));
+        assert_eq!(mcu.take_written(), None);
+    }
+
+    #[test]
+    fn ir_fragments_follow_the_acks_and_wrap() {
+        let mut mcu = Mcu::new();
+        let blocks = replay(&mut mcu, &IR_TRACE, 0);
+        assert_eq!(blocks[1][7], McuMode::Ir as u8);
+        // the camera is not set up yet
+        assert_eq!(
+            blocks[2][..8],
+            [REPORT_STATUS, 0x00, 0x00, 0x00, 0x08, 0x00, 0x1b, 0x05]
+        );
+        assert_eq!(blocks[3][0], REPORT_IR_MODE);
+        let fragments: Vec<_> = blocks[4..10]
+            .iter()
+            .map(|block| {
+                assert_eq!(block[0], REPORT_IR_FRAGMENT);
+                block[3]
+            })
+            .collect();
+        // unacknowledged fragments are sent again
+        assert_eq!(fragments, [0, 0, 1, 2, 0, 1]);
+        assert_eq!(blocks[10][..4], [REPORT_IR_STATUS, 0x00, 0x07, 0x00]);
+    }
+
+    const SUBCOMMAND_REPORT: u8 = 0x01;
+    const MCU_REQUEST_REPORT: u8 = 0x11;
+    const SET_MCU_CONF: u8 = 0x21;
+    const SET_MCU_STATE: u8 = 0x22;
+    const GET_MCU_STATUS: u8 = 0x01;
+    const GET_NFC_DATA: u8 = 0x02;
+    const GET_IR_DATA: u8 = 0x03;
+
+    // Replays output reports the way the switch sends them, 10ms apart starting with the
+    // `first` one of a trace, and returns the mcu block each is answered with. SetMCUConf
+    // is answered by its reply, everything else by the next 0x31 report.
+    fn replay(mcu: &mut Mcu, trace: &[&[u8]], first: usize) -> Vec<McuReport> {
+        trace
+            .iter()
+            .enumerate()
+            .map(|(idx, request)| {
+                let now = at((first + idx) as u64 * 10);
+                match (request[0], request.get(OUTPUT_SUBCOMMAND).copied()) {
+                    (SUBCOMMAND_REPORT, Some(SET_MCU_CONF)) => {
+                        return mcu.configure(&args::<MCU_COMMAND_LEN>(request))
+                    }
+                    (SUBCOMMAND_REPORT, Some(SET_MCU_STATE)) => mcu.set_state(request[OUTPUT_ARGS]),
+                    (MCU_REQUEST_REPORT, Some(GET_MCU_STATUS)) => mcu.request_status(),
+                    (MCU_REQUEST_REPORT, Some(GET_NFC_DATA)) => {
+                        mcu.request_nfc(&args::<NFC_REQUEST_LEN>(request), now)
+                    }
+                    (MCU_REQUEST_REPORT, Some(GET_IR_DATA)) => {
+                        mcu.request_ir(&args::<IR_REQUEST_LEN>(request))
+                    }
+                    _ => (),
+                }
+                report(mcu, now)
+            })
+            .collect()
+    }
+
+    // Output reports as the switch sends them: report id, timer, neutral rumble, the
+    // subcommand or mcu request and its arguments, which end in a crc8 for the mcu.
+    const SCAN_TRACE: [&[u8]; 8] = [
+        // resume the mcu
+        &[
+            0x01, 0x00, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x22, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+        // nfc mode
+        &[
+            0x01, 0x01, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x21, 0x21, 0x00, 0x04,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x3e,
+        ],
+        // start polling
+        &[
+            0x11, 0x02, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x01, 0x00, 0x00,
+            0x08, 0x05, 0x01, 0x00, 0x00, 0x2c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xf7,
+        ],
+        // status
+        &[
+            0x11, 0x03, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x04, 0x00, 0x00,
+            0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x87,
+        ],
+        // read the tag
+        &[
+            0x11, 0x04, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x06, 0x00, 0x00,
+            0x08, 0x13, 0xd0, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x84,
+        ],
+        // status, answered with the second half
+        &[
+            0x11, 0x05, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x04, 0x00, 0x00,
+            0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x87,
+        ],
+        // rumble only
+        &[0x10, 0x06, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40],
+        // stop polling
+        &[
+            0x11, 0x07, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x02, 0x00, 0x00,
+            0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x50,
+        ],
+    ];
+    const WRITE_TRACE: [&[u8]; 5] = [
+        // resume the mcu
+        &[
+            0x01, 0x00, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x22, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+        // nfc mode
+        &[
+            0x01, 0x01, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x21, 0x21, 0x00, 0x04,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x3e,
+        ],
+        // start polling
+        &[
+            0x11, 0x02, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x01, 0x00, 0x00,
+            0x08, 0x05, 0x01, 0x00, 0x00, 0x2c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xf7,
+        ],
+        // first write fragment
+        &[
+            0x11, 0x03, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x08, 0x01, 0x00,
+            0x00, 0x1f, 0x07, 0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x00, 0xaa, 0xaa, 0xaa,
+            0xaa, 0x04, 0x04, 0x04, 0x04, 0x04, 0x05, 0x05, 0x05, 0x05, 0x05, 0x06, 0x06, 0x06,
+            0x06, 0x06, 0x07, 0x07, 0x07, 0x3b,
+        ],
+        // last write fragment
+        &[
+            0x11, 0x04, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x02, 0x08, 0x02, 0x00,
+            0x08, 0x16, 0x07, 0x07, 0x08, 0x08, 0x08, 0x08, 0x08, 0x09, 0x09, 0x09, 0x09, 0x09,
+            0x0a, 0x0a, 0x0a, 0x0a, 0x0a, 0x0b, 0x0b, 0x0b, 0x0b, 0x0b, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x87,
+        ],
+    ];
+    const IR_TRACE: [&[u8]; 11] = [
+        // resume the mcu
+        &[
+            0x01, 0x00, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x22, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+        // ir mode
+        &[
+            0x01, 0x01, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x21, 0x21, 0x00, 0x05,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x6a,
+        ],
+        // rumble only
+        &[0x10, 0x02, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40],
+        // ir mode 7, fragments 0 to 2
+        &[
+            0x01, 0x03, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x21, 0x23, 0x01, 0x07,
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xe1,
+        ],
+        // rumble only
+        &[0x10, 0x04, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40],
+        // rumble only
+        &[0x10, 0x05, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40],
+        // ack fragment 0
+        &[
+            0x11, 0x06, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x03, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+        // ack fragment 1
+        &[
+            0x11, 0x07, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x03, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x1c,
+        ],
+        // ack fragment 2
+        &[
+            0x11, 0x08, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x03, 0x00, 0x00, 0x00,
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x38,
+        ],
+        // ack fragment 0
+        &[
+            0x11, 0x09, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x03, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+        // ir status
+        &[
+            0x11, 0x0a, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x03, 0x02, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xb0,
+        ],
+    ];
+}
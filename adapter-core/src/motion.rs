@@ -0,0 +1,294 @@
+use crate::curve::isqrt;
+use crate::input::XboxButton;
+use crate::report::{IMU_FRAME_LEN, IMU_LEN};
+use serde::{Deserialize, Serialize};
+
+// Factory calibration we serve over SPI: no origin offset and a sensitivity coefficient of
+// 0x4000, so 1G reads as 4096. Everything generated below is clamped to ±ACCEL_LIMIT,
+// well inside the ±8G the calibration can express.
+const ACCEL_ORIGIN: i16 = 0;
+const ACCEL_COEFF: i16 = 0x4000;
+const GYRO_ORIGIN: i16 = 0;
+const GYRO_COEFF: i16 = 0x343B;
+pub const ONE_G: i32 = 4096;
+pub const ACCEL_LIMIT: i32 = 4 * ONE_G;
+pub const SENSOR_CALIBRATION_LEN: usize = 24;
+
+const SHAKE_G: i32 = 3 * ONE_G;
+// imu frames per direction change, 3 frames are sent per report 5ms apart
+const SHAKE_HALF_PERIOD: u16 = 8;
+// a tap still shakes for this many imu frames
+const SHAKE_FRAMES: u16 = 4 * SHAKE_HALF_PERIOD;
+// imu frames per stride while jogging, about 2.5 steps a second
+const JOG_PERIOD: u16 = 80;
+const JOG_G: i32 = ONE_G;
+// sin of the steepest tilt, in 1/1000ths (45°)
+const TILT_MAX: i32 = 707;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TiltStick {
+    Off,
+    // the stick tilts the controller instead of reaching the switch
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MotionConfig {
+    pub shake: Option<XboxButton>,
+    pub tilt: TiltStick,
+    // held for jogging, bounces a left joy-con the way the leg strap would
+    pub leg_strap: Option<XboxButton>,
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            shake: None,
+            tilt: TiltStick::Off,
+            leg_strap: None,
+        }
+    }
+}
+
+// what the pipeline wants the motion sensors to show
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MotionInput {
+    pub shake: bool,
+    // bounce up and down like a leg strap on a jogging player
+    pub jog: bool,
+    // stick deflection in xbox units, up and right positive
+    pub tilt: (i16, i16),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Motion {
+    input: MotionInput,
+    shake_left: u16,
+    phase: u16,
+    jog_phase: u16,
+}
+
+impl Motion {
+    pub fn set_input(&mut self, input: MotionInput) {
+        if input.shake {
+            self.shake_left = SHAKE_FRAMES
+        }
+        self.input = input;
+    }
+
+    // the three imu samples of the next 0x30 report
+    pub fn frames(&mut self) -> [u8; IMU_LEN] {
+        let mut frames = [0; IMU_LEN];
+        for frame in frames.as_chunks_mut::<IMU_FRAME_LEN>().0 {
+            *frame = self.next_frame()
+        }
+        frames
+    }
+
+    // three little endian accelerometer axes followed by three gyro axes
+    fn next_frame(&mut self) -> [u8; IMU_FRAME_LEN] {
+        // controller lying flat, face up, tipped over by the tilt stick
+        let (sx, sy) = (self.input.tilt.0 as i32, self.input.tilt.1 as i32);
+        let ax = sy * TILT_MAX / 1000 * ONE_G / i16::MAX as i32;
+        let ay = -sx * TILT_MAX / 1000 * ONE_G / i16::MAX as i32;
+        let az = isqrt((ONE_G * ONE_G - ax * ax - ay * ay) as u32) as i32;
+
+        let mut accel = [ax, ay, az];
+        if self.shake_left > 0 {
+            self.shake_left -= 1;
+            let direction = if (self.phase / SHAKE_HALF_PERIOD).is_multiple_of(2) {
+                1
+            } else {
+                -1
+            };
+            self.phase = self.phase.wrapping_add(1);
+            accel[0] += direction * SHAKE_G;
+        } else {
+            self.phase = 0;
+        }
+        if self.input.jog {
+            // a triangle wave, up for the first half of the stride and down for the rest
+            self.jog_phase = (self.jog_phase + 1) % JOG_PERIOD;
+            let half = (JOG_PERIOD / 2) as i32;
+            let distance = (self.jog_phase as i32 - half).abs();
+            accel[2] += JOG_G * (half - 2 * distance) / half;
+        } else {
+            self.jog_phase = 0;
+        }
+
+        let mut bytes = [0; IMU_FRAME_LEN];
+        for (idx, value) in accel.into_iter().enumerate() {
+            let value = value.clamp(-ACCEL_LIMIT, ACCEL_LIMIT) as i16;
+            bytes[idx * 2..idx * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+// the spi block at 0x6020, origins and coefficients for the three accelerometer and then
+// the three gyro axes
+pub fn sensor_calibration() -> [u8; SENSOR_CALIBRATION_LEN] {
+    let mut bytes = [0; SENSOR_CALIBRATION_LEN];
+    for (idx, value) in [
+        ACCEL_ORIGIN,
+        ACCEL_ORIGIN,
+        ACCEL_ORIGIN,
+        ACCEL_COEFF,
+        ACCEL_COEFF,
+        ACCEL_COEFF,
+        GYRO_ORIGIN,
+        GYRO_ORIGIN,
+        GYRO_ORIGIN,
+        GYRO_COEFF,
+        GYRO_COEFF,
+        GYRO_COEFF,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        bytes[idx * 2..idx * 2 + 2].copy_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn words(bytes: &[u8]) -> Vec<i16> {
+        bytes
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .map(|&word| i16::from_le_bytes(word))
+            .collect()
+    }
+
+    // the accelerometer axes of every frame in G, the way the switch scales them with the
+    // factory calibration it read over spi
+    fn accel_g(frames: &[u8; IMU_LEN]) -> Vec<[f64; 3]> {
+        let calibration = words(&sensor_calibration());
+        frames
+            .as_chunks::<IMU_FRAME_LEN>()
+            .0
+            .iter()
+            .map(|frame| {
+                let raw = words(frame);
+                core::array::from_fn(|axis| {
+                    let origin = calibration[axis] as f64;
+                    let coeff = calibration[3 + axis] as f64;
+                    raw[axis] as f64 * 4.0 / (coeff - origin)
+                })
+            })
+            .collect()
+    }
+
+    fn run(input: MotionInput, reports: usize) -> Vec<[u8; IMU_LEN]> {
+        let mut motion = Motion::default();
+        motion.set_input(input);
+        (0..reports).map(|_| motion.frames()).collect()
+    }
+
+    #[test]
+    fn calibration_reads_back_as_served() {
+        assert_eq!(
+            words(&sensor_calibration()),
+            [0, 0, 0, 0x4000, 0x4000, 0x4000, 0, 0, 0, 0x343B, 0x343B, 0x343B]
+        );
+    }
+
+    #[test]
+    fn resting_controller_reads_one_g_down() {
+        for frames in run(MotionInput::default(), 4) {
+            for [x, y, z] in accel_g(&frames) {
+                assert_eq!((x, y), (0.0, 0.0));
+                assert!((z - 1.0).abs() < 0.001);
+            }
+            // the gyro stays still
+            for frame in frames.as_chunks::<IMU_FRAME_LEN>().0 {
+                assert_eq!(frame[6..], [0; 6]);
+            }
+        }
+    }
+
+    proptest! {
+        // the clamp to ACCEL_LIMIT is only a safety net, no gesture should reach it, and
+        // the limit itself sits well inside the ±8G the calibration can express
+        #[test]
+        fn accel_stays_inside_the_calibration_range(
+            tilt: (i16, i16),
+            shake: bool,
+            jog: bool,
+            reports in 1..120usize,
+        ) {
+            let limit = ACCEL_LIMIT as f64 / ONE_G as f64;
+            prop_assert!(limit < 8.0);
+            let input = MotionInput { shake, jog, tilt };
+            for frames in run(input, reports) {
+                for axes in accel_g(&frames) {
+                    for g in axes {
+                        prop_assert!(g.abs() < limit, "{} G", g);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn tilting_keeps_gravity_at_one_g(tilt: (i16, i16)) {
+            let input = MotionInput { tilt, ..MotionInput::default() };
+            for frames in run(input, 2) {
+                for [x, y, z] in accel_g(&frames) {
+                    let g = (x * x + y * y + z * z).sqrt();
+                    prop_assert!((g - 1.0).abs() < 0.002, "{} G", g);
+                    // each axis tips it at most 45°, so it never ends up face down
+                    prop_assert!(z >= 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_shake_tap_swings_back_and_forth_then_stops() {
+        let mut motion = Motion::default();
+        motion.set_input(MotionInput {
+            shake: true,
+            ..MotionInput::default()
+        });
+        motion.set_input(MotionInput::default());
+        let x: Vec<f64> = (0..SHAKE_FRAMES as usize / 3 + 4)
+            .flat_map(|_| accel_g(&motion.frames()))
+            .map(|[x, _, _]| x)
+            .collect();
+        let (shaking, after) = x.split_at(SHAKE_FRAMES as usize);
+        assert!(shaking.iter().all(|x| x.abs() >= 2.0));
+        let swings = shaking
+            .windows(2)
+            .filter(|w| w[0].signum() != w[1].signum());
+        assert_eq!(
+            swings.count(),
+            (SHAKE_FRAMES / SHAKE_HALF_PERIOD) as usize - 1
+        );
+        assert!(after.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn jogging_bounces_once_per_stride() {
+        let input = MotionInput {
+            jog: true,
+            ..MotionInput::default()
+        };
+        let z: Vec<f64> = run(input, 3 * JOG_PERIOD as usize / 3)
+            .iter()
+            .flat_map(accel_g)
+            .map(|[_, _, z]| z)
+            .collect();
+        let peaks = z.windows(3).filter(|w| w[1] > w[0] && w[1] >= w[2]).count();
+        assert_eq!(peaks, 3);
+        assert!(z.iter().all(|&z| (0.0..=2.0).contains(&z)));
+    }
+}
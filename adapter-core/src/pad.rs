@@ -0,0 +1,238 @@
+use crate::gamepad::{Button, GamepadState, StickPosition};
+use crate::macros::MacroEngine;
+use crate::turbo::Turbo;
+
+// The pad state the switch sees: the input as it arrived, what macros and turbo make of
+// it and whether that changed since it was last looked at.
+#[derive(Debug)]
+pub struct Pad {
+    // the last input as it arrived, before macros and turbo
+    raw: GamepadState,
+    // what macros and turbo made of it
+    state: GamepadState,
+    pub turbo: Turbo,
+    pub macros: MacroEngine,
+    // the output last handed out by `take_change`
+    seen: GamepadState,
+}
+
+impl Pad {
+    pub fn new() -> Self {
+        Self {
+            raw: GamepadState::NEUTRAL,
+            state: GamepadState::NEUTRAL,
+            turbo: Turbo::default(),
+            macros: MacroEngine::default(),
+            seen: GamepadState::NEUTRAL,
+        }
+    }
+
+    pub fn snapshot(&self) -> GamepadState {
+        self.state
+    }
+
+    pub fn apply(&mut self, state: GamepadState) {
+        self.raw = state;
+        let mut state = self.macros.input(state);
+        state.buttons = self.turbo.input(state.buttons);
+        self.state = state;
+    }
+
+    // the setters change one part of the raw input, so combos already hidden by macros or
+    // turbo don't leak back in
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let mut state = self.raw;
+        state.buttons.set(button, pressed);
+        self.apply(state)
+    }
+
+    pub fn set_left_stick(&mut self, position: StickPosition) {
+        self.apply(GamepadState {
+            left_stick: position,
+            ..self.raw
+        })
+    }
+
+    pub fn set_right_stick(&mut self, position: StickPosition) {
+        self.apply(GamepadState {
+            right_stick: position,
+            ..self.raw
+        })
+    }
+
+    // releases every button and centers both sticks, bypassing macros and turbo so
+    // neither can press anything again
+    pub fn neutral(&mut self) {
+        self.macros.reset();
+        self.turbo.reset();
+        self.raw = GamepadState::NEUTRAL;
+        self.state = GamepadState::NEUTRAL;
+    }
+
+    // the full pad state with macro playback and turbo applied
+    pub fn output(&self) -> GamepadState {
+        let mut state = self.macros.output(self.state);
+        state.buttons = self.turbo.output(state.buttons);
+        state
+    }
+
+    // moves macros and turbo on by one report
+    pub fn advance(&mut self) {
+        self.macros.advance(self.state);
+        self.turbo.advance();
+    }
+
+    // the output if it changed since the last call
+    pub fn take_change(&mut self) -> Option<GamepadState> {
+        let output = self.output();
+        (output != self.seen).then(|| {
+            self.seen = output;
+            output
+        })
+    }
+}
+
+impl Default for Pad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamepad::Buttons;
+    use crate::macros::{MacroConfig, PlaybackMode};
+    use crate::turbo::TurboConfig;
+
+    // turns turbo on for A with Plus as the modifier, one report on and one off
+    fn turbo_on_a(pad: &mut Pad) {
+        pad.turbo.config = TurboConfig {
+            modifier: Some(Button::Plus),
+            period: 2,
+            on: 1,
+        };
+        pad.apply(pressed(&[Button::Plus]));
+        pad.apply(pressed(&[Button::Plus, Button::A]));
+        pad.apply(GamepadState::NEUTRAL);
+    }
+
+    fn pressed(buttons: &[Button]) -> GamepadState {
+        let mut state = GamepadState::NEUTRAL;
+        for &button in buttons {
+            state.buttons.press(button)
+        }
+        state
+    }
+
+    #[test]
+    fn apply_is_seen_in_the_snapshot_and_reported_once() {
+        let mut pad = Pad::new();
+        assert_eq!(pad.take_change(), None);
+        let state = GamepadState {
+            left_stick: StickPosition::new(0x100, 0xf00),
+            ..pressed(&[Button::A])
+        };
+        pad.apply(state);
+        assert_eq!(pad.snapshot(), state);
+        assert_eq!(pad.take_change(), Some(state));
+        pad.apply(state);
+        assert_eq!(pad.take_change(), None);
+    }
+
+    #[test]
+    fn setters_change_only_their_part() {
+        let mut pad = Pad::new();
+        pad.apply(pressed(&[Button::A]));
+        pad.set_button(Button::B, true);
+        pad.set_left_stick(StickPosition::new(0, 0));
+        pad.set_right_stick(StickPosition::new(0xfff, 0xfff));
+        pad.set_button(Button::A, false);
+        assert_eq!(
+            pad.snapshot(),
+            GamepadState {
+                buttons: Button::B.into(),
+                left_stick: StickPosition::new(0, 0),
+                right_stick: StickPosition::new(0xfff, 0xfff),
+            }
+        );
+    }
+
+    #[test]
+    fn setters_keep_a_consumed_combo_hidden() {
+        let mut pad = Pad::new();
+        pad.macros.config = MacroConfig {
+            record_combo: Buttons::from(Button::Minus) | Button::Capture.into(),
+            play_combo: Buttons::NONE,
+            mode: PlaybackMode::Override,
+        };
+        pad.apply(pressed(&[Button::Minus, Button::Capture]));
+        assert_eq!(pad.snapshot(), GamepadState::NEUTRAL);
+        // the combo is still held in the raw input, a stick move must not reveal it
+        pad.set_left_stick(StickPosition::new(0, 0x800));
+        assert_eq!(pad.snapshot().buttons, Buttons::NONE);
+    }
+
+    #[test]
+    fn neutral_releases_everything_and_restarts_turbo() {
+        let mut pad = Pad::new();
+        turbo_on_a(&mut pad);
+        pad.apply(pressed(&[Button::A]));
+        pad.set_right_stick(StickPosition::new(0, 0));
+        pad.advance();
+        assert_ne!(pad.take_change(), None);
+
+        pad.neutral();
+        assert_eq!(pad.snapshot(), GamepadState::NEUTRAL);
+        assert_eq!(pad.output(), GamepadState::NEUTRAL);
+        assert_eq!(pad.take_change(), Some(GamepadState::NEUTRAL));
+        // a fresh press after the release starts on an on phase
+        pad.apply(pressed(&[Button::A]));
+        assert_eq!(pad.output(), pressed(&[Button::A]));
+    }
+
+    #[test]
+    fn turbo_phases_are_changes() {
+        let mut pad = Pad::new();
+        turbo_on_a(&mut pad);
+        pad.apply(pressed(&[Button::A]));
+        pad.take_change();
+        let mut changes = 0;
+        for _ in 0..6 {
+            pad.advance();
+            changes += pad.take_change().is_some() as u32;
+        }
+        // the input never changed, but every report flips A
+        assert_eq!(changes, 6);
+    }
+
+    #[test]
+    fn recorded_macro_plays_back_over_live_input() {
+        let mut pad = Pad::new();
+        let record = Buttons::from(Button::Minus) | Button::Capture.into();
+        let play = Buttons::from(Button::Plus) | Button::Capture.into();
+        pad.macros.config = MacroConfig {
+            record_combo: record,
+            play_combo: play,
+            mode: PlaybackMode::Override,
+        };
+        pad.apply(pressed(&[Button::Minus, Button::Capture]));
+        pad.advance();
+        pad.apply(pressed(&[Button::Minus, Button::Capture, Button::A]));
+        pad.advance();
+        pad.apply(GamepadState::NEUTRAL);
+        pad.advance();
+        pad.apply(pressed(&[Button::Minus, Button::Capture]));
+        assert!(pad.macros.take_recorded());
+        pad.apply(GamepadState::NEUTRAL);
+        pad.apply(pressed(&[Button::Plus, Button::Capture]));
+        assert!(pad.macros.is_playing());
+        let mut played = Vec::new();
+        while pad.macros.is_playing() {
+            played.push(pad.output().buttons);
+            pad.advance();
+        }
+        assert!(played.contains(&Button::A.into()));
+        assert_eq!(pad.output(), pad.snapshot());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::input::{XboxButton, XboxButtons, XboxState};
+use embassy_time::{Duration, Instant};
+
+// held while tapping d-pad left or right to cycle profiles
+pub const PROFILE_MODIFIER: XboxButton = XboxButton::View;
+// how long the modifier is held back alone before it is passed on as a plain press
+const MODIFIER_HOLD_MS: u64 = 500;
+// how long a held back modifier is sent for when it was released without a combo
+const MODIFIER_TAP_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Modifier {
+    // released, or held and passed on
+    #[default]
+    Idle,
+    // held alone and hidden until it turns out what it is for
+    Pending(Instant),
+    // released before anything else was pressed, replaying the held back press
+    Tap(Instant),
+}
+
+// watches for the profile combo and hides it from the rest of the pipeline. The modifier
+// is held back until the combo resolves, so a switch never shows up as a Minus press.
+#[derive(Debug, Default)]
+pub struct ProfileSwitcher {
+    held: XboxButtons,
+    consumed: XboxButtons,
+    modifier: Modifier,
+}
+
+impl ProfileSwitcher {
+    // true while a decision depends on time passing rather than on new input
+    pub fn is_pending(&self) -> bool {
+        self.modifier != Modifier::Idle
+    }
+
+    // returns Some(forward) when the player asked for the next or previous profile
+    pub fn input(&mut self, xbox: &mut XboxState, now: Instant) -> Option<bool> {
+        let buttons = xbox.buttons;
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+        self.consumed = self.consumed & buttons;
+
+        let modifier = XboxButtons::from(PROFILE_MODIFIER);
+        let held = buttons.is_pressed(PROFILE_MODIFIER);
+        let mut step = None;
+        if held {
+            for (button, forward) in [(XboxButton::Right, true), (XboxButton::Left, false)] {
+                if pressed.is_pressed(button) {
+                    self.consumed = self.consumed | button.into() | modifier;
+                    step = Some(forward);
+                }
+            }
+        }
+
+        let others = !(pressed & !modifier).is_empty();
+        self.modifier = match self.modifier {
+            _ if step.is_some() => Modifier::Idle,
+            // pressed along with something else, that already resolves it
+            Modifier::Idle | Modifier::Tap(_) if pressed.is_pressed(PROFILE_MODIFIER) => {
+                if others {
+                    Modifier::Idle
+                } else {
+                    Modifier::Pending(now)
+                }
+            }
+            Modifier::Pending(_) if !held => {
+                Modifier::Tap(now + Duration::from_millis(MODIFIER_TAP_MS))
+            }
+            // not the combo, the modifier goes out along with the press that resolved it
+            Modifier::Pending(_) if others => Modifier::Idle,
+            Modifier::Pending(since)
+                if now.duration_since(since) >= Duration::from_millis(MODIFIER_HOLD_MS) =>
+            {
+                Modifier::Idle
+            }
+            Modifier::Tap(until) if now >= until => Modifier::Idle,
+            modifier => modifier,
+        };
+
+        let mut buttons = buttons & !self.consumed;
+        match self.modifier {
+            Modifier::Pending(_) => buttons.set(PROFILE_MODIFIER, false),
+            Modifier::Tap(_) => buttons.set(PROFILE_MODIFIER, true),
+            Modifier::Idle => (),
+        }
+        xbox.buttons = buttons;
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buttons(pressed: &[XboxButton]) -> XboxButtons {
+        let mut buttons = XboxButtons::default();
+        for &button in pressed {
+            buttons.set(button, true);
+        }
+        buttons
+    }
+
+    // feeds `held` at `ms` and returns what is left of the buttons and the step asked for
+    fn step(
+        switcher: &mut ProfileSwitcher,
+        held: &[XboxButton],
+        ms: u64,
+    ) -> (XboxButtons, Option<bool>) {
+        let mut xbox = XboxState {
+            buttons: buttons(held),
+            ..XboxState::default()
+        };
+        let step = switcher.input(&mut xbox, Instant::from_millis(ms));
+        (xbox.buttons, step)
+    }
+
+    #[test]
+    fn view_and_the_dpad_switch_without_a_minus_press() {
+        let mut switcher = ProfileSwitcher::default();
+        let none = XboxButtons::default();
+        assert_eq!(step(&mut switcher, &[XboxButton::View], 0), (none, None));
+        assert!(switcher.is_pending());
+        assert_eq!(
+            step(&mut switcher, &[XboxButton::View, XboxButton::Right], 100),
+            (none, Some(true))
+        );
+        assert_eq!(step(&mut switcher, &[XboxButton::View], 200), (none, None));
+        assert_eq!(
+            step(&mut switcher, &[XboxButton::View, XboxButton::Left], 300),
+            (none, Some(false))
+        );
+        assert_eq!(step(&mut switcher, &[], 400), (none, None));
+        assert!(!switcher.is_pending());
+    }
+
+    #[test]
+    fn a_lone_view_press_is_replayed_as_a_tap() {
+        let mut switcher = ProfileSwitcher::default();
+        let view = buttons(&[XboxButton::View]);
+        assert_eq!(
+            step(&mut switcher, &[XboxButton::View], 0).0,
+            XboxButtons::default()
+        );
+        assert_eq!(step(&mut switcher, &[], 50).0, view);
+        assert_eq!(step(&mut switcher, &[], 100).0, view);
+        assert_eq!(step(&mut switcher, &[], 150).0, XboxButtons::default());
+        assert!(!switcher.is_pending());
+    }
+
+    #[test]
+    fn view_goes_out_with_the_press_that_resolves_it() {
+        let mut switcher = ProfileSwitcher::default();
+        step(&mut switcher, &[XboxButton::View], 0);
+        // the amiibo combo shares the modifier and has to see both at once
+        let held = [XboxButton::View, XboxButton::Up];
+        assert_eq!(step(&mut switcher, &held, 100), (buttons(&held), None));
+        assert!(!switcher.is_pending());
+        // passed on, a later d-pad tap still switches
+        assert_eq!(
+            step(
+                &mut switcher,
+                &[XboxButton::View, XboxButton::Up, XboxButton::Left],
+                200
+            ),
+            (buttons(&[XboxButton::Up]), Some(false))
+        );
+    }
+
+    #[test]
+    fn a_long_view_hold_is_passed_on() {
+        let mut switcher = ProfileSwitcher::default();
+        let view = buttons(&[XboxButton::View]);
+        step(&mut switcher, &[XboxButton::View], 0);
+        assert_eq!(
+            step(&mut switcher, &[XboxButton::View], MODIFIER_HOLD_MS - 1).0,
+            XboxButtons::default()
+        );
+        assert_eq!(
+            step(&mut switcher, &[XboxButton::View], MODIFIER_HOLD_MS).0,
+            view
+        );
+        assert_eq!(
+            step(&mut switcher, &[], MODIFIER_HOLD_MS + 10).0,
+            XboxButtons::default()
+        );
+    }
+}
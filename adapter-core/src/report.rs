@@ -0,0 +1,222 @@
+use crate::gamepad::GamepadState;
+use crate::mcu::MCU_REPORT_LEN;
+
+// how often the pad state goes out to the switch
+pub const REPORT_INTERVAL_MS: u64 = 8;
+
+// Byte offsets into the input reports the switch reads. 0x21, 0x30 and 0x31 all start
+// with the timer, the status byte and the pad state.
+pub const TIMER: usize = 1;
+pub const STATUS: usize = 2;
+pub const BUTTONS: usize = 3;
+pub const LEFT_STICK: usize = 6;
+pub const RIGHT_STICK: usize = 9;
+pub const VIBRATOR: usize = 12;
+
+// 0x30 and 0x31 go on with three imu frames of three accelerometer and three gyro axes
+pub const IMU: usize = 13;
+pub const IMU_FRAME_LEN: usize = 12;
+pub const IMU_LEN: usize = 3 * IMU_FRAME_LEN;
+// and 0x31 with the mcu block after them
+pub const MCU: usize = IMU + IMU_LEN;
+
+// every other report fits a 64 byte usb packet, 0x31 is the longest input report
+pub const REPORT_LEN: usize = 64;
+pub const MCU_INPUT_LEN: usize = MCU + MCU_REPORT_LEN;
+
+// 0x21 goes on with the reply to a subcommand
+pub const ACK: usize = 13;
+pub const SUBCOMMAND: usize = 14;
+pub const REPLY_DATA: usize = 15;
+pub const REPLY_DATA_LEN: usize = 35;
+
+// output reports from the switch are [id, counter, rumble (8), subcommand, args..]
+pub const OUTPUT_SUBCOMMAND: usize = 10;
+pub const OUTPUT_ARGS: usize = 11;
+
+// writes the buttons and both sticks into an input report
+pub fn write_input(report: &mut [u8], state: &GamepadState) {
+    report[BUTTONS..BUTTONS + 3].copy_from_slice(&state.buttons.bytes());
+    report[LEFT_STICK..LEFT_STICK + 3].copy_from_slice(&state.left_stick.bytes());
+    report[RIGHT_STICK..RIGHT_STICK + 3].copy_from_slice(&state.right_stick.bytes());
+}
+
+pub fn write_imu(report: &mut [u8], frames: &[u8; IMU_LEN]) {
+    report[IMU..IMU + IMU_LEN].copy_from_slice(frames);
+}
+
+// the first N argument bytes of an output report, zero filled past its end
+pub fn args<const N: usize>(request: &[u8]) -> [u8; N] {
+    let mut args = [0; N];
+    let request = request.get(OUTPUT_ARGS..).unwrap_or_default();
+    let len = request.len().min(N);
+    args[..len].copy_from_slice(&request[..len]);
+    args
+}
+
+// the controller kind byte the switch reads from device info and the usb status reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ControllerKind {
+    JoyConLeft = 0x01,
+    JoyConRight = 0x02,
+    ProController = 0x03,
+    Snes = 0x0b,
+    N64 = 0x0c,
+    Genesis = 0x0d,
+}
+
+// the spi byte at 0x601B telling the switch which of the spi colors to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SpiColors {
+    WithoutGrip = 0x01,
+    IncludingGrip = 0x02,
+}
+
+pub const DEVICE_INFO_LEN: usize = 12;
+
+// the reply to a device info request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceInfo {
+    pub firmware: [u8; 2],
+    pub kind: ControllerKind,
+    pub mac: [u8; 6],
+    pub colors: SpiColors,
+}
+
+impl DeviceInfo {
+    // firmware version, controller kind, a fixed 0x02, the mac address, a fixed 0x01
+    // and which spi colors to use
+    pub fn bytes(&self) -> [u8; DEVICE_INFO_LEN] {
+        let mut info = [0; DEVICE_INFO_LEN];
+        info[0..2].copy_from_slice(&self.firmware);
+        info[2] = self.kind as u8;
+        info[3] = 0x02;
+        info[4..10].copy_from_slice(&self.mac);
+        info[10] = 0x01;
+        info[11] = self.colors as u8;
+        info
+    }
+
+    // A charging grip answers for both joy-cons it holds, the left one first, each under
+    // its own mac. The kind is taken from the side, not from `self`.
+    pub fn pair(&self) -> [u8; 2 * DEVICE_INFO_LEN] {
+        let mut pair = [0; 2 * DEVICE_INFO_LEN];
+        for (idx, kind) in [ControllerKind::JoyConLeft, ControllerKind::JoyConRight]
+            .into_iter()
+            .enumerate()
+        {
+            let mut info = DeviceInfo { kind, ..*self };
+            info.mac[5] = info.mac[5].wrapping_add(idx as u8);
+            pair[idx * DEVICE_INFO_LEN..(idx + 1) * DEVICE_INFO_LEN].copy_from_slice(&info.bytes());
+        }
+        pair
+    }
+}
+
+// a subcommand reply written straight into the bytes of a 0x21 report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RawReply {
+    pub ack: u8,
+    pub id: u8,
+    pub data: [u8; REPLY_DATA_LEN],
+}
+
+impl RawReply {
+    pub fn new(ack: u8, id: u8, data: &[u8]) -> Self {
+        let mut reply = Self {
+            ack,
+            id,
+            data: [0; REPLY_DATA_LEN],
+        };
+        reply.data[..data.len()].copy_from_slice(data);
+        reply
+    }
+
+    // overwrites the ack, subcommand id and data of a 0x21 input report
+    pub fn patch(&self, report: &mut [u8]) {
+        report[ACK] = self.ack;
+        report[SUBCOMMAND] = self.id;
+        let len = REPLY_DATA_LEN.min(report.len() - REPLY_DATA);
+        report[REPLY_DATA..REPLY_DATA + len].copy_from_slice(&self.data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamepad::{Button, StickPosition};
+
+    #[test]
+    fn input_lands_on_the_pad_state_bytes() {
+        let mut state = GamepadState::NEUTRAL;
+        state.buttons.press(Button::A);
+        state.buttons.press(Button::ZL);
+        state.left_stick = StickPosition::new(0x123, 0x456);
+        let mut report = [0xee; 64];
+        write_input(&mut report, &state);
+        assert_eq!(report[..3], [0xee; 3]);
+        assert_eq!(report[BUTTONS..LEFT_STICK], [0x08, 0x00, 0x80]);
+        assert_eq!(report[LEFT_STICK..RIGHT_STICK], [0x23, 0x61, 0x45]);
+        assert_eq!(report[RIGHT_STICK..VIBRATOR], [0x00, 0x08, 0x80]);
+        assert_eq!(report[VIBRATOR], 0xee);
+    }
+
+    #[test]
+    fn args_are_zero_filled() {
+        let mut request = [0; 13];
+        request[OUTPUT_SUBCOMMAND] = 0x22;
+        request[OUTPUT_ARGS] = 0x01;
+        request[OUTPUT_ARGS + 1] = 0x02;
+        assert_eq!(args::<4>(&request), [0x01, 0x02, 0, 0]);
+        assert_eq!(args::<2>(&request[..5]), [0, 0]);
+    }
+
+    #[test]
+    fn device_info_layout() {
+        let info = DeviceInfo {
+            firmware: [0x03, 0x48],
+            kind: ControllerKind::JoyConRight,
+            mac: [1, 2, 3, 4, 5, 6],
+            colors: SpiColors::WithoutGrip,
+        };
+        assert_eq!(
+            info.bytes(),
+            [0x03, 0x48, 0x02, 0x02, 1, 2, 3, 4, 5, 6, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn a_grip_reports_both_joy_cons() {
+        let info = DeviceInfo {
+            firmware: [0x03, 0x48],
+            kind: ControllerKind::ProController,
+            mac: [1, 2, 3, 4, 5, 6],
+            colors: SpiColors::WithoutGrip,
+        };
+        let pair = info.pair();
+        assert_eq!(
+            pair[..DEVICE_INFO_LEN],
+            [0x03, 0x48, 0x01, 0x02, 1, 2, 3, 4, 5, 6, 0x01, 0x01]
+        );
+        assert_eq!(
+            pair[DEVICE_INFO_LEN..],
+            [0x03, 0x48, 0x02, 0x02, 1, 2, 3, 4, 5, 7, 0x01, 0x01]
+        );
+        // and the pair still fits a subcommand reply
+        assert!(pair.len() <= REPLY_DATA_LEN);
+    }
+
+    #[test]
+    fn reply_is_cut_to_the_report() {
+        let reply = RawReply::new(0x90, 0x10, &[1, 2, 3]);
+        let mut report = [0; 17];
+        reply.patch(&mut report);
+        assert_eq!(report[ACK..], [0x90, 0x10, 1, 2]);
+    }
+}
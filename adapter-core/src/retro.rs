@@ -0,0 +1,227 @@
+use crate::gamepad::{Button, Buttons, GamepadState, StickPosition};
+use crate::report::ControllerKind;
+use serde::{Deserialize, Serialize};
+
+// Nintendo Switch Online controllers. The retro apps label buttons after them and take
+// the N64 C-buttons from joy-con buttons, see `Mapping::N64`. Ids and kinds are the ones
+// the linux hid-nintendo driver knows them by.
+pub const SNES_PRODUCT_ID: u16 = 0x2017;
+pub const N64_PRODUCT_ID: u16 = 0x2019;
+pub const GENESIS_PRODUCT_ID: u16 = 0x201e;
+
+const DPAD: [Button; 4] = [Button::Up, Button::Down, Button::Left, Button::Right];
+
+// select on minus and start on plus
+const SNES_BUTTONS: [Button; 10] = [
+    Button::A,
+    Button::B,
+    Button::X,
+    Button::Y,
+    Button::L,
+    Button::R,
+    Button::ZL,
+    Button::ZR,
+    Button::Minus,
+    Button::Plus,
+];
+
+// A, B, L, R, Z on ZL, start on plus, C up, down, left and right on Y, ZR, X and
+// minus, and the extra ZR shoulder on the left stick click
+const N64_BUTTONS: [Button; 13] = [
+    Button::A,
+    Button::B,
+    Button::L,
+    Button::R,
+    Button::ZL,
+    Button::Plus,
+    Button::Y,
+    Button::ZR,
+    Button::X,
+    Button::Minus,
+    Button::LStick,
+    Button::Home,
+    Button::Capture,
+];
+
+// A, B, C on R, X, Y, Z on L, mode on ZR and start on plus
+const GENESIS_BUTTONS: [Button; 10] = [
+    Button::A,
+    Button::B,
+    Button::R,
+    Button::X,
+    Button::Y,
+    Button::L,
+    Button::ZR,
+    Button::Plus,
+    Button::Home,
+    Button::Capture,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RetroController {
+    Snes,
+    // reports its C-buttons on Y, ZR, X and minus, see `Mapping::N64`
+    N64,
+    Genesis,
+}
+
+impl RetroController {
+    pub fn product_id(self) -> u16 {
+        match self {
+            RetroController::Snes => SNES_PRODUCT_ID,
+            RetroController::N64 => N64_PRODUCT_ID,
+            RetroController::Genesis => GENESIS_PRODUCT_ID,
+        }
+    }
+
+    pub fn product_name(self) -> &'static str {
+        match self {
+            RetroController::Snes => "SNES Controller",
+            RetroController::N64 => "N64 Controller",
+            RetroController::Genesis => "MD/Gen Control Pad",
+        }
+    }
+
+    pub fn kind(self) -> ControllerKind {
+        match self {
+            RetroController::Snes => ControllerKind::Snes,
+            RetroController::N64 => ControllerKind::N64,
+            RetroController::Genesis => ControllerKind::Genesis,
+        }
+    }
+
+    // keeps the buttons the controller has, the d-pad goes out on the usual bits and
+    // only the n64 has a stick
+    pub fn layout(self, state: GamepadState) -> GamepadState {
+        let (list, left_stick): (&[Button], _) = match self {
+            RetroController::Snes => (&SNES_BUTTONS, StickPosition::CENTER),
+            RetroController::N64 => (&N64_BUTTONS, state.left_stick),
+            RetroController::Genesis => (&GENESIS_BUTTONS, StickPosition::CENTER),
+        };
+        GamepadState {
+            buttons: state.buttons & (buttons(list) | buttons(&DPAD)),
+            left_stick,
+            right_stick: StickPosition::CENTER,
+        }
+    }
+}
+
+fn buttons(list: &[Button]) -> Buttons {
+    list.iter()
+        .fold(Buttons::NONE, |buttons, button| buttons | (*button).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{XboxButton, XboxState};
+    use crate::mapping::Mapping;
+    use crate::report::{DeviceInfo, SpiColors};
+
+    const ALL: [RetroController; 3] = [
+        RetroController::Snes,
+        RetroController::N64,
+        RetroController::Genesis,
+    ];
+
+    fn n64(xbox: &XboxState, triggers: (bool, bool)) -> GamepadState {
+        RetroController::N64.layout(Mapping::N64.apply(xbox, triggers))
+    }
+
+    #[test]
+    fn ids_and_kinds_match_hid_nintendo() {
+        let ids: Vec<_> = ALL
+            .iter()
+            .map(|retro| (retro.product_id(), retro.kind() as u8))
+            .collect();
+        assert_eq!(ids, [(0x2017, 0x0b), (0x2019, 0x0c), (0x201e, 0x0d)]);
+    }
+
+    #[test]
+    fn device_info_names_the_retro_controller() {
+        for (retro, kind) in ALL.into_iter().zip([0x0b, 0x0c, 0x0d]) {
+            let info = DeviceInfo {
+                firmware: [0x03, 0x48],
+                kind: retro.kind(),
+                mac: [1, 2, 3, 4, 5, 6],
+                colors: SpiColors::WithoutGrip,
+            };
+            assert_eq!(
+                info.bytes(),
+                [0x03, 0x48, kind, 0x02, 1, 2, 3, 4, 5, 6, 0x01, 0x01]
+            );
+        }
+    }
+
+    #[test]
+    fn the_right_stick_presses_the_c_buttons() {
+        let cases = [
+            ((0, i16::MAX), Button::Y),
+            ((0, i16::MIN), Button::ZR),
+            ((i16::MIN, 0), Button::X),
+            ((i16::MAX, 0), Button::Minus),
+        ];
+        for ((right_x, right_y), c_button) in cases {
+            let xbox = XboxState {
+                right_x,
+                right_y,
+                ..XboxState::default()
+            };
+            let state = n64(&xbox, (false, false));
+            assert_eq!(state.buttons, c_button.into(), "{right_x} {right_y}");
+            assert_eq!(state.right_stick, StickPosition::CENTER);
+        }
+        // a small push is not a press
+        let xbox = XboxState {
+            right_y: i16::MAX / 4,
+            ..XboxState::default()
+        };
+        assert_eq!(n64(&xbox, (false, false)).buttons, Buttons::NONE);
+    }
+
+    #[test]
+    fn n64_face_buttons_and_shoulders() {
+        let mut xbox = XboxState::default();
+        for button in [XboxButton::A, XboxButton::X, XboxButton::B, XboxButton::Y] {
+            xbox.buttons.set(button, true);
+        }
+        // A and B are the big buttons, xbox B and Y are C-down and C-left, the triggers
+        // are Z and the extra ZR shoulder
+        let expected = [
+            Button::A,
+            Button::B,
+            Button::ZR,
+            Button::X,
+            Button::ZL,
+            Button::LStick,
+        ];
+        assert_eq!(n64(&xbox, (true, true)).buttons, buttons(&expected));
+    }
+
+    #[test]
+    fn layouts_keep_only_the_buttons_the_controller_has() {
+        let mut state = GamepadState {
+            left_stick: StickPosition::new(0x100, 0xf00),
+            right_stick: StickPosition::new(0xf00, 0x100),
+            ..GamepadState::NEUTRAL
+        };
+        for button in Button::ALL {
+            state.buttons.press(button)
+        }
+        let snes = RetroController::Snes.layout(state);
+        assert_eq!(snes.buttons, buttons(&SNES_BUTTONS) | buttons(&DPAD));
+        assert_eq!(
+            (snes.left_stick, snes.right_stick),
+            (StickPosition::CENTER, StickPosition::CENTER)
+        );
+        let n64 = RetroController::N64.layout(state);
+        assert!(!n64.buttons.is_pressed(Button::RStick));
+        assert_eq!(n64.left_stick, state.left_stick);
+        assert_eq!(n64.right_stick, StickPosition::CENTER);
+        let genesis = RetroController::Genesis.layout(state);
+        assert!(genesis.buttons.is_pressed(Button::ZR));
+        assert!(!genesis.buttons.is_pressed(Button::ZL));
+        assert_eq!(genesis.left_stick, StickPosition::CENTER);
+    }
+}
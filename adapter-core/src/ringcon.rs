@@ -0,0 +1,326 @@
+use crate::input::XboxState;
+use crate::mcu::{self, McuReport, MCU_REPORT_LEN};
+use crate::report::{RawReply, REPLY_DATA_LEN};
+use serde::{Deserialize, Serialize};
+
+// subcommands for devices on the joy-con rail
+pub const ACCESSORY: u8 = 0x58;
+pub const EXT_DEVICE_INFO: u8 = 0x59;
+pub const EXT_POLLING_ON: u8 = 0x5a;
+pub const EXT_POLLING_OFF: u8 = 0x5b;
+pub const EXT_CONFIGURE: u8 = 0x5c;
+pub const ACCESSORY_COMMAND_LEN: usize = 8;
+
+// id a Ring-Con reports through EXT_DEVICE_INFO
+const RINGCON_ID: u8 = 0x20;
+// acks for replies without and with data
+const ACK: u8 = 0x80;
+const ACK_DATA: u8 = 0xa0;
+
+// ACCESSORY commands are [operation, device, item, length, ..]
+const READ: u8 = 0x04;
+const RESET: u8 = 0x14;
+const WRITE: u8 = 0x20;
+const ITEM_CALIBRATION: u8 = 0x1a;
+const ITEM_OFFLINE_STEPS: u8 = 0x31;
+
+// strain gauge readings of a ring pulled apart, at rest and squeezed together
+const FLEX_PULLED: u8 = 0x00;
+const FLEX_NEUTRAL: u8 = 0x0a;
+const FLEX_SQUEEZED: u8 = 0x14;
+// While polling, the gauge goes out in the mcu block of 0x31 reports, as an ext device
+// block of [EXT_DEVICE_DATA, device id, flex] ending in the usual crc8. The imu frames
+// keep the real motion.
+const EXT_DEVICE_DATA: u8 = 0x0e;
+
+// only takes effect when posing as a right joy-con or the grip, the ring holds a right one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RingConConfig {
+    pub enabled: bool,
+}
+
+// how hard the ring is pulled apart and squeezed, 0..=XboxState::TRIGGER_MAX
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RingConInput {
+    pub pull: u16,
+    pub squeeze: u16,
+}
+
+impl RingConInput {
+    // LT pulls and RT squeezes, both triggers then read released
+    pub fn from_triggers(xbox: &mut XboxState) -> Self {
+        Self {
+            pull: core::mem::take(&mut xbox.left_trigger),
+            squeeze: core::mem::take(&mut xbox.right_trigger),
+        }
+    }
+}
+
+// A Ring-Con on the rail, answering the accessory subcommands the way a real one on a
+// right joy-con does. Everything here is plain bytes so exchanges can be replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingCon {
+    pub attached: bool,
+    polling: bool,
+    flex: u8,
+}
+
+impl RingCon {
+    pub fn new() -> Self {
+        Self {
+            attached: false,
+            polling: false,
+            flex: FLEX_NEUTRAL,
+        }
+    }
+
+    pub fn input(&mut self, input: RingConInput) {
+        let max = XboxState::TRIGGER_MAX as u32;
+        let pull = input.pull.min(XboxState::TRIGGER_MAX) as u32;
+        let squeeze = input.squeeze.min(XboxState::TRIGGER_MAX) as u32;
+        let flex = FLEX_NEUTRAL as u32 + squeeze * (FLEX_SQUEEZED - FLEX_NEUTRAL) as u32 / max;
+        self.flex = (flex - pull * (FLEX_NEUTRAL - FLEX_PULLED) as u32 / max) as u8;
+    }
+
+    // None leaves the subcommand to the default handling, as if nothing was attached
+    pub fn subcommand(&mut self, id: u8, args: &[u8]) -> Option<RawReply> {
+        if !self.attached {
+            return None;
+        }
+        Some(match id {
+            EXT_DEVICE_INFO => RawReply::new(ACK_DATA, id, &[0x00, RINGCON_ID]),
+            EXT_POLLING_ON | EXT_POLLING_OFF => {
+                self.polling = id == EXT_POLLING_ON;
+                info!("ring-con polling {}", self.polling);
+                RawReply::new(ACK, id, &[])
+            }
+            EXT_CONFIGURE => RawReply::new(ACK, id, &[]),
+            ACCESSORY => RawReply::new(ACK_DATA, id, &self.accessory(args)),
+            _ => return None,
+        })
+    }
+
+    // the mcu block carrying the ring's strain gauge, while the switch polls it
+    pub fn report(&self) -> Option<McuReport> {
+        if !(self.attached && self.polling) {
+            return None;
+        }
+        let mut report = [0; MCU_REPORT_LEN];
+        report[..3].copy_from_slice(&[EXT_DEVICE_DATA, RINGCON_ID, self.flex]);
+        Some(mcu::finish(report))
+    }
+
+    // replies are [status, length, data..]
+    fn accessory(&self, command: &[u8]) -> [u8; REPLY_DATA_LEN] {
+        let mut reply = [0; REPLY_DATA_LEN];
+        match *command {
+            [READ, _, ITEM_CALIBRATION, ..] => {
+                reply[1] = 6;
+                for (idx, value) in [FLEX_NEUTRAL, FLEX_PULLED, FLEX_SQUEEZED]
+                    .iter()
+                    .enumerate()
+                {
+                    reply[2 + idx * 2] = *value;
+                }
+            }
+            // no steps were counted away from the switch
+            [READ, _, ITEM_OFFLINE_STEPS, ..] => reply[1] = 2,
+            [READ | RESET | WRITE, ..] => (),
+            _ => warn!("unhandled ring-con command {:x}", command),
+        }
+        reply
+    }
+}
+
+impl Default for RingCon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{args, ACK as ACK_BYTE, OUTPUT_SUBCOMMAND, REPORT_LEN};
+
+    const MAX: u16 = XboxState::TRIGGER_MAX;
+
+    fn attached() -> RingCon {
+        RingCon {
+            attached: true,
+            ..RingCon::new()
+        }
+    }
+
+    // Replays output reports the way the switch sends them and returns the 0x21 report
+    // each is answered with, if the ring answers, and the mcu block sent right after.
+    fn replay(
+        ringcon: &mut RingCon,
+        trace: &[(&[u8], &[u8])],
+    ) -> Vec<(Option<[u8; REPORT_LEN]>, Option<McuReport>)> {
+        trace
+            .iter()
+            .map(|(request, _)| {
+                let args = args::<ACCESSORY_COMMAND_LEN>(request);
+                let reply = ringcon
+                    .subcommand(request[OUTPUT_SUBCOMMAND], &args)
+                    .map(|reply| {
+                        let mut report = [0; REPORT_LEN];
+                        reply.patch(&mut report);
+                        report
+                    });
+                (reply, ringcon.report())
+            })
+            .collect()
+    }
+
+    // a ring through the first part of the setup, up to polling
+    fn polling() -> RingCon {
+        let mut ringcon = attached();
+        replay(&mut ringcon, &SETUP_TRACE[..3]);
+        ringcon
+    }
+
+    fn flex(ringcon: &RingCon) -> u8 {
+        let block = ringcon.report().unwrap();
+        assert_eq!(block[..2], [EXT_DEVICE_DATA, RINGCON_ID]);
+        assert_eq!(block, mcu::finish(block));
+        block[2]
+    }
+
+    #[test]
+    fn nothing_answers_without_a_ring_on_the_rail() {
+        let mut ringcon = RingCon::new();
+        for (reply, block) in replay(&mut ringcon, &SETUP_TRACE) {
+            assert_eq!((reply, block), (None, None));
+        }
+    }
+
+    #[test]
+    fn the_setup_is_answered_like_a_ring() {
+        let mut ringcon = attached();
+        let answers = replay(&mut ringcon, &SETUP_TRACE);
+        for (idx, ((reply, _), (_, expected))) in answers.iter().zip(SETUP_TRACE).enumerate() {
+            let reply = reply.unwrap();
+            assert_eq!(
+                reply[ACK_BYTE..ACK_BYTE + expected.len()],
+                *expected,
+                "exchange {idx}"
+            );
+        }
+        // the gauge goes out from the start of polling until it stops
+        let polled: Vec<_> = answers.iter().map(|(_, block)| block.is_some()).collect();
+        assert_eq!(polled, [false, false, true, true, true, true, false]);
+    }
+
+    #[test]
+    fn triggers_bend_the_ring_between_its_calibration_points() {
+        let mut ringcon = polling();
+        let cases = [
+            (0, 0, FLEX_NEUTRAL),
+            (MAX, 0, FLEX_PULLED),
+            (0, MAX, FLEX_SQUEEZED),
+            (MAX / 2, 0, FLEX_NEUTRAL - 4),
+            (0, MAX / 2, FLEX_NEUTRAL + 4),
+            // out of range triggers are clamped
+            (u16::MAX, 0, FLEX_PULLED),
+            (0, u16::MAX, FLEX_SQUEEZED),
+        ];
+        for (pull, squeeze, expected) in cases {
+            ringcon.input(RingConInput { pull, squeeze });
+            assert_eq!(flex(&ringcon), expected, "pull {pull} squeeze {squeeze}");
+        }
+    }
+
+    #[test]
+    fn triggers_become_the_ring_and_read_released() {
+        let mut xbox = XboxState {
+            left_trigger: 100,
+            right_trigger: 200,
+            ..XboxState::default()
+        };
+        let input = RingConInput::from_triggers(&mut xbox);
+        assert_eq!((input.pull, input.squeeze), (100, 200));
+        assert_eq!((xbox.left_trigger, xbox.right_trigger), (0, 0));
+    }
+
+    // Output reports as Ring Fit Adventure sends them while setting up the ring, each
+    // with the start of the 0x21 reply it gets: ack, subcommand and data. The ring is
+    // configured with the bytes the game sends, which the ring only acknowledges.
+    const SETUP_TRACE: [(&[u8], &[u8]); 7] = [
+        // which device is on the rail
+        (
+            &[
+                0x01, 0x00, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x59, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            &[0xa0, 0x59, 0x00, 0x20, 0x00, 0x00],
+        ),
+        // configure the ring
+        (
+            &[
+                0x01, 0x01, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x5c, 0x06, 0x03, 0x25,
+                0x06, 0x00, 0x00, 0x00, 0x00, 0x1c, 0x16, 0xed, 0x34, 0x36, 0x00, 0x00, 0x00, 0x0a,
+                0x64, 0x0b, 0xe6, 0xa9, 0x22, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x90, 0xa8, 0xe1, 0x34, 0x36,
+            ],
+            &[0x80, 0x5c, 0x00, 0x00],
+        ),
+        // start polling
+        (
+            &[
+                0x01, 0x02, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x5a, 0x04, 0x01, 0x01,
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            &[0x80, 0x5a, 0x00, 0x00],
+        ),
+        // read the calibration
+        (
+            &[
+                0x01, 0x03, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x58, 0x04, 0x04, 0x1a,
+                0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            &[
+                0xa0, 0x58, 0x00, 0x06, 0x0a, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00,
+            ],
+        ),
+        // read the steps taken offline
+        (
+            &[
+                0x01, 0x04, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x58, 0x04, 0x04, 0x31,
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            &[0xa0, 0x58, 0x00, 0x02, 0x00, 0x00, 0x00],
+        ),
+        // reset the step count
+        (
+            &[
+                0x01, 0x05, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x58, 0x14, 0x04, 0x31,
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            &[0xa0, 0x58, 0x00, 0x00, 0x00],
+        ),
+        // stop polling
+        (
+            &[
+                0x01, 0x06, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x5b, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            &[0x80, 0x5b, 0x00, 0x00],
+        ),
+    ];
+}
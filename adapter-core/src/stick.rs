@@ -0,0 +1,173 @@
+use crate::gamepad::{pack_12bit, StickPosition};
+
+// The factory calibration we serve over SPI. The switch maps `center - below` to full
+// negative travel and `center + above` to full positive travel, so every conversion
+// below is anchored to these exact values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxisCalibration {
+    pub center: u16,
+    pub below: u16,
+    pub above: u16,
+}
+
+impl AxisCalibration {
+    pub const fn min(&self) -> u16 {
+        self.center - self.below
+    }
+
+    pub const fn max(&self) -> u16 {
+        self.center + self.above
+    }
+
+    // maps a signed 16 bit axis onto the calibrated 12 bit range
+    pub fn from_i16(&self, value: i16) -> u16 {
+        let value = value as i32;
+        let offset = if value >= 0 {
+            value * self.above as i32 / i16::MAX as i32
+        } else {
+            value * self.below as i32 / -(i16::MIN as i32)
+        };
+        (self.center as i32 + offset) as u16
+    }
+
+    // the inverse of `from_i16`, clamping anything outside the calibrated range. Rounds
+    // away from the center so `from_i16` gives back the exact position.
+    pub fn to_i16(&self, value: u16) -> i16 {
+        let value = value.clamp(self.min(), self.max()) as i32 - self.center as i32;
+        let (above, below) = (self.above as i32, self.below as i32);
+        if value >= 0 {
+            ((value * i16::MAX as i32 + above - 1) / above) as i16
+        } else {
+            (-((-value * -(i16::MIN as i32) + below - 1) / below)) as i16
+        }
+    }
+}
+
+pub const AXIS_CALIBRATION: AxisCalibration = AxisCalibration {
+    center: 0x800,
+    below: 0x7F0,
+    above: 0x7F0,
+};
+
+// Xbox axes are signed 16 bit with up and right positive, which matches the switch
+pub fn from_xbox(x: i16, y: i16) -> StickPosition {
+    StickPosition::new(AXIS_CALIBRATION.from_i16(x), AXIS_CALIBRATION.from_i16(y))
+}
+
+pub fn to_xbox(position: StickPosition) -> (i16, i16) {
+    (
+        AXIS_CALIBRATION.to_i16(position.x),
+        AXIS_CALIBRATION.to_i16(position.y),
+    )
+}
+
+pub const STICKS_CALIBRATION_LEN: usize = 18;
+
+// the spi block at 0x603D
+pub fn factory_calibration() -> [u8; STICKS_CALIBRATION_LEN] {
+    let cal = AXIS_CALIBRATION;
+    let center = pack_12bit(cal.center, cal.center);
+    let below = pack_12bit(cal.below, cal.below);
+    let above = pack_12bit(cal.above, cal.above);
+
+    // the left stick stores (above, center, below), the right stick (center, below, above)
+    let mut bytes = [0; STICKS_CALIBRATION_LEN];
+    bytes[0..3].copy_from_slice(&above);
+    bytes[3..6].copy_from_slice(&center);
+    bytes[6..9].copy_from_slice(&below);
+    bytes[9..12].copy_from_slice(&center);
+    bytes[12..15].copy_from_slice(&below);
+    bytes[15..18].copy_from_slice(&above);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAL: AxisCalibration = AXIS_CALIBRATION;
+
+    // one 12 bit step in xbox units, the most a round trip may be off by
+    fn step() -> i32 {
+        i16::MAX as i32 / CAL.above.min(CAL.below) as i32 + 1
+    }
+
+    fn unpack_12bit(bytes: &[u8]) -> (u16, u16) {
+        (
+            bytes[0] as u16 | (bytes[1] as u16 & 0x0F) << 8,
+            (bytes[1] >> 4) as u16 | (bytes[2] as u16) << 4,
+        )
+    }
+
+    #[test]
+    fn extremes_land_on_the_calibrated_range() {
+        assert_eq!(CAL.from_i16(0), CAL.center);
+        assert_eq!(CAL.from_i16(i16::MAX), CAL.max());
+        assert_eq!(CAL.from_i16(i16::MIN), CAL.min());
+        assert_eq!(CAL.to_i16(CAL.center), 0);
+        assert_eq!(CAL.to_i16(CAL.max()), i16::MAX);
+        assert_eq!(CAL.to_i16(CAL.min()), i16::MIN);
+    }
+
+    #[test]
+    fn every_xbox_value_round_trips_within_a_step() {
+        for value in i16::MIN..=i16::MAX {
+            let back = CAL.to_i16(CAL.from_i16(value));
+            assert!(
+                (back as i32 - value as i32).abs() <= step(),
+                "{value} came back as {back}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_calibrated_position_round_trips_exactly() {
+        for raw in CAL.min()..=CAL.max() {
+            assert_eq!(CAL.from_i16(CAL.to_i16(raw)), raw, "{raw:#x}");
+        }
+    }
+
+    #[test]
+    fn conversion_is_monotonic() {
+        let mut last = 0;
+        for value in i16::MIN..=i16::MAX {
+            let raw = CAL.from_i16(value);
+            assert!(raw >= last);
+            last = raw;
+        }
+    }
+
+    #[test]
+    fn positions_outside_the_calibration_clamp() {
+        assert_eq!(CAL.to_i16(0), i16::MIN);
+        assert_eq!(CAL.to_i16(StickPosition::MAX), i16::MAX);
+    }
+
+    #[test]
+    fn sticks_round_trip_through_the_wire_format() {
+        for (x, y) in [(0, 0), (i16::MIN, i16::MAX), (1234, -5678), (-1, 1)] {
+            let position = from_xbox(x, y);
+            assert_eq!(unpack_12bit(&position.bytes()), (position.x, position.y));
+            let (bx, by) = to_xbox(position);
+            assert!((bx as i32 - x as i32).abs() <= step());
+            assert!((by as i32 - y as i32).abs() <= step());
+        }
+    }
+
+    #[test]
+    fn factory_calibration_matches_the_conversion() {
+        let bytes = factory_calibration();
+        let (center, below, above) = (
+            (CAL.center, CAL.center),
+            (CAL.below, CAL.below),
+            (CAL.above, CAL.above),
+        );
+        // left stick (above, center, below), right stick (center, below, above)
+        assert_eq!(unpack_12bit(&bytes[0..3]), above);
+        assert_eq!(unpack_12bit(&bytes[3..6]), center);
+        assert_eq!(unpack_12bit(&bytes[6..9]), below);
+        assert_eq!(unpack_12bit(&bytes[9..12]), center);
+        assert_eq!(unpack_12bit(&bytes[12..15]), below);
+        assert_eq!(unpack_12bit(&bytes[15..18]), above);
+    }
+}
@@ -0,0 +1,375 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+pub const SECTOR_SIZE: u32 = 4096;
+
+// Settings live in the last 512K of flash, clear of the program and of the cyw43
+// firmware blobs flashed at 0x10100000 (see the justfile). memory.x keeps the linker
+// out of this area.
+pub const STORAGE_OFFSET: u32 = 0x18_0000;
+
+// a fixed, sector aligned area of flash holding one length prefixed record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Slot {
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl Slot {
+    const fn new(sector: u32, sectors: u32) -> Self {
+        Self {
+            offset: STORAGE_OFFSET + sector * SECTOR_SIZE,
+            size: sectors * SECTOR_SIZE,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.size as usize - HEADER_SIZE
+    }
+
+    // the sectors of the slot, for erasing it a piece at a time
+    pub fn sectors(&self) -> impl Iterator<Item = u32> {
+        (self.offset..self.offset + self.size).step_by(SECTOR_SIZE as usize)
+    }
+}
+
+pub const MACRO_SLOT: Slot = Slot::new(0, 4);
+pub const PROFILES_SLOT: Slot = Slot::new(4, 1);
+pub const ACTIVE_PROFILE_SLOT: Slot = Slot::new(5, 1);
+pub const AMIIBO_SLOTS: u8 = 8;
+pub const ACTIVE_AMIIBO_SLOT: Slot = Slot::new(6, 1);
+
+// one sector per stored amiibo file
+pub const fn amiibo_slot(index: u8) -> Slot {
+    Slot::new(7 + index as u32 % AMIIBO_SLOTS as u32, 1)
+}
+
+pub const LAST_HOST_SLOT: Slot = Slot::new(7 + AMIIBO_SLOTS as u32, 1);
+
+const HEADER_SIZE: usize = 4;
+// erased flash reads as all ones, which doubles as "no record"
+const EMPTY: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SaveError<E> {
+    Flash(E),
+    // the record does not fit the slot, nothing was erased
+    TooLarge,
+}
+
+impl<E> From<E> for SaveError<E> {
+    fn from(e: E) -> Self {
+        Self::Flash(e)
+    }
+}
+
+// Erases one sector at a time and yields in between. An erase stalls the whole chip for
+// tens of milliseconds, so the usb tasks get to answer the host between sectors instead
+// of missing several report intervals in a row.
+pub async fn save_yielding<F: NorFlash>(
+    flash: &mut F,
+    slot: Slot,
+    data: &[u8],
+) -> Result<(), SaveError<F::Error>> {
+    check(slot, data)?;
+    erase_yielding(flash, slot).await?;
+    write(flash, slot, data)
+}
+
+// fails before anything is erased when `data` does not fit the slot
+fn check<E>(slot: Slot, data: &[u8]) -> Result<(), SaveError<E>> {
+    if data.len() > slot.capacity() {
+        return Err(SaveError::TooLarge);
+    }
+    Ok(())
+}
+
+// writes the record into a slot that was already erased
+fn write<F: NorFlash>(flash: &mut F, slot: Slot, data: &[u8]) -> Result<(), SaveError<F::Error>> {
+    check(slot, data)?;
+    write_padded(flash, slot.offset, &(data.len() as u32).to_le_bytes())?;
+    write_padded(flash, slot.offset + HEADER_SIZE as u32, data)?;
+    Ok(())
+}
+
+pub fn load<'a, F: ReadNorFlash>(
+    flash: &mut F,
+    slot: Slot,
+    buf: &'a mut [u8],
+) -> Result<Option<&'a [u8]>, F::Error> {
+    let mut header = [0; HEADER_SIZE];
+    flash.read(slot.offset, &mut header)?;
+    let len = u32::from_le_bytes(header);
+    if len == EMPTY || len as usize > slot.capacity() || len as usize > buf.len() {
+        return Ok(None);
+    }
+    let buf = &mut buf[..len as usize];
+    flash.read(slot.offset + HEADER_SIZE as u32, buf)?;
+    Ok(Some(buf))
+}
+
+pub async fn clear<F: NorFlash>(flash: &mut F, slot: Slot) -> Result<(), F::Error> {
+    erase_yielding(flash, slot).await
+}
+
+async fn erase_yielding<F: NorFlash>(flash: &mut F, slot: Slot) -> Result<(), F::Error> {
+    for sector in slot.sectors() {
+        flash.erase(sector, sector + SECTOR_SIZE)?;
+        YieldNow(false).await;
+    }
+    Ok(())
+}
+
+// gives the other tasks a turn, once
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn write_padded<F: NorFlash>(flash: &mut F, offset: u32, data: &[u8]) -> Result<(), F::Error> {
+    let aligned = data.len() - data.len() % F::WRITE_SIZE;
+    flash.write(offset, &data[..aligned])?;
+    if aligned < data.len() {
+        let mut tail = [0xFF; 16];
+        let rest = &data[aligned..];
+        tail[..rest.len()].copy_from_slice(rest);
+        flash.write(offset + aligned as u32, &tail[..F::WRITE_SIZE])?;
+    }
+    Ok(())
+}
+
+// An in-memory NOR flash for host tests. Like the real part, writes can only clear bits
+// and everything has to be aligned to the write and erase sizes.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::{SECTOR_SIZE, STORAGE_OFFSET};
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    const SIZE: usize = 0x20_0000;
+
+    // runs a flash future to completion, returning its output and how often it yielded
+    pub fn poll_counted<F: Future>(future: F) -> (F::Output, usize) {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut yields = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, yields),
+                Poll::Pending => yields += 1,
+            }
+        }
+    }
+
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        poll_counted(future).0
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MockError(pub NorFlashErrorKind);
+
+    impl NorFlashError for MockError {
+        fn kind(&self) -> NorFlashErrorKind {
+            self.0
+        }
+    }
+
+    pub struct MockFlash {
+        data: Vec<u8>,
+        pub erases: usize,
+    }
+
+    impl MockFlash {
+        pub fn new() -> Self {
+            Self {
+                data: vec![0xFF; SIZE],
+                erases: 0,
+            }
+        }
+
+        pub fn bytes(&self, offset: u32, len: usize) -> &[u8] {
+            &self.data[offset as usize..offset as usize + len]
+        }
+
+        // a flash that already holds a few sectors of garbage in the storage area
+        pub fn dirty() -> Self {
+            let mut flash = Self::new();
+            let start = STORAGE_OFFSET as usize;
+            for (i, byte) in flash.data[start..start + 32 * SECTOR_SIZE as usize]
+                .iter_mut()
+                .enumerate()
+            {
+                *byte = (i * 7 % 251) as u8;
+            }
+            flash
+        }
+
+        fn check(&self, offset: u32, len: usize, align: usize) -> Result<(), MockError> {
+            if !(offset as usize).is_multiple_of(align) || !len.is_multiple_of(align) {
+                return Err(MockError(NorFlashErrorKind::NotAligned));
+            }
+            if offset as usize + len > SIZE {
+                return Err(MockError(NorFlashErrorKind::OutOfBounds));
+            }
+            Ok(())
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = MockError;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), MockError> {
+            self.check(offset, bytes.len(), Self::READ_SIZE)?;
+            bytes.copy_from_slice(self.bytes(offset, bytes.len()));
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            SIZE
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), MockError> {
+            self.check(from, (to - from) as usize, Self::ERASE_SIZE)?;
+            self.data[from as usize..to as usize].fill(0xFF);
+            self.erases += ((to - from) / SECTOR_SIZE) as usize;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), MockError> {
+            self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+            let start = offset as usize;
+            for (cell, byte) in self.data[start..start + bytes.len()].iter_mut().zip(bytes) {
+                *cell &= byte;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{block_on, poll_counted, MockFlash};
+    use super::*;
+
+    #[test]
+    fn records_round_trip_at_every_length() {
+        let mut flash = MockFlash::dirty();
+        let data: Vec<u8> = (0..=255).cycle().take(PROFILES_SLOT.capacity()).collect();
+        for len in (0..9).chain([255, 256, 257, PROFILES_SLOT.capacity()]) {
+            block_on(save_yielding(&mut flash, PROFILES_SLOT, &data[..len])).unwrap();
+            let mut buf = [0; SECTOR_SIZE as usize];
+            assert_eq!(
+                load(&mut flash, PROFILES_SLOT, &mut buf).unwrap(),
+                Some(&data[..len])
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_records_are_refused_before_erasing() {
+        let mut flash = MockFlash::new();
+        block_on(save_yielding(&mut flash, LAST_HOST_SLOT, &[1])).unwrap();
+        let erases = flash.erases;
+        let data = [0; SECTOR_SIZE as usize];
+        assert_eq!(
+            block_on(save_yielding(&mut flash, LAST_HOST_SLOT, &data)),
+            Err(SaveError::TooLarge)
+        );
+        assert_eq!(flash.erases, erases);
+        let mut buf = [0; 4];
+        assert_eq!(
+            load(&mut flash, LAST_HOST_SLOT, &mut buf).unwrap(),
+            Some(&[1][..])
+        );
+    }
+
+    #[test]
+    fn erased_and_cleared_slots_hold_nothing() {
+        let mut flash = MockFlash::new();
+        let mut buf = [0; 16];
+        assert_eq!(
+            load(&mut flash, ACTIVE_PROFILE_SLOT, &mut buf).unwrap(),
+            None
+        );
+        block_on(save_yielding(&mut flash, ACTIVE_PROFILE_SLOT, &[3])).unwrap();
+        block_on(clear(&mut flash, ACTIVE_PROFILE_SLOT)).unwrap();
+        assert_eq!(
+            load(&mut flash, ACTIVE_PROFILE_SLOT, &mut buf).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn records_larger_than_the_buffer_are_not_loaded() {
+        let mut flash = MockFlash::new();
+        block_on(save_yielding(&mut flash, PROFILES_SLOT, &[0; 32])).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(load(&mut flash, PROFILES_SLOT, &mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn saves_and_clears_yield_after_every_sector() {
+        let data: Vec<u8> = (0..10_000).map(|i| (i % 253) as u8).collect();
+        let mut flash = MockFlash::dirty();
+        let (saved, yields) = poll_counted(save_yielding(&mut flash, MACRO_SLOT, &data));
+        saved.unwrap();
+        assert_eq!((flash.erases, yields), (4, 4));
+        let mut buf = vec![0; MACRO_SLOT.capacity()];
+        assert_eq!(
+            load(&mut flash, MACRO_SLOT, &mut buf).unwrap(),
+            Some(&data[..])
+        );
+        let (cleared, yields) = poll_counted(clear(&mut flash, MACRO_SLOT));
+        cleared.unwrap();
+        assert_eq!((flash.erases, yields), (8, 4));
+        assert_eq!(load(&mut flash, MACRO_SLOT, &mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn slots_do_not_overlap_and_stay_in_the_reserved_area() {
+        let mut slots = vec![
+            MACRO_SLOT,
+            PROFILES_SLOT,
+            ACTIVE_PROFILE_SLOT,
+            ACTIVE_AMIIBO_SLOT,
+            LAST_HOST_SLOT,
+        ];
+        slots.extend((0..AMIIBO_SLOTS).map(amiibo_slot));
+        slots.sort_by_key(|slot| slot.offset);
+        for pair in slots.windows(2) {
+            assert!(pair[0].offset + pair[0].size <= pair[1].offset);
+        }
+        assert!(slots[0].offset >= STORAGE_OFFSET);
+        assert!(slots.iter().all(|slot| slot.offset % SECTOR_SIZE == 0));
+        // and the last one ends before the end of the 2M part
+        let end = slots.last().map(|slot| slot.offset + slot.size).unwrap();
+        assert!(end <= 0x20_0000);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::input::XboxState;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerConfig {
+    // presses at `press` and only lets go again below `release`
+    Threshold { press: u16, release: u16 },
+    // presses as soon as the trigger moves `sensitivity` deeper and releases as soon as it
+    // moves `sensitivity` back out, wherever in its travel that happens
+    HairTrigger { sensitivity: u16 },
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        TriggerConfig::Threshold {
+            press: XboxState::TRIGGER_MAX / 2,
+            release: XboxState::TRIGGER_MAX / 3,
+        }
+    }
+}
+
+// turns an analog trigger into a digital ZL/ZR without flickering on noise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Trigger {
+    pub config: TriggerConfig,
+    pressed: bool,
+    // furthest point reached since the last state change, for hair trigger mode
+    extreme: u16,
+}
+
+impl Trigger {
+    pub fn new(config: TriggerConfig) -> Self {
+        Self {
+            config,
+            pressed: false,
+            extreme: 0,
+        }
+    }
+
+    pub fn pressed(&self) -> bool {
+        self.pressed
+    }
+
+    pub fn update(&mut self, value: u16) -> bool {
+        match self.config {
+            TriggerConfig::Threshold { press, release } => {
+                if self.pressed {
+                    self.pressed = value >= release.min(press)
+                } else {
+                    self.pressed = value >= press
+                }
+            }
+            TriggerConfig::HairTrigger { sensitivity } => {
+                let sensitivity = sensitivity.max(1);
+                if self.pressed {
+                    self.extreme = self.extreme.max(value);
+                    if value == 0 || value.saturating_add(sensitivity) <= self.extreme {
+                        self.pressed = false;
+                        self.extreme = value;
+                    }
+                } else {
+                    self.extreme = self.extreme.min(value);
+                    if value >= self.extreme.saturating_add(sensitivity) {
+                        self.pressed = true;
+                        self.extreme = value;
+                    }
+                }
+            }
+        }
+        self.pressed
+    }
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Self::new(TriggerConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: u16 = XboxState::TRIGGER_MAX;
+
+    // a repeatable trace of `len` readings wobbling by up to `noise` around `level`
+    fn noisy(level: impl Fn(usize) -> u16, noise: u16, len: usize) -> Vec<u16> {
+        let mut seed = 0x2545_f491u32;
+        (0..len)
+            .map(|idx| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let wobble = (seed % (2 * noise as u32 + 1)) as i32 - noise as i32;
+                (level(idx) as i32 + wobble).clamp(0, MAX as i32) as u16
+            })
+            .collect()
+    }
+
+    // the readings at which the trigger changed state
+    fn edges(trigger: &mut Trigger, trace: &[u16]) -> Vec<(usize, bool)> {
+        let mut last = trigger.pressed();
+        let mut edges = Vec::new();
+        for (idx, value) in trace.iter().enumerate() {
+            let pressed = trigger.update(*value);
+            if pressed != last {
+                edges.push((idx, pressed));
+                last = pressed;
+            }
+        }
+        edges
+    }
+
+    #[test]
+    fn noise_around_the_press_point_presses_once() {
+        let mut trigger = Trigger::default();
+        let TriggerConfig::Threshold { press, release } = trigger.config else {
+            unreachable!()
+        };
+        let noise = (press - release) / 2 - 1;
+        let trace = noisy(|_| press, noise, 500);
+        let edges = edges(&mut trigger, &trace);
+        assert!(edges.len() <= 1, "{edges:?}");
+        assert!(edges.iter().all(|(_, pressed)| *pressed));
+    }
+
+    #[test]
+    fn noisy_pull_and_let_go_toggles_once_each_way() {
+        let mut trigger = Trigger::default();
+        // squeeze to the end over 100 readings, hold, then let go over 100
+        let level = |idx: usize| match idx {
+            0..=99 => (idx as u32 * MAX as u32 / 100) as u16,
+            100..=199 => MAX,
+            _ => (MAX as u32 * (300 - idx.min(300)) as u32 / 100) as u16,
+        };
+        let trace = noisy(level, 60, 320);
+        let edges = edges(&mut trigger, &trace);
+        assert_eq!(edges.len(), 2, "{edges:?}");
+        assert!(edges[0].1 && !edges[1].1);
+    }
+
+    #[test]
+    fn hair_trigger_holds_through_noise_below_its_sensitivity() {
+        let mut trigger = Trigger::new(TriggerConfig::HairTrigger { sensitivity: 40 });
+        let mut trace = vec![0, 100];
+        trace.extend(noisy(|_| 500, 19, 500));
+        let edges = edges(&mut trigger, &trace);
+        assert_eq!(edges, [(1, true)]);
+    }
+
+    #[test]
+    fn hair_trigger_follows_small_movements_anywhere_in_the_travel() {
+        let mut trigger = Trigger::new(TriggerConfig::HairTrigger { sensitivity: 40 });
+        let trace = [0, 600, 580, 560, 600, 640, 700, 650, 710];
+        assert_eq!(
+            edges(&mut trigger, &trace),
+            [(1, true), (3, false), (4, true), (7, false), (8, true)]
+        );
+    }
+
+    #[test]
+    fn huge_sensitivity_saturates_instead_of_overflowing() {
+        let mut trigger = Trigger::new(TriggerConfig::HairTrigger {
+            sensitivity: u16::MAX,
+        });
+        // no reading is ever far enough from released
+        assert_eq!(edges(&mut trigger, &[0, MAX, 10, MAX, 0]), []);
+        let mut trigger = Trigger::new(TriggerConfig::HairTrigger { sensitivity: MAX });
+        // the full travel is, and only letting go entirely releases again
+        assert_eq!(
+            edges(&mut trigger, &[0, MAX, 1, MAX, 0]),
+            [(1, true), (4, false)]
+        );
+    }
+}
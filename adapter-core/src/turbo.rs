@@ -0,0 +1,212 @@
+use crate::gamepad::{Button, Buttons};
+use crate::report::REPORT_INTERVAL_MS;
+use serde::{Deserialize, Serialize};
+
+// Timing is counted in 0x30 reports rather than milliseconds, so every on and off phase
+// lasts at least one full report and can't fall between two of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TurboConfig {
+    // hold this and tap a button to toggle turbo on it
+    pub modifier: Option<Button>,
+    // reports per press and release cycle
+    pub period: u8,
+    // reports per cycle the button is held down
+    pub on: u8,
+}
+
+impl TurboConfig {
+    pub fn from_rate(modifier: Option<Button>, presses_per_sec: u8, duty_percent: u8) -> Self {
+        let reports_per_sec = (1000 / REPORT_INTERVAL_MS) as u32;
+        let period = (reports_per_sec / presses_per_sec.max(1) as u32).clamp(2, u8::MAX as u32);
+        let on = (period * duty_percent.min(100) as u32 / 100).clamp(1, period - 1);
+        Self {
+            modifier,
+            period: period as u8,
+            on: on as u8,
+        }
+    }
+
+    fn period(&self) -> u8 {
+        self.period.max(2)
+    }
+
+    fn on(&self) -> u8 {
+        self.on.clamp(1, self.period() - 1)
+    }
+}
+
+impl Default for TurboConfig {
+    fn default() -> Self {
+        Self::from_rate(None, 10, 50)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Turbo {
+    pub config: TurboConfig,
+    enabled: Buttons,
+    held: Buttons,
+    // presses used to toggle turbo, hidden until they are released
+    consumed: Buttons,
+    toggled: bool,
+    frame: u8,
+}
+
+impl Turbo {
+    pub fn new(config: TurboConfig) -> Self {
+        Self {
+            config,
+            enabled: Buttons::NONE,
+            held: Buttons::NONE,
+            consumed: Buttons::NONE,
+            toggled: false,
+            frame: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> Buttons {
+        self.enabled
+    }
+
+    // handles modifier + button toggles and returns the buttons that should be held
+    pub fn input(&mut self, buttons: Buttons) -> Buttons {
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+        self.consumed = self.consumed & buttons;
+
+        match self.config.modifier {
+            Some(modifier) if buttons.is_pressed(modifier) => {
+                for button in pressed.iter().filter(|button| *button != modifier) {
+                    self.enabled = self.enabled ^ button.into();
+                    self.consumed = self.consumed | button.into();
+                    self.toggled = true;
+                }
+            }
+            _ => self.toggled = false,
+        }
+
+        // restart the cycle so a fresh press always begins with an on phase
+        if !(pressed & self.enabled).is_empty() && ((buttons & !pressed) & self.enabled).is_empty()
+        {
+            self.frame = 0
+        }
+
+        let mut buttons = buttons & !self.consumed;
+        if let (true, Some(modifier)) = (self.toggled, self.config.modifier) {
+            buttons.release(modifier)
+        }
+        buttons
+    }
+
+    // masks turbo buttons during the off phase of the current report
+    pub fn output(&self, buttons: Buttons) -> Buttons {
+        if self.frame < self.config.on() {
+            buttons
+        } else {
+            buttons & !self.enabled
+        }
+    }
+
+    // forgets held buttons and pending toggles, the enabled set stays
+    pub fn reset(&mut self) {
+        self.held = Buttons::NONE;
+        self.consumed = Buttons::NONE;
+        self.toggled = false;
+        self.frame = 0;
+    }
+
+    // called once per 0x30 report
+    pub fn advance(&mut self) {
+        self.frame = (self.frame + 1) % self.config.period()
+    }
+}
+
+impl Default for Turbo {
+    fn default() -> Self {
+        Self::new(TurboConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIER: Button = Button::Minus;
+
+    // the buttons each 0x30 report shows while `held` stays down, the way
+    // `ControllerState::standard_full` drives turbo
+    fn reports(turbo: &mut Turbo, held: Buttons, count: usize) -> Vec<Buttons> {
+        let held = turbo.input(held);
+        (0..count)
+            .map(|_| {
+                let shown = turbo.output(held);
+                turbo.advance();
+                shown
+            })
+            .collect()
+    }
+
+    fn enable(turbo: &mut Turbo, button: Button) {
+        turbo.input(MODIFIER.into());
+        turbo.input(Buttons::from(MODIFIER) | button.into());
+        turbo.input(Buttons::NONE);
+    }
+
+    #[test]
+    fn every_phase_is_seen_by_a_report() {
+        for rate in 1..=u8::MAX {
+            for duty in 0..=100 {
+                let config = TurboConfig::from_rate(Some(MODIFIER), rate, duty);
+                let mut turbo = Turbo::new(config);
+                enable(&mut turbo, Button::A);
+                let period = config.period as usize;
+                let shown = reports(&mut turbo, Button::A.into(), 3 * period);
+                let pressed: Vec<bool> = shown.iter().map(|b| b.is_pressed(Button::A)).collect();
+                // a fresh press starts on, and each cycle has both phases
+                assert!(pressed[0], "{rate}/s at {duty}%");
+                for cycle in pressed.chunks(period) {
+                    let on = cycle.iter().filter(|pressed| **pressed).count();
+                    assert_eq!(on, config.on as usize, "{rate}/s at {duty}%");
+                    assert!(on >= 1 && on < period, "{rate}/s at {duty}%");
+                    // one on phase then one off phase, never split up
+                    assert!(cycle[..on].iter().all(|pressed| *pressed));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rate_matches_the_report_interval() {
+        let config = TurboConfig::from_rate(None, 10, 50);
+        let period_ms = config.period as u64 * REPORT_INTERVAL_MS;
+        assert!((90..=110).contains(&period_ms), "{period_ms}ms");
+        let config = TurboConfig::from_rate(None, u8::MAX, 50);
+        assert_eq!((config.period, config.on), (2, 1));
+    }
+
+    #[test]
+    fn modifier_toggles_and_is_hidden() {
+        let mut turbo = Turbo::new(TurboConfig::from_rate(Some(MODIFIER), 10, 50));
+        turbo.input(MODIFIER.into());
+        let shown = turbo.input(Buttons::from(MODIFIER) | Button::B.into());
+        assert_eq!(shown, Buttons::NONE);
+        assert!(turbo.enabled().is_pressed(Button::B));
+        // still hidden while the modifier is held after the toggle
+        assert_eq!(turbo.input(MODIFIER.into()), Buttons::NONE);
+        assert_eq!(turbo.input(Buttons::NONE), Buttons::NONE);
+        enable(&mut turbo, Button::B);
+        assert!(turbo.enabled().is_empty());
+    }
+
+    #[test]
+    fn other_buttons_are_left_alone() {
+        let mut turbo = Turbo::new(TurboConfig::from_rate(Some(MODIFIER), 10, 50));
+        enable(&mut turbo, Button::A);
+        let held = Buttons::from(Button::A) | Button::B.into();
+        let shown = reports(&mut turbo, held, 40);
+        assert!(shown.iter().all(|buttons| buttons.is_pressed(Button::B)));
+        assert!(shown.iter().any(|buttons| !buttons.is_pressed(Button::A)));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::gamepad::{Button, GamepadState, StickPosition};
+
+// [0x00, length, buttons lo, buttons hi, lt, rt, lx, ly, rx, ry, 6 reserved], axes are
+// i16 little endian with up positive
+pub const XINPUT_REPORT_LEN: usize = 20;
+
+// output messages from the host, [type, length, ..]
+pub const RUMBLE: u8 = 0x00;
+pub const LED: u8 = 0x01;
+// the 360 pad takes 0..=255 per motor, the xbox one pad 0..=100
+const RUMBLE_MAX: u16 = 100;
+
+// switch buttons and the xinput bits they set. Face buttons go by position, so switch
+// B, at the bottom, is xbox A.
+const XINPUT_BUTTONS: [(Button, u16); 15] = [
+    (Button::Up, 0x0001),
+    (Button::Down, 0x0002),
+    (Button::Left, 0x0004),
+    (Button::Right, 0x0008),
+    (Button::Plus, 0x0010),
+    (Button::Minus, 0x0020),
+    (Button::LStick, 0x0040),
+    (Button::RStick, 0x0080),
+    (Button::L, 0x0100),
+    (Button::R, 0x0200),
+    (Button::Home, 0x0400),
+    (Button::B, 0x1000),
+    (Button::A, 0x2000),
+    (Button::Y, 0x4000),
+    (Button::X, 0x8000),
+];
+
+// the input report for the pad state the switch would see, the digital ZL and ZR pull
+// the triggers all the way
+pub fn report(state: &GamepadState) -> [u8; XINPUT_REPORT_LEN] {
+    let mut buttons = 0u16;
+    for (button, mask) in XINPUT_BUTTONS {
+        if state.buttons.is_pressed(button) {
+            buttons |= mask
+        }
+    }
+    let trigger = |button| {
+        if state.buttons.is_pressed(button) {
+            u8::MAX
+        } else {
+            0
+        }
+    };
+
+    let mut report = [0; XINPUT_REPORT_LEN];
+    report[1] = XINPUT_REPORT_LEN as u8;
+    report[2..4].copy_from_slice(&buttons.to_le_bytes());
+    report[4] = trigger(Button::ZL);
+    report[5] = trigger(Button::ZR);
+    let (left_x, left_y) = axes(state.left_stick);
+    let (right_x, right_y) = axes(state.right_stick);
+    for (idx, axis) in [left_x, left_y, right_x, right_y].into_iter().enumerate() {
+        report[6 + idx * 2..8 + idx * 2].copy_from_slice(&axis.to_le_bytes());
+    }
+    report
+}
+
+// 12 bit switch axes to the full i16 range, both grow upwards
+fn axes(stick: StickPosition) -> (i16, i16) {
+    let axis = |value: u16| {
+        let centered = (value as i32 - StickPosition::CENTER.x as i32) << 4;
+        centered.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    };
+    (axis(stick.x), axis(stick.y))
+}
+
+// the strong and weak motor levels of a host message scaled to the xbox pad, if it is a
+// rumble message
+pub fn rumble_levels(message: &[u8]) -> Option<(u8, u8)> {
+    match *message {
+        [RUMBLE, 0x08, _, strong, weak, ..] => {
+            let scale = |level: u8| (level as u16 * RUMBLE_MAX / u8::MAX as u16) as u8;
+            Some((scale(strong), scale(weak)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neutral_report() {
+        let report = report(&GamepadState::NEUTRAL);
+        assert_eq!(report[..2], [0x00, 0x14]);
+        assert_eq!(report[2..], [0; 18]);
+    }
+
+    #[test]
+    fn face_buttons_go_by_position() {
+        let mut state = GamepadState::NEUTRAL;
+        state.buttons.press(Button::B);
+        state.buttons.press(Button::X);
+        let report = report(&state);
+        assert_eq!(u16::from_le_bytes([report[2], report[3]]), 0x1000 | 0x8000);
+    }
+
+    #[test]
+    fn zl_and_zr_pull_the_triggers() {
+        let mut state = GamepadState::NEUTRAL;
+        state.buttons.press(Button::ZR);
+        state.buttons.press(Button::Home);
+        let report = report(&state);
+        assert_eq!(report[2..6], [0x00, 0x04, 0x00, 0xff]);
+    }
+
+    #[test]
+    fn sticks_span_the_i16_range() {
+        let state = GamepadState {
+            left_stick: StickPosition::new(0, StickPosition::MAX),
+            right_stick: StickPosition::new(0x900, 0x700),
+            ..GamepadState::NEUTRAL
+        };
+        let report = report(&state);
+        let axis = |idx: usize| i16::from_le_bytes([report[6 + idx * 2], report[7 + idx * 2]]);
+        assert_eq!(axis(0), i16::MIN);
+        assert_eq!(axis(1), i16::MAX - 15);
+        assert_eq!(axis(2), 0x1000);
+        assert_eq!(axis(3), -0x1000);
+    }
+
+    #[test]
+    fn rumble_is_scaled_to_the_xbox_pad() {
+        assert_eq!(
+            rumble_levels(&[RUMBLE, 0x08, 0x00, 0xff, 0x80, 0, 0, 0]),
+            Some((100, 50))
+        );
+        assert_eq!(rumble_levels(&[LED, 0x03, 0x06]), None);
+        assert_eq!(rumble_levels(&[RUMBLE, 0x08]), None);
+    }
+}
@@ -1,5 +1,6 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    /* the last 512K from 0x10180000 hold the settings, see adapter_core::storage */
+    FLASH : ORIGIN = 0x10000100, LENGTH = 1536K - 0x100
     RAM   : ORIGIN = 0x20000000, LENGTH = 256K
 }
@@ -0,0 +1,90 @@
+use super::feedback::{Feedback, FEEDBACK};
+use super::library::{self, LibraryError};
+use super::{CONTROLLER_STATE, FLASH};
+use defmt::*;
+use embassy_futures::select::{select3, Either3};
+use embassy_rp::clocks::RoscRng;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use rand_core::RngCore;
+
+pub use adapter_core::amiibo::{
+    Amiibo, AmiiboAction, AmiiboConfig, AmiiboTrigger, Tag, AMIIBO_MODIFIER, NTAG215_PAGES,
+    NTAG215_SIZE, TAG_PRESENT_MS,
+};
+
+// raised when the player asks to put the active amiibo on the reader
+pub static AMIIBO_SCAN: Signal<CriticalSectionRawMutex, AmiiboConfig> = Signal::new();
+// raised when the player asks for the next stored amiibo
+pub static AMIIBO_CYCLE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+// raised when the switch wrote to the tag, with the slot it was read from
+pub static AMIIBO_WRITTEN: Signal<CriticalSectionRawMutex, (u8, Amiibo)> = Signal::new();
+
+#[embassy_executor::task]
+pub async fn amiibo_storage() -> ! {
+    loop {
+        match select3(
+            AMIIBO_SCAN.wait(),
+            AMIIBO_CYCLE.wait(),
+            AMIIBO_WRITTEN.wait(),
+        )
+        .await
+        {
+            Either3::First(config) => {
+                let (slot, file) = {
+                    let mut flash = FLASH.get().await.lock().await;
+                    let slot = library::active(&mut *flash);
+                    (slot, library::load_file(&mut *flash, slot))
+                };
+                let amiibo = match file {
+                    Ok(Some(file)) => file.amiibo,
+                    Ok(None) => {
+                        warn!("no amiibo stored in slot {}", slot);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("failed to read amiibo {}: {:?}", slot, e);
+                        continue;
+                    }
+                };
+                let mut tag = Tag::place(slot, amiibo, Instant::now());
+                if config.random_uid {
+                    let mut uid = [0; 7];
+                    RoscRng.fill_bytes(&mut uid);
+                    tag = tag.with_random_uid(uid);
+                }
+                info!("placing amiibo {:x} on the reader", tag.amiibo.uid());
+                let mut controller = CONTROLLER_STATE.get().await.lock().await;
+                controller.place_amiibo(tag);
+            }
+            Either3::Second(()) => {
+                let mut flash = FLASH.get().await.lock().await;
+                match library::cycle(&mut *flash).await {
+                    Ok(Some(slot)) => {
+                        info!("amiibo {} selected", slot);
+                        FEEDBACK.signal(Feedback::AmiiboSelected(slot));
+                    }
+                    Ok(None) => warn!("no amiibo stored"),
+                    Err(e) => warn!("failed to select amiibo: {:?}", e),
+                }
+            }
+            Either3::Third((slot, amiibo)) => {
+                let mut flash = FLASH.get().await.lock().await;
+                // keep the file's name, only the tag contents changed
+                let saved = match library::load_file(&mut *flash, slot) {
+                    Ok(Some(mut file)) => {
+                        file.amiibo = amiibo;
+                        library::save_file(&mut *flash, slot, &file).await
+                    }
+                    Ok(None) => Err(LibraryError::EmptySlot),
+                    Err(e) => Err(e),
+                };
+                match saved {
+                    Ok(()) => info!("amiibo {} written back to flash", slot),
+                    Err(e) => warn!("failed to save amiibo {}: {:?}", slot, e),
+                }
+            }
+        }
+    }
+}
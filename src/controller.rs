@@ -0,0 +1,208 @@
+use super::gamepad::{Button, Buttons, GamepadState, StickPosition};
+use adapter_core::descriptor::{HID_DESCRIPTOR, JOYCON_HID_DESCRIPTOR};
+use adapter_core::report::{ControllerKind, SpiColors};
+use adapter_core::retro::RetroController;
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+// Which controller the adapter poses as. The usb identity is picked once at power up,
+// so changing it only takes effect the next time the adapter is plugged in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum ControllerType {
+    #[default]
+    ProController,
+    JoyConLeft,
+    JoyConRight,
+    // a lone joy-con held sideways, the way one is handed to a second player
+    SidewaysLeft,
+    SidewaysRight,
+    // both joy-cons in a charging grip, which reports the whole layout
+    ChargingGrip,
+    // a Nintendo Switch Online controller, the retro apps label buttons after it
+    Retro(RetroController),
+}
+
+const LEFT_BUTTONS: [Button; 11] = [
+    Button::Minus,
+    Button::LStick,
+    Button::Capture,
+    Button::Down,
+    Button::Up,
+    Button::Right,
+    Button::Left,
+    Button::LeftSR,
+    Button::LeftSL,
+    Button::L,
+    Button::ZL,
+];
+
+const RIGHT_BUTTONS: [Button; 11] = [
+    Button::Y,
+    Button::X,
+    Button::B,
+    Button::A,
+    Button::RightSR,
+    Button::RightSL,
+    Button::R,
+    Button::ZR,
+    Button::Plus,
+    Button::RStick,
+    Button::Home,
+];
+
+// pro controller buttons and the joy-con buttons they land on when held sideways. The
+// left joy-con is turned a quarter counterclockwise, so its d-pad becomes the face
+// buttons, the right one a quarter clockwise.
+const SIDEWAYS_LEFT: [(Button, Button); 13] = [
+    (Button::A, Button::Down),
+    (Button::B, Button::Left),
+    (Button::X, Button::Right),
+    (Button::Y, Button::Up),
+    (Button::L, Button::LeftSL),
+    (Button::ZL, Button::LeftSL),
+    (Button::R, Button::LeftSR),
+    (Button::ZR, Button::LeftSR),
+    (Button::Minus, Button::Minus),
+    (Button::Plus, Button::Minus),
+    (Button::LStick, Button::LStick),
+    (Button::Capture, Button::Capture),
+    (Button::Home, Button::Capture),
+];
+
+const SIDEWAYS_RIGHT: [(Button, Button); 13] = [
+    (Button::A, Button::X),
+    (Button::B, Button::A),
+    (Button::X, Button::Y),
+    (Button::Y, Button::B),
+    (Button::L, Button::RightSL),
+    (Button::ZL, Button::RightSL),
+    (Button::R, Button::RightSR),
+    (Button::ZR, Button::RightSR),
+    (Button::Minus, Button::Plus),
+    (Button::Plus, Button::Plus),
+    (Button::LStick, Button::RStick),
+    (Button::Capture, Button::Home),
+    (Button::Home, Button::Home),
+];
+
+impl ControllerType {
+    // A joy-con only shows up on usb through the charging grip, so every joy-con
+    // enumerates with the grip's id and tells the switch which side it is through
+    // `kind`.
+    pub fn product_id(self) -> u16 {
+        match self {
+            ControllerType::ProController => joycon_sys::PRO_CONTROLLER,
+            ControllerType::Retro(retro) => retro.product_id(),
+            _ => joycon_sys::JOYCON_CHARGING_GRIP,
+        }
+    }
+
+    pub fn product_name(self) -> &'static str {
+        match self {
+            ControllerType::ProController => "Pro Controller",
+            ControllerType::JoyConLeft | ControllerType::SidewaysLeft => "Joy-Con (L)",
+            ControllerType::JoyConRight | ControllerType::SidewaysRight => "Joy-Con (R)",
+            ControllerType::ChargingGrip => "Joy-Con Charging Grip",
+            ControllerType::Retro(retro) => retro.product_name(),
+        }
+    }
+
+    pub fn hid_descriptor(self) -> &'static [u8] {
+        match self {
+            ControllerType::ProController
+            | ControllerType::ChargingGrip
+            | ControllerType::Retro(_) => &HID_DESCRIPTOR,
+            _ => &JOYCON_HID_DESCRIPTOR,
+        }
+    }
+
+    // a grip names its left joy-con here, device info lists both, see `DeviceInfo::pair`
+    pub fn kind(self) -> ControllerKind {
+        match self {
+            ControllerType::JoyConLeft
+            | ControllerType::SidewaysLeft
+            | ControllerType::ChargingGrip => ControllerKind::JoyConLeft,
+            ControllerType::JoyConRight | ControllerType::SidewaysRight => {
+                ControllerKind::JoyConRight
+            }
+            ControllerType::ProController => ControllerKind::ProController,
+            ControllerType::Retro(retro) => retro.kind(),
+        }
+    }
+
+    // The leg strap holds a left joy-con and the Ring-Con a right one, the pair in a grip
+    // has a joy-con for each so Ring Fit gets both at once.
+    pub fn has_left_joycon(self) -> bool {
+        matches!(
+            self,
+            ControllerType::JoyConLeft | ControllerType::ChargingGrip
+        )
+    }
+
+    pub fn has_right_joycon(self) -> bool {
+        matches!(
+            self,
+            ControllerType::JoyConRight | ControllerType::ChargingGrip
+        )
+    }
+
+    // only the pro controller has grips to color
+    pub fn spi_colors(self) -> SpiColors {
+        match self {
+            ControllerType::ProController => SpiColors::IncludingGrip,
+            _ => SpiColors::WithoutGrip,
+        }
+    }
+
+    // folds a full pro controller state onto the buttons and stick this controller has
+    pub fn layout(self, state: GamepadState) -> GamepadState {
+        let center = StickPosition::CENTER;
+        match self {
+            ControllerType::ProController | ControllerType::ChargingGrip => state,
+            ControllerType::Retro(retro) => retro.layout(state),
+            ControllerType::JoyConLeft => GamepadState {
+                buttons: state.buttons & buttons(&LEFT_BUTTONS),
+                left_stick: state.left_stick,
+                right_stick: center,
+            },
+            ControllerType::JoyConRight => GamepadState {
+                buttons: state.buttons & buttons(&RIGHT_BUTTONS),
+                left_stick: center,
+                right_stick: state.right_stick,
+            },
+            // player up is joy-con right
+            ControllerType::SidewaysLeft => GamepadState {
+                buttons: sideways(state.buttons, &SIDEWAYS_LEFT),
+                left_stick: StickPosition::new(
+                    state.left_stick.y,
+                    StickPosition::MAX - state.left_stick.x,
+                ),
+                right_stick: center,
+            },
+            // player up is joy-con left
+            ControllerType::SidewaysRight => GamepadState {
+                buttons: sideways(state.buttons, &SIDEWAYS_RIGHT),
+                left_stick: center,
+                right_stick: StickPosition::new(
+                    StickPosition::MAX - state.left_stick.y,
+                    state.left_stick.x,
+                ),
+            },
+        }
+    }
+}
+
+fn buttons(list: &[Button]) -> Buttons {
+    list.iter()
+        .fold(Buttons::NONE, |buttons, button| buttons | (*button).into())
+}
+
+fn sideways(pressed: Buttons, table: &[(Button, Button)]) -> Buttons {
+    let mut buttons = Buttons::NONE;
+    for (from, to) in table {
+        if pressed.is_pressed(*from) {
+            buttons.press(*to)
+        }
+    }
+    buttons
+}
@@ -0,0 +1,125 @@
+use super::cyw43_task;
+use cyw43_pio::PioSpi;
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_rp::gpio::Output;
+use embassy_rp::peripherals::{DMA_CH0, PIO0};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+use static_cell::StaticCell;
+
+const XBOX_OUTPUT_CHANNEL_SIZE: usize = 4;
+pub const XBOX_OUTPUT_LEN: usize = 9;
+
+// confirmation for things the player changes from the pad, newest wins
+pub static FEEDBACK: Signal<CriticalSectionRawMutex, Feedback> = Signal::new();
+
+// output reports for the xbox controller, sent by `link::run` over whatever transport it
+// is connected over. Until a transport runs nothing drains it, the player led is then the
+// only confirmation the player gets.
+pub static XBOX_OUTPUT: Channel<
+    CriticalSectionRawMutex,
+    [u8; XBOX_OUTPUT_LEN],
+    XBOX_OUTPUT_CHANNEL_SIZE,
+> = Channel::new();
+
+// the pulses `feedback` rumbled, for the led task to blink along
+static LED_BLINKS: Signal<CriticalSectionRawMutex, Rumble> = Signal::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum Feedback {
+    // zero based index of the profile that is now active
+    ProfileSelected(u8),
+    // zero based slot of the amiibo that is now active
+    AmiiboSelected(u8),
+}
+
+impl Feedback {
+    // one short pulse per profile or slot number, so the player can count which one is active
+    pub fn pulses(&self) -> u8 {
+        match self {
+            Feedback::ProfileSelected(index) | Feedback::AmiiboSelected(index) => index + 1,
+        }
+    }
+
+    pub fn rumble(&self) -> Rumble {
+        Rumble {
+            strong: 0x40,
+            weak: 0x40,
+            on_ms: 80,
+            off_ms: 120,
+            pulses: self.pulses(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct Rumble {
+    pub strong: u8,
+    pub weak: u8,
+    pub on_ms: u16,
+    pub off_ms: u16,
+    pub pulses: u8,
+}
+
+impl Rumble {
+    // output report 0x03 of the xbox wireless controller, timing is in 10ms steps
+    pub fn report(&self) -> [u8; XBOX_OUTPUT_LEN] {
+        [
+            0x03,
+            0x03, // enable only the two main motors
+            0,
+            0,
+            self.strong,
+            self.weak,
+            (self.on_ms / 10).min(u8::MAX as u16) as u8,
+            (self.off_ms / 10).min(u8::MAX as u16) as u8,
+            self.pulses.saturating_sub(1),
+        ]
+    }
+}
+
+// rumbles the xbox pad and has `feedback_led` blink the same number of times
+#[embassy_executor::task]
+pub async fn feedback() -> ! {
+    loop {
+        let feedback = FEEDBACK.wait().await;
+        info!("feedback: {}", feedback);
+        let rumble = feedback.rumble();
+        if XBOX_OUTPUT.try_send(rumble.report()).is_err() {
+            warn!("xbox output full, dropping rumble");
+        }
+        LED_BLINKS.signal(rumble);
+    }
+}
+
+// blinks the pico w led, which hangs off the cyw43. Only the wifi half of the chip is
+// brought up, from firmware flashed next to the program by `just cyw43-dev`.
+#[embassy_executor::task]
+pub async fn feedback_led(
+    spawner: Spawner,
+    pwr: Output<'static>,
+    spi: PioSpi<'static, PIO0, 0, DMA_CH0>,
+) -> ! {
+    let fw = unsafe { core::slice::from_raw_parts(0x10100000 as *const u8, 224190) };
+    let clm = unsafe { core::slice::from_raw_parts(0x10140000 as *const u8, 4752) };
+
+    static STATE: StaticCell<cyw43::State> = StaticCell::new();
+    let state = STATE.init(cyw43::State::new());
+    let (_net_device, mut control, runner) = cyw43::new(state, pwr, spi, fw).await;
+    unwrap!(spawner.spawn(cyw43_task(runner)));
+    control.init(clm).await;
+    info!("cyw43 up, blinking feedback on the led");
+
+    loop {
+        let rumble = LED_BLINKS.wait().await;
+        for _ in 0..rumble.pulses {
+            control.gpio_set(0, true).await;
+            Timer::after_millis(rumble.on_ms as u64).await;
+            control.gpio_set(0, false).await;
+            Timer::after_millis(rumble.off_ms as u64).await;
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use super::profile::{Profiles, PROFILES_BYTES, PROFILES_UPDATED};
+use super::FLASH;
+use adapter_core::descriptor::HOST_HID_DESCRIPTOR;
+use defmt::*;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::Driver;
+use embassy_usb::class::hid::{self, HidReaderWriter};
+use embassy_usb::Builder;
+use static_cell::StaticCell;
+
+pub use adapter_core::host::{HostInterface, HOST_PACKET_SIZE, HOST_REQUEST};
+
+pub type HostHid =
+    HidReaderWriter<'static, Driver<'static, USB>, HOST_PACKET_SIZE, HOST_PACKET_SIZE>;
+
+// adds the pc tool's interface, after the pad's so the pad stays interface 0
+pub fn host_hid(builder: &mut Builder<'static, Driver<'static, USB>>) -> HostHid {
+    static STATE: StaticCell<hid::State> = StaticCell::new();
+    let config = hid::Config {
+        report_descriptor: &HOST_HID_DESCRIPTOR,
+        request_handler: None,
+        poll_ms: 0x08,
+        max_packet_size: 64,
+    };
+    HidReaderWriter::new(builder, STATE.init(hid::State::new()), config)
+}
+
+// Answers the pc tool. Flash work happens here with its own reader, so erases never hold
+// up reading the switch's output reports.
+#[embassy_executor::task]
+pub async fn host_interface(hid: HostHid) -> ! {
+    let (mut reader, mut writer) = hid.split();
+    reader.ready().await;
+    let mut host = HostInterface::<PROFILES_BYTES>::new();
+    let mut request = [0; HOST_PACKET_SIZE];
+    loop {
+        match reader.read(&mut request).await {
+            Ok(_) if request[0] == HOST_REQUEST => {
+                let (reply, profiles) = {
+                    let mut flash = FLASH.get().await.lock().await;
+                    host.handle(&mut *flash, &request, Profiles::decode).await
+                };
+                if let Some(profiles) = profiles {
+                    info!("{} profiles uploaded", profiles.len());
+                    PROFILES_UPDATED.signal(profiles);
+                }
+                if let Err(error) = writer.write(&reply).await {
+                    warn!("host write error: {}", error)
+                }
+            }
+            Ok(_) => warn!("unknown host report {:x}", request[0]),
+            Err(error) => warn!("host read error: {}", error),
+        }
+    }
+}
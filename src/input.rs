@@ -0,0 +1,69 @@
+use super::amiibo::{AmiiboAction, AMIIBO_CYCLE, AMIIBO_SCAN};
+use super::feedback::{Feedback, FEEDBACK};
+use super::link::{LinkInput, LINK_LOST};
+use super::pipeline::Pipeline;
+use super::profile::{PROFILES_UPDATED, PROFILE_SELECTED};
+use super::{CONTROLLER_STATE, REPORT_INTERVAL_MS};
+use defmt::*;
+use embassy_futures::select::{select4, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Instant, Timer};
+
+pub use adapter_core::input::{XboxButton, XboxButtons, XboxState, XBOX_REPORT_LEN};
+
+const XBOX_REPORT_CHANNEL_SIZE: usize = 4;
+
+// parsed xbox reports from whatever transport the controller is connected over
+pub static XBOX_REPORTS: Channel<CriticalSectionRawMutex, XboxState, XBOX_REPORT_CHANNEL_SIZE> =
+    Channel::new();
+
+#[embassy_executor::task]
+pub async fn xbox_input(mut pipeline: Pipeline) -> ! {
+    let mut input = LinkInput::default();
+    loop {
+        // the controller only reports changes, so keep ticking while a hotkey waits on time
+        let pending = pipeline.is_pending();
+        let tick = async {
+            if pending {
+                Timer::after_millis(REPORT_INTERVAL_MS).await
+            } else {
+                core::future::pending().await
+            }
+        };
+        match select4(
+            XBOX_REPORTS.receive(),
+            PROFILES_UPDATED.wait(),
+            LINK_LOST.wait(),
+            tick,
+        )
+        .await
+        {
+            Either4::First(report) => input.report(report),
+            Either4::Second(profiles) => pipeline.set_profiles(profiles),
+            // the supervisor already released the pad, nothing held may come back
+            Either4::Third(()) => {
+                input.lost();
+                pipeline.release();
+            }
+            Either4::Fourth(()) => (),
+        }
+
+        let state = pipeline.process(input.current(), Instant::now());
+        let mut controller = CONTROLLER_STATE.get().await.lock().await;
+        if pipeline.take_switched() {
+            let index = pipeline.profiles().active_index();
+            controller.set_profile(pipeline.profiles().active());
+            PROFILE_SELECTED.signal(index);
+            FEEDBACK.signal(Feedback::ProfileSelected(index));
+        }
+        match pipeline.take_amiibo_action() {
+            Some(AmiiboAction::Scan) => AMIIBO_SCAN.signal(pipeline.profiles().active().amiibo),
+            Some(AmiiboAction::Cycle) => AMIIBO_CYCLE.signal(()),
+            None => (),
+        }
+        controller.set_motion(pipeline.motion());
+        controller.set_ringcon(pipeline.ringcon());
+        controller.apply(state);
+    }
+}
@@ -0,0 +1,100 @@
+use super::feedback::{XBOX_OUTPUT, XBOX_OUTPUT_LEN};
+use super::input::{XboxState, XBOX_REPORTS, XBOX_REPORT_LEN};
+use super::CONTROLLER_STATE;
+pub use adapter_core::link::{Backoff, LinkConfig, LinkEvent, LinkInput, LinkSupervisor};
+use defmt::*;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Timer};
+
+const LINK_EVENT_CHANNEL_SIZE: usize = 8;
+
+// transports report here so the supervisor can tell a live link from a dead one
+pub static LINK_EVENTS: Channel<CriticalSectionRawMutex, LinkEvent, LINK_EVENT_CHANNEL_SIZE> =
+    Channel::new();
+
+// tells the input task the pad was released, so it drops the last report with it
+pub static LINK_LOST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+// a wireless connection to the controller, kept up by `run`
+pub trait Transport {
+    type Error: Format;
+
+    // waits until the controller connected
+    async fn connect(&mut self) -> Result<(), Self::Error>;
+
+    // returns the next input report of the controller, or None after `keepalive` once the
+    // transport confirmed the connection is still up. Fails when the connection is gone.
+    // Dropped whenever an output report is waiting, so it has to be cancel safe.
+    async fn poll(
+        &mut self,
+        keepalive: Duration,
+    ) -> Result<Option<[u8; XBOX_REPORT_LEN]>, Self::Error>;
+
+    // sends an output report such as rumble to the controller
+    async fn send(&mut self, report: &[u8; XBOX_OUTPUT_LEN]) -> Result<(), Self::Error>;
+}
+
+// keeps the controller connected over `transport`, reconnecting with backoff whenever the
+// link drops, hands its reports to the input task and sends it what is queued in
+// XBOX_OUTPUT. The controller only reports changes, so a held button sends nothing and
+// the supervisor is fed from the transport polls instead.
+pub async fn run<T: Transport>(mut transport: T, config: LinkConfig) -> ! {
+    let mut backoff = Backoff::new(config.reconnect_min, config.reconnect_max);
+    loop {
+        if let Err(e) = transport.connect().await {
+            let delay = backoff.next_delay();
+            warn!(
+                "controller connection failed: {}, retrying in {}ms",
+                e,
+                delay.as_millis()
+            );
+            Timer::after(delay).await;
+            continue;
+        }
+        backoff.reset();
+        // rumble queued while nothing was connected is stale by now
+        XBOX_OUTPUT.clear();
+        LINK_EVENTS.send(LinkEvent::Connected).await;
+
+        let error = loop {
+            match select(transport.poll(config.keepalive), XBOX_OUTPUT.receive()).await {
+                Either::First(Ok(report)) => {
+                    if let Some(state) = report.and_then(|report| XboxState::parse(&report)) {
+                        XBOX_REPORTS.send(state).await
+                    }
+                    LINK_EVENTS.send(LinkEvent::Alive).await
+                }
+                Either::First(Err(e)) => break e,
+                Either::Second(output) => {
+                    if let Err(e) = transport.send(&output).await {
+                        break e;
+                    }
+                }
+            }
+        };
+        warn!("controller link dropped: {}", error);
+        LINK_EVENTS.send(LinkEvent::Lost).await;
+        Timer::after(backoff.next_delay()).await;
+    }
+}
+
+#[embassy_executor::task]
+pub async fn link_supervisor(config: LinkConfig) -> ! {
+    let mut supervisor = LinkSupervisor::new();
+    loop {
+        let event = with_timeout(config.supervision_timeout, LINK_EVENTS.receive())
+            .await
+            .ok();
+        if supervisor.step(event) {
+            warn!(
+                "controller link silent or lost (connected: {}), releasing all inputs",
+                supervisor.connected()
+            );
+            CONTROLLER_STATE.get().await.lock().await.neutral();
+            LINK_LOST.signal(());
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use super::storage::{self, FlashStorage, MACRO_SLOT};
+use super::{CONTROLLER_STATE, FLASH};
+use defmt::*;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+pub use adapter_core::macros::{
+    Macro, MacroConfig, MacroEngine, MacroFrame, PlaybackMode, MACRO_FRAMES,
+};
+
+// bump whenever `Macro` or anything it contains changes shape
+pub const MACRO_FORMAT_VERSION: u8 = 1;
+// worst case postcard size of a full macro plus the version byte
+pub const MACRO_BYTES: usize = 1 + 3 + MACRO_FRAMES * 20;
+
+// raised when a recording finishes so it can be written to flash
+pub static MACRO_RECORDED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn encode_macro<'a>(recording: &Macro, buf: &'a mut [u8]) -> Option<&'a mut [u8]> {
+    postcard::to_slice(&(MACRO_FORMAT_VERSION, recording), buf).ok()
+}
+
+pub fn decode_macro(bytes: &[u8]) -> Option<Macro> {
+    let (version, rest) = postcard::take_from_bytes::<u8>(bytes).ok()?;
+    if version != MACRO_FORMAT_VERSION {
+        warn!("ignoring macro with format version {}", version);
+        return None;
+    }
+    postcard::from_bytes(rest).ok()
+}
+
+pub fn load_macro(flash: &mut FlashStorage) -> Option<Macro> {
+    let mut buf = [0; MACRO_BYTES];
+    match storage::load(flash, MACRO_SLOT, &mut buf) {
+        Ok(Some(bytes)) => decode_macro(bytes),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("failed to read macro from flash: {:?}", e);
+            None
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn macro_storage() -> ! {
+    let mut buf = [0; MACRO_BYTES];
+    loop {
+        MACRO_RECORDED.wait().await;
+        let len = {
+            let state = CONTROLLER_STATE.get().await.lock().await;
+            match encode_macro(state.macros().recording(), &mut buf) {
+                Some(bytes) => bytes.len(),
+                None => {
+                    warn!("failed to encode macro");
+                    continue;
+                }
+            }
+        };
+        let mut flash = FLASH.get().await.lock().await;
+        match storage::save_yielding(&mut *flash, MACRO_SLOT, &buf[..len]).await {
+            Ok(()) => info!("macro saved to flash ({} bytes)", len),
+            Err(e) => warn!("failed to save macro: {:?}", e),
+        }
+    }
+}
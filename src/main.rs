@@ -1,14 +1,32 @@
 #![no_std]
 #![no_main]
 
+use adapter_core::accessibility;
+use adapter_core::curve;
+use adapter_core::dpad;
+use adapter_core::gamepad;
+use adapter_core::gate;
+use adapter_core::hotkey;
+use adapter_core::layer;
+use adapter_core::library;
+use adapter_core::mapping;
+use adapter_core::mcu;
+use adapter_core::motion;
+use adapter_core::pad;
+use adapter_core::report::{self, REPORT_INTERVAL_MS};
+use adapter_core::ringcon;
+use adapter_core::stick;
+use adapter_core::trigger;
+use adapter_core::turbo;
 use cyw43_pio::PioSpi;
 use defmt::*;
 use embassy_executor::Spawner;
 use embassy_rp::bind_interrupts;
 use embassy_rp::gpio;
 use embassy_rp::peripherals::{DMA_CH0, PIO0, USB};
-use embassy_rp::pio::{self};
+use embassy_rp::pio::{self, Pio};
 use embassy_rp::usb::{self, Driver};
+use embassy_rp::watchdog::Watchdog;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
@@ -21,13 +39,40 @@ use embassy_time::Timer;
 use embassy_usb::class::hid::{self, HidReader, HidReaderWriter, HidWriter};
 use embassy_usb::UsbVersion;
 use embassy_usb::{Builder, Config};
-use gpio::Output;
+use gpio::{Level, Output};
 use joycon_sys;
 use static_cell::StaticCell;
 use {defmt_rtt as _, panic_probe as _};
 
+mod amiibo;
+use amiibo::*;
+mod controller;
+mod feedback;
+mod hori;
+use hori::*;
+mod host;
+use host::*;
+mod input;
+use input::*;
+mod link;
+use link::*;
+mod macros;
+use macros::*;
+mod personality;
+use personality::*;
+mod pipeline;
+use pipeline::*;
+mod profile;
+use profile::*;
+mod storage;
+use storage::*;
 mod switch;
 use switch::*;
+mod xinput;
+use xinput::*;
+// The ble transport in xbox.rs is what drives `link::run`. It needs the bluetooth
+// support of cyw43, which the cyw43 0.2 release this builds against doesn't have, so
+// until that lands nothing feeds LINK_EVENTS and the supervisor never has to release.
 // mod xbox;
 // use xbox::*;
 
@@ -37,67 +82,126 @@ bind_interrupts!(struct Irqs {
 });
 
 const USB_RESPONSE_CHANNEL_SIZE: usize = 10;
+// One channel message is one whole input report, up to the 0x31 one with its mcu block,
+// so the packets of two reports never interleave.
+type UsbReport = heapless::Vec<u8, { report::MCU_INPUT_LEN }>;
+// The hid writer takes one packet at a time. Sized for a whole 0x31 report embassy would
+// end every 64 byte report with a zero length packet.
+pub const HID_WRITE_LEN: usize = report::REPORT_LEN;
+// the short last packet of a 0x31 report ends its transfer
+const _: () = assert!(report::MCU_INPUT_LEN % HID_WRITE_LEN != 0);
 
 static CONTROLLER_STATE: OnceLock<Mutex<NoopRawMutex, ControllerState>> = OnceLock::new();
+static FLASH: OnceLock<Mutex<NoopRawMutex, FlashStorage>> = OnceLock::new();
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     // Initialise Peripherals
     let p = embassy_rp::init(Default::default());
+    let mut watchdog = Watchdog::new(p.WATCHDOG);
 
     CONTROLLER_STATE
         .init(Mutex::new(ControllerState::new()))
         .expect("Failed to init Controller State");
 
-    // // spawn xbox controller task
-    // {
-    //     // https://github.com/embassy-rs/embassy/tree/main/cyw43-firmware
-    //     // let fw = include_bytes!("../cyw43-firmware/43439A0.bin");
-    //     // let clm = include_bytes!("../cyw43-firmware/43439A0_clm.bin");
-    //     // let btfw = include_bytes!("../cyw43-firmware/43439A0_btfw.bin");
-
-    //     // To make flashing faster for development, you may want to flash the firmwares independently
-    //     // at hardcoded addresses, instead of baking them into the program with `include_bytes!`:
-    //     //     probe-rs download 43439A0.bin --binary-format bin --chip RP2040 --base-address 0x10100000
-    //     //     probe-rs download 43439A0_clm.bin --binary-format bin --chip RP2040 --base-address 0x10140000
-    //     //     probe-rs download 43439A0_btfw.bin --binary-format bin --chip RP2040 --base-address 0x10141400
-    //     let fw = unsafe { core::slice::from_raw_parts(0x10100000 as *const u8, 224190) };
-    //     let clm = unsafe { core::slice::from_raw_parts(0x10140000 as *const u8, 4752) };
-    //     let btfw = unsafe { core::slice::from_raw_parts(0x10141400 as *const u8, 6164) };
-
-    //     let pwr = Output::new(p.PIN_23, Level::Low);
-    //     let cs = Output::new(p.PIN_25, Level::High);
-    //     let mut pio = Pio::new(p.PIO0, Irqs);
-    //     let spi = PioSpi::new(
-    //         &mut pio.common,
-    //         pio.sm0,
-    //         pio.irq0,
-    //         cs,
-    //         p.PIN_24,
-    //         p.PIN_29,
-    //         p.DMA_CH0,
-    //     );
-
-    //     static STATE: StaticCell<cyw43::State> = StaticCell::new();
-    //     let state = STATE.init(cyw43::State::new());
-    //     info!("before");
-    //     let (_net_device, bt_device, mut control, runner) =
-    //         cyw43::new_with_bluetooth(state, pwr, spi, fw, btfw).await;
-    //     info!("after");
-    //     unwrap!(spawner.spawn(cyw43_task(runner)));
-    //     control.init(clm).await;t.is_special()
-    //     info!("setting up");
-    //     bluetooth_setup(bt_device).await;
-    // }
+    let mut flash = FlashStorage::new_blocking(p.FLASH);
+    if let Some(recording) = load_macro(&mut flash) {
+        info!("loaded macro with {} frames", recording.frames.len());
+        CONTROLLER_STATE
+            .get()
+            .await
+            .lock()
+            .await
+            .macros_mut()
+            .load(recording);
+    }
+    let profiles = load_profiles(&mut flash);
+    let last_host = load_last_host(&mut flash);
+    info!(
+        "{} profiles, active: {}",
+        profiles.list().len(),
+        profiles.active().name.as_str()
+    );
+    let controller = profiles.active().controller;
+    let configured = profiles.active().personality;
+    let personality = take_personality(
+        &mut watchdog,
+        configured
+            .or(last_host.map(Host::personality))
+            .unwrap_or_default(),
+    );
+    info!(
+        "posing as {} over {}, last host {}",
+        controller, personality, last_host
+    );
+    {
+        let mut state = CONTROLLER_STATE.get().await.lock().await;
+        // the type first, the profile's accessories depend on it
+        state.set_controller_type(controller);
+        state.set_profile(profiles.active());
+    }
+    FLASH
+        .init(Mutex::new(flash))
+        .map_err(|_| ())
+        .expect("Failed to init Flash");
+
+    unwrap!(spawner.spawn(link_supervisor(LinkConfig::default())));
+    unwrap!(spawner.spawn(xbox_input(Pipeline::new(profiles))));
+    unwrap!(spawner.spawn(macro_storage()));
+    unwrap!(spawner.spawn(profile_storage()));
+    unwrap!(spawner.spawn(amiibo_storage()));
+    unwrap!(spawner.spawn(feedback::feedback()));
+
+    // the led blinks feedback on its own, a cyw43 that never comes up can't hold back usb
+    {
+        let pwr = Output::new(p.PIN_23, Level::Low);
+        let cs = Output::new(p.PIN_25, Level::High);
+        let mut pio = Pio::new(p.PIO0, Irqs);
+        let spi = PioSpi::new(
+            &mut pio.common,
+            pio.sm0,
+            pio.irq0,
+            cs,
+            p.PIN_24,
+            p.PIN_29,
+            p.DMA_CH0,
+        );
+        unwrap!(spawner.spawn(feedback::feedback_led(spawner, pwr, spi)));
+    }
 
     // spawns usb tasks
     {
         let usb = Driver::new(p.USB, Irqs);
 
+        let (vendor_id, product_id, manufacturer, product, report_descriptor) = match personality {
+            UsbPersonality::Switch => (
+                joycon_sys::NINTENDO_VENDOR_ID,
+                controller.product_id(),
+                "Nintendo Co., Ltd.",
+                controller.product_name(),
+                controller.hid_descriptor(),
+            ),
+            UsbPersonality::SimpleHid => (
+                HORI_VENDOR_ID,
+                HORI_PRODUCT_ID,
+                HORI_MANUFACTURER,
+                HORI_PRODUCT,
+                &HORI_HID_DESCRIPTOR[..],
+            ),
+            // no hid interface at all
+            UsbPersonality::XInput => (
+                XINPUT_VENDOR_ID,
+                XINPUT_PRODUCT_ID,
+                XINPUT_MANUFACTURER,
+                XINPUT_PRODUCT,
+                &[][..],
+            ),
+        };
+
         // Create embassy-usb Config
-        let mut config = Config::new(joycon_sys::NINTENDO_VENDOR_ID, joycon_sys::PRO_CONTROLLER);
-        config.manufacturer = Some("Nintendo Co., Ltd.");
-        config.product = Some("Pro Controller");
+        let mut config = Config::new(vendor_id, product_id);
+        config.manufacturer = Some(manufacturer);
+        config.product = Some(product);
         config.serial_number = Some("000000000001");
         config.max_packet_size_0 = 64;
         config.device_class = 0x00;
@@ -109,10 +213,17 @@ async fn main(spawner: Spawner) {
         config.max_power = 500;
         config.supports_remote_wakeup = true;
         config.self_powered = false;
+        if personality == UsbPersonality::XInput {
+            config.device_class = XINPUT_DEVICE_CLASS;
+            config.device_sub_class = XINPUT_DEVICE_CLASS;
+            config.device_protocol = XINPUT_DEVICE_CLASS;
+            config.device_release = XINPUT_DEVICE_RELEASE;
+        }
 
         // Create embassy-usb DeviceBuilder using the driver and config.
         // It needs some buffers for building the descriptors.
-        static CONFIG_DESCRIPTOR: StaticCell<[u8; 64]> = StaticCell::new();
+        // room for the pad's interface and the host interface
+        static CONFIG_DESCRIPTOR: StaticCell<[u8; 128]> = StaticCell::new();
         static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
 
         static STATE: StaticCell<hid::State> = StaticCell::new();
@@ -120,35 +231,67 @@ async fn main(spawner: Spawner) {
         let mut builder = Builder::new(
             usb,
             config,
-            CONFIG_DESCRIPTOR.init([0; 64]),
+            CONFIG_DESCRIPTOR.init([0; 128]),
             &mut [], // pro controller does not implement bos
             &mut [], // no msos descriptors
             CONTROL_BUF.init([0; 64]),
         );
 
+        static HOST_WATCH: StaticCell<HostWatch> = StaticCell::new();
+        builder.handler(HOST_WATCH.init(HostWatch::new(report_descriptor)));
+        let detector = HostDetector::new(personality, configured.is_none());
+        unwrap!(spawner.spawn(host_detection(watchdog, detector, last_host)));
+
+        // The host interface is still added in xinput mode, but the device class is 0xff
+        // like a real 360 pad, so Windows binds the whole device to xusb22 instead of
+        // splitting it into functions and the pc tool never sees the hid interface. Only
+        // hosts that bind per interface, like linux, reach it. To manage the library from
+        // Windows, pin the Switch or SimpleHid personality in the profile first.
+        if personality == UsbPersonality::XInput {
+            let (ep_in, ep_out) = xinput_endpoints(&mut builder);
+            let host = host_hid(&mut builder);
+            let mut usb = builder.build();
+            info!("Usb setup and running as {}", personality);
+            unwrap!(spawner.spawn(xinput_writer(ep_in)));
+            unwrap!(spawner.spawn(xinput_reader(ep_out)));
+            unwrap!(spawner.spawn(host_interface(host)));
+            usb.run().await;
+        }
+
         let config = hid::Config {
-            report_descriptor: &HID_DESCRIPTOR,
+            report_descriptor,
             request_handler: None,
             poll_ms: 0x08,
             max_packet_size: 64,
         };
 
-        let hid =
-            HidReaderWriter::<_, 64, 64>::new(&mut builder, STATE.init(hid::State::new()), config);
+        let hid = HidReaderWriter::<_, 64, HID_WRITE_LEN>::new(
+            &mut builder,
+            STATE.init(hid::State::new()),
+            config,
+        );
+        let host = host_hid(&mut builder);
 
         let mut usb = builder.build();
         let usb_fut = usb.run();
 
-        static CHANNEL: StaticCell<Channel<NoopRawMutex, [u8; 64], USB_RESPONSE_CHANNEL_SIZE>> =
+        static CHANNEL: StaticCell<Channel<NoopRawMutex, UsbReport, USB_RESPONSE_CHANNEL_SIZE>> =
             StaticCell::new();
         let channel =
-            CHANNEL.init(Channel::<NoopRawMutex, [u8; 64], USB_RESPONSE_CHANNEL_SIZE>::new());
+            CHANNEL.init(Channel::<NoopRawMutex, UsbReport, USB_RESPONSE_CHANNEL_SIZE>::new());
 
-        info!("Usb setup and running");
+        info!("Usb setup and running as {}", personality);
+        unwrap!(spawner.spawn(host_interface(host)));
         let (reader, writer) = hid.split();
-        unwrap!(spawner.spawn(hid_reader(reader, channel.sender())));
-        unwrap!(spawner.spawn(hid_writer(writer, channel.receiver())));
-        unwrap!(spawner.spawn(notify(channel.sender())));
+        match personality {
+            UsbPersonality::Switch => {
+                unwrap!(spawner.spawn(hid_reader(reader, channel.sender())));
+                unwrap!(spawner.spawn(hid_writer(writer, channel.receiver())));
+                unwrap!(spawner.spawn(notify(channel.sender())));
+            }
+            UsbPersonality::SimpleHid => unwrap!(spawner.spawn(hori_writer(writer))),
+            UsbPersonality::XInput => unreachable!(),
+        }
 
         usb_fut.await;
     }
@@ -157,7 +300,7 @@ async fn main(spawner: Spawner) {
 #[embassy_executor::task]
 async fn hid_reader(
     mut reader: HidReader<'static, Driver<'static, USB>, 64>,
-    channel: Sender<'static, NoopRawMutex, [u8; 64], USB_RESPONSE_CHANNEL_SIZE>,
+    channel: Sender<'static, NoopRawMutex, UsbReport, USB_RESPONSE_CHANNEL_SIZE>,
 ) -> ! {
     reader.ready().await;
     let mut output_report = joycon_sys::OutputReport::new();
@@ -167,9 +310,12 @@ async fn hid_reader(
             Ok(_) => {
                 // is handshaking packet
                 if buf[0] == 0x80 {
+                    send_event(HostEvent::Handshake(buf[1]));
                     match handshake_response(&buf) {
                         Some(resp) => {
-                            channel.send(resp.resp()).await;
+                            let controller =
+                                CONTROLLER_STATE.get().await.lock().await.controller_type();
+                            send_report(&channel, &resp.resp(controller)).await;
                             if let NintendoReportType::NoTimeout = resp {
                                 NOTIFY_SIGNAL.signal(true)
                             }
@@ -184,15 +330,8 @@ async fn hid_reader(
                     if let Ok(request) =
                         joycon_sys::output::OutputReportEnum::try_from(output_report)
                     {
-                        if let Some(report) = handle_request(request).await {
-                            channel
-                                .send(
-                                    joycon_sys::InputReport::from(report)
-                                        .as_bytes()
-                                        .try_into()
-                                        .expect("Not the enough bytes"),
-                                )
-                                .await;
+                        if let Some(report) = handle_request(request, &buf).await {
+                            send_report(&channel, report.as_bytes()).await;
                         }
                     }
                 }
@@ -205,56 +344,51 @@ async fn hid_reader(
 pub static NOTIFY_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
 
 #[embassy_executor::task]
-async fn notify(channel: Sender<'static, NoopRawMutex, [u8; 64], USB_RESPONSE_CHANNEL_SIZE>) -> ! {
+async fn notify(channel: Sender<'static, NoopRawMutex, UsbReport, USB_RESPONSE_CHANNEL_SIZE>) -> ! {
     // wait till handshakes are done
     NOTIFY_SIGNAL.wait().await;
     loop {
-        Timer::after_millis(8).await;
-        channel
-            .send(
-                CONTROLLER_STATE
-                    .get()
-                    .await
-                    .lock()
-                    .await
-                    .standard_full()
-                    .as_bytes()
-                    .try_into()
-                    .expect("Not the enough bytes"),
-            )
-            .await;
+        Timer::after_millis(REPORT_INTERVAL_MS).await;
+        let report = CONTROLLER_STATE.get().await.lock().await.standard_full();
+        send_report(&channel, report.as_bytes()).await;
     }
 }
 
-pub async fn switch_write(writer: &mut HidWriter<'static, Driver<'static, USB>, 64>, data: &[u8]) {
-    let mut buf: [u8; 64] = [0; 64];
-    for (idx, byte) in data.iter().enumerate() {
-        buf[idx] = *byte;
-    }
+// queues an input report for the hid writer
+async fn send_report(
+    channel: &Sender<'static, NoopRawMutex, UsbReport, USB_RESPONSE_CHANNEL_SIZE>,
+    report: &[u8],
+) {
+    channel.send(unwrap!(UsbReport::from_slice(report))).await
 }
 
 #[embassy_executor::task]
 async fn hid_writer(
-    mut writer: HidWriter<'static, Driver<'static, USB>, 64>,
-    channel: Receiver<'static, NoopRawMutex, [u8; 64], USB_RESPONSE_CHANNEL_SIZE>,
+    mut writer: HidWriter<'static, Driver<'static, USB>, HID_WRITE_LEN>,
+    channel: Receiver<'static, NoopRawMutex, UsbReport, USB_RESPONSE_CHANNEL_SIZE>,
 ) -> ! {
     writer.ready().await;
 
     // sends connection status
-    let report = joycon_sys::input::InputReportEnum::StandardAndSubcmd((
-        CONTROLLER_STATE.get().await.lock().await.standard(),
-        joycon_sys::input::SubcommandReply::from(
-            joycon_sys::input::SubcommandReplyEnum::RequestDeviceInfo(device_info()),
-        ),
-    ));
-    unwrap!(
-        writer
-            .write(&joycon_sys::InputReport::from(report).as_bytes())
-            .await
-    );
+    let report = {
+        let mut state = CONTROLLER_STATE.get().await.lock().await;
+        let controller = state.controller_type();
+        state.raw_reply(device_info(controller))
+    };
+    write_report(&mut writer, report.as_bytes()).await;
 
     loop {
-        unwrap!(writer.write(&channel.receive().await).await)
+        write_report(&mut writer, &channel.receive().await).await
+    }
+}
+
+// writes an input report packet by packet, so only the 0x31 ones take more than one
+async fn write_report(
+    writer: &mut HidWriter<'static, Driver<'static, USB>, HID_WRITE_LEN>,
+    report: &[u8],
+) {
+    for packet in report.chunks(HID_WRITE_LEN) {
+        unwrap!(writer.write(packet).await)
     }
 }
 
@@ -0,0 +1,151 @@
+use super::storage::{self, LAST_HOST_SLOT};
+use super::FLASH;
+use defmt::*;
+use embassy_rp::watchdog::Watchdog;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{with_deadline, Instant};
+use embassy_usb::control::{InResponse, Recipient, Request, RequestType};
+use embassy_usb::types::StringIndex;
+use embassy_usb::Handler;
+use embedded_storage::nor_flash::ReadNorFlash;
+
+use adapter_core::descriptor::HOST_HID_DESCRIPTOR;
+use adapter_core::detect::DESCRIPTOR_HID_REPORT;
+pub use adapter_core::detect::{Host, HostDetector, HostEvent, UsbPersonality};
+
+// The personality to come back as after a reset lives in a watchdog scratch register.
+// Those survive a reset but not a power cycle, so a fallback only lasts until the
+// adapter is unplugged.
+const SCRATCH_INDEX: usize = 0;
+const SCRATCH_MAGIC: u32 = 0x5045_0000;
+const SCRATCH_MAGIC_MASK: u32 = 0xFFFF_0000;
+
+const HOST_EVENT_CHANNEL_SIZE: usize = 8;
+
+// whatever the host did that hints at what it is
+pub static HOST_EVENTS: Channel<CriticalSectionRawMutex, HostEvent, HOST_EVENT_CHANNEL_SIZE> =
+    Channel::new();
+
+fn from_scratch(value: u32) -> Option<UsbPersonality> {
+    if value & SCRATCH_MAGIC_MASK != SCRATCH_MAGIC {
+        return None;
+    }
+    UsbPersonality::from_byte((value & !SCRATCH_MAGIC_MASK) as u8)
+}
+
+// the personality requested before the last reset, else `configured`. Each request is
+// used once.
+pub fn take_personality(watchdog: &mut Watchdog, configured: UsbPersonality) -> UsbPersonality {
+    let personality = from_scratch(watchdog.get_scratch(SCRATCH_INDEX));
+    watchdog.set_scratch(SCRATCH_INDEX, 0);
+    personality.unwrap_or(configured)
+}
+
+// resets the adapter so it enumerates again as `personality`
+pub fn reattach(personality: UsbPersonality, watchdog: &mut Watchdog) -> ! {
+    info!("reattaching as {}", personality);
+    watchdog.set_scratch(SCRATCH_INDEX, SCRATCH_MAGIC | personality as u32);
+    watchdog.trigger_reset();
+    loop {
+        cortex_m::asm::nop()
+    }
+}
+
+// reports the usb control requests HostDetector looks at
+#[derive(Debug)]
+pub struct HostWatch {
+    // report descriptor lengths by interface, the pad's first and then the host's
+    report_descriptors: [u16; 2],
+}
+
+impl HostWatch {
+    // takes the pad's report descriptor, empty when it has no hid interface
+    pub fn new(pad_descriptor: &[u8]) -> Self {
+        Self {
+            report_descriptors: [
+                pad_descriptor.len() as u16,
+                HOST_HID_DESCRIPTOR.len() as u16,
+            ],
+        }
+    }
+}
+
+impl Handler for HostWatch {
+    fn configured(&mut self, configured: bool) {
+        if configured {
+            send_event(HostEvent::Configured)
+        }
+    }
+
+    // Standard device requests never get here, but requests to an interface do. Only
+    // looks, every request still goes on to the class it is for.
+    fn control_in<'a>(&'a mut self, req: Request, _buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        if (req.request_type, req.recipient, req.request)
+            == (
+                RequestType::Standard,
+                Recipient::Interface,
+                Request::GET_DESCRIPTOR,
+            )
+            && (req.value >> 8) as u8 == DESCRIPTOR_HID_REPORT
+        {
+            if let Some(&length) = self.report_descriptors.get(req.index as usize) {
+                send_event(HostEvent::ReportDescriptor {
+                    requested: req.length,
+                    length,
+                })
+            }
+        }
+        None
+    }
+
+    fn get_string(&mut self, index: StringIndex, _lang_id: u16) -> Option<&str> {
+        send_event(HostEvent::UnknownString(index.into()));
+        None
+    }
+}
+
+pub fn send_event(event: HostEvent) {
+    if HOST_EVENTS.try_send(event).is_err() {
+        warn!("host events full, dropping {}", event);
+    }
+}
+
+pub fn load_last_host<F: ReadNorFlash>(flash: &mut F) -> Option<Host> {
+    let mut host = [0; 1];
+    match storage::load(flash, LAST_HOST_SLOT, &mut host) {
+        Ok(Some(&[byte])) => Host::from_byte(byte),
+        _ => None,
+    }
+}
+
+// watches the host, remembers what it is and reattaches when it wants something else
+#[embassy_executor::task]
+pub async fn host_detection(
+    mut watchdog: Watchdog,
+    mut detector: HostDetector,
+    mut last_host: Option<Host>,
+) -> ! {
+    loop {
+        let event = match detector.deadline() {
+            Some(deadline) => with_deadline(deadline, HOST_EVENTS.receive())
+                .await
+                .unwrap_or(HostEvent::Timeout),
+            None => HOST_EVENTS.receive().await,
+        };
+        debug!("host event {}", event);
+        let wanted = detector.observe(event, Instant::now());
+
+        if let Some(host) = detector.host().filter(|host| Some(*host) != last_host) {
+            let mut flash = FLASH.get().await.lock().await;
+            match storage::save_yielding(&mut *flash, LAST_HOST_SLOT, &[host as u8]).await {
+                Ok(()) => info!("host {} remembered", host),
+                Err(e) => warn!("failed to save host: {:?}", e),
+            }
+            last_host = Some(host);
+        }
+        if let Some(personality) = wanted {
+            reattach(personality, &mut watchdog)
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use super::accessibility::Accessibility;
+use super::amiibo::{AmiiboAction, AmiiboTrigger};
+use super::curve::StickConfig;
+use super::dpad::Dpad;
+use super::gamepad::GamepadState;
+use super::hotkey::Hotkeys;
+use super::input::XboxState;
+use super::layer::Layers;
+use super::mapping::Mapping;
+use super::motion::{MotionConfig, MotionInput, TiltStick};
+use super::profile::{Profile, ProfileSwitcher, Profiles, MAX_PROFILES};
+use super::ringcon::{RingConConfig, RingConInput};
+use super::trigger::Trigger;
+use defmt::*;
+use embassy_time::Instant;
+
+// turns raw xbox input into the state reported to the switch
+#[derive(Debug)]
+pub struct Pipeline {
+    profiles: Profiles,
+    switcher: ProfileSwitcher,
+    switched: bool,
+    amiibo: AmiiboTrigger,
+    amiibo_action: Option<AmiiboAction>,
+    pub hotkeys: Hotkeys,
+    pub layers: Layers,
+    pub left_stick: StickConfig,
+    pub right_stick: StickConfig,
+    pub left_trigger: Trigger,
+    pub right_trigger: Trigger,
+    pub mapping: Mapping,
+    pub motion: MotionConfig,
+    motion_input: MotionInput,
+    pub ringcon: RingConConfig,
+    ringcon_input: RingConInput,
+    pub accessibility: Accessibility,
+    pub dpad: Dpad,
+}
+
+impl Pipeline {
+    pub fn new(profiles: Profiles) -> Self {
+        let mut pipeline = Self {
+            profiles,
+            switcher: ProfileSwitcher::default(),
+            switched: false,
+            amiibo: AmiiboTrigger::default(),
+            amiibo_action: None,
+            hotkeys: Hotkeys::default(),
+            layers: Layers::default(),
+            left_stick: StickConfig::default(),
+            right_stick: StickConfig::default(),
+            left_trigger: Trigger::default(),
+            right_trigger: Trigger::default(),
+            mapping: Mapping::default(),
+            motion: MotionConfig::default(),
+            motion_input: MotionInput::default(),
+            ringcon: RingConConfig::default(),
+            ringcon_input: RingConInput::default(),
+            accessibility: Accessibility::default(),
+            dpad: Dpad::default(),
+        };
+        pipeline.load_profile();
+        pipeline
+    }
+
+    fn load_profile(&mut self) {
+        let profile = self.profiles.active();
+        self.hotkeys = Hotkeys::new(profile.hotkeys);
+        self.layers = Layers::new(profile.layers);
+        self.left_stick = profile.left_stick;
+        self.right_stick = profile.right_stick;
+        self.left_trigger = Trigger::new(profile.left_trigger);
+        self.right_trigger = Trigger::new(profile.right_trigger);
+        self.mapping = profile.mapping;
+        self.motion = profile.motion;
+        self.ringcon = profile.ringcon;
+        self.accessibility = Accessibility::new(profile.accessibility);
+        self.dpad = Dpad::new(profile.dpad);
+    }
+
+    pub fn profiles(&self) -> &Profiles {
+        &self.profiles
+    }
+
+    // swaps in a new set of profiles, staying on the active index if it still exists
+    pub fn set_profiles(&mut self, profiles: heapless::Vec<Profile, MAX_PROFILES>) {
+        self.profiles = Profiles::new(profiles, self.profiles.active_index());
+        self.load_profile();
+        self.switched = true;
+    }
+
+    // true once after the player switched profiles
+    pub fn take_switched(&mut self) -> bool {
+        core::mem::take(&mut self.switched)
+    }
+
+    // what the player last asked of the amiibo library, once
+    pub fn take_amiibo_action(&mut self) -> Option<AmiiboAction> {
+        self.amiibo_action.take()
+    }
+
+    // what the motion sensors should report for the last processed input
+    pub fn motion(&self) -> MotionInput {
+        self.motion_input
+    }
+
+    // how the ring-con is bent for the last processed input
+    pub fn ringcon(&self) -> RingConInput {
+        self.ringcon_input
+    }
+
+    // forgets every held back, latched or pending press, as if the pad had just been
+    // connected
+    pub fn release(&mut self) {
+        self.switcher = ProfileSwitcher::default();
+        self.amiibo = AmiiboTrigger::default();
+        self.amiibo_action = None;
+        self.load_profile();
+    }
+
+    // true while the output can change without new input
+    pub fn is_pending(&self) -> bool {
+        self.switcher.is_pending() || self.hotkeys.is_pending() || self.layers.is_pending()
+    }
+
+    pub fn process(&mut self, xbox: &XboxState, now: Instant) -> GamepadState {
+        let mut xbox = *xbox;
+        if let Some(forward) = self.switcher.input(&mut xbox, now) {
+            self.profiles.cycle(forward);
+            self.load_profile();
+            self.switched = true;
+            info!(
+                "switched to profile {}",
+                self.profiles.active().name.as_str()
+            );
+        }
+        if let Some(action) = self.amiibo.input(&mut xbox) {
+            self.amiibo_action = Some(action)
+        }
+        let hotkeys = self.hotkeys.input(&mut xbox, now);
+        let layers = self.layers.input(&mut xbox, now);
+
+        (xbox.left_x, xbox.left_y) = self.left_stick.process(xbox.left_x, xbox.left_y);
+        (xbox.right_x, xbox.right_y) = self.right_stick.process(xbox.right_x, xbox.right_y);
+        self.motion_input = self.motion_gestures(&mut xbox);
+        if self.ringcon.enabled {
+            self.ringcon_input = RingConInput::from_triggers(&mut xbox)
+        }
+        let triggers = (
+            self.left_trigger.update(xbox.left_trigger),
+            self.right_trigger.update(xbox.right_trigger),
+        );
+        let mapping = if self.layers.shifted() {
+            &self.layers.config.mapping
+        } else {
+            &self.mapping
+        };
+        let mut state = mapping.apply(&xbox, triggers);
+        state.buttons = state.buttons | hotkeys | layers;
+        let state = self.accessibility.process(state);
+        self.dpad.process(state)
+    }
+
+    // hides the shake and leg strap buttons and the tilt stick from `xbox`
+    fn motion_gestures(&self, xbox: &mut XboxState) -> MotionInput {
+        let mut input = MotionInput::default();
+        if let Some(shake) = self.motion.shake {
+            input.shake = xbox.buttons.is_pressed(shake);
+            xbox.buttons.set(shake, false);
+        }
+        if let Some(leg_strap) = self.motion.leg_strap {
+            input.jog = xbox.buttons.is_pressed(leg_strap);
+            xbox.buttons.set(leg_strap, false);
+        }
+        input.tilt = match self.motion.tilt {
+            TiltStick::Off => (0, 0),
+            TiltStick::Left => (
+                core::mem::take(&mut xbox.left_x),
+                core::mem::take(&mut xbox.left_y),
+            ),
+            TiltStick::Right => (
+                core::mem::take(&mut xbox.right_x),
+                core::mem::take(&mut xbox.right_y),
+            ),
+        };
+        input
+    }
+}
@@ -0,0 +1,246 @@
+use super::accessibility::AccessibilityConfig;
+use super::amiibo::AmiiboConfig;
+use super::controller::ControllerType;
+use super::curve::{Curve, StickConfig};
+use super::dpad::DpadConfig;
+use super::hotkey::HotkeyConfig;
+use super::layer::LayerConfig;
+use super::macros::MacroConfig;
+use super::mapping::Mapping;
+use super::motion::MotionConfig;
+use super::personality::UsbPersonality;
+use super::ringcon::RingConConfig;
+use super::storage::{self, FlashStorage, ACTIVE_PROFILE_SLOT, PROFILES_SLOT};
+use super::trigger::TriggerConfig;
+use super::turbo::TurboConfig;
+use super::FLASH;
+use adapter_core::retro::RetroController;
+use defmt::*;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use serde::{Deserialize, Serialize};
+
+pub use adapter_core::profile::ProfileSwitcher;
+
+// bump whenever `Profile` or anything it contains changes shape
+pub const PROFILE_FORMAT_VERSION: u8 = 19;
+pub const MAX_PROFILES: usize = 4;
+pub const PROFILE_NAME_LEN: usize = 16;
+pub const PROFILES_BYTES: usize = 2048;
+
+// raised with the new active profile index so it can be remembered across power cycles
+pub static PROFILE_SELECTED: Signal<CriticalSectionRawMutex, u8> = Signal::new();
+
+// raised with profiles the host uploaded and saved, for the input task to switch to
+pub static PROFILES_UPDATED: Signal<CriticalSectionRawMutex, heapless::Vec<Profile, MAX_PROFILES>> =
+    Signal::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub struct ControllerColors {
+    pub body: [u8; 3],
+    pub buttons: [u8; 3],
+    pub left_grip: [u8; 3],
+    pub right_grip: [u8; 3],
+}
+
+impl ControllerColors {
+    pub const PRO_CONTROLLER: ControllerColors = ControllerColors {
+        body: [0x32, 0x32, 0x32],
+        buttons: [0xFF, 0xFF, 0xFF],
+        left_grip: [0x32, 0x32, 0x32],
+        right_grip: [0x32, 0x32, 0x32],
+    };
+
+    // the spi block at 0x6050
+    pub fn bytes(&self) -> [u8; 12] {
+        let mut bytes = [0; 12];
+        bytes[0..3].copy_from_slice(&self.body);
+        bytes[3..6].copy_from_slice(&self.buttons);
+        bytes[6..9].copy_from_slice(&self.left_grip);
+        bytes[9..12].copy_from_slice(&self.right_grip);
+        bytes
+    }
+}
+
+impl Default for ControllerColors {
+    fn default() -> Self {
+        Self::PRO_CONTROLLER
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: heapless::String<PROFILE_NAME_LEN>,
+    pub mapping: Mapping,
+    pub hotkeys: HotkeyConfig,
+    pub layers: LayerConfig,
+    pub left_stick: StickConfig,
+    pub right_stick: StickConfig,
+    pub dpad: DpadConfig,
+    pub accessibility: AccessibilityConfig,
+    pub motion: MotionConfig,
+    pub ringcon: RingConConfig,
+    pub left_trigger: TriggerConfig,
+    pub right_trigger: TriggerConfig,
+    pub turbo: TurboConfig,
+    pub macros: MacroConfig,
+    pub amiibo: AmiiboConfig,
+    pub colors: ControllerColors,
+    // read at power up only, see `ControllerType`
+    pub controller: ControllerType,
+    // also read at power up only, None picks one for the last host seen. A fallback
+    // requested before a reset wins either way.
+    pub personality: Option<UsbPersonality>,
+}
+
+impl Profile {
+    pub fn new(name: &str, mapping: Mapping) -> Self {
+        let mut profile_name = heapless::String::new();
+        for c in name.chars() {
+            if profile_name.push(c).is_err() {
+                break;
+            }
+        }
+        Self {
+            name: profile_name,
+            mapping,
+            hotkeys: HotkeyConfig::default(),
+            layers: LayerConfig::default(),
+            left_stick: StickConfig::default(),
+            right_stick: StickConfig::default(),
+            dpad: DpadConfig::default(),
+            accessibility: AccessibilityConfig::default(),
+            motion: MotionConfig::default(),
+            ringcon: RingConConfig::default(),
+            left_trigger: TriggerConfig::default(),
+            right_trigger: TriggerConfig::default(),
+            turbo: TurboConfig::default(),
+            macros: MacroConfig::default(),
+            amiibo: AmiiboConfig::default(),
+            colors: ControllerColors::default(),
+            controller: ControllerType::default(),
+            personality: None,
+        }
+    }
+
+    // curves no stick could follow are dropped for a linear one
+    fn validate(&mut self) {
+        for stick in [&mut self.left_stick, &mut self.right_stick] {
+            if !stick.curve.is_valid() {
+                warn!(
+                    "profile {} has an invalid stick curve, using a linear one",
+                    self.name.as_str()
+                );
+                stick.curve = Curve::Linear;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profiles {
+    active: u8,
+    profiles: heapless::Vec<Profile, MAX_PROFILES>,
+}
+
+impl Profiles {
+    pub fn new(profiles: heapless::Vec<Profile, MAX_PROFILES>, active: u8) -> Self {
+        let mut profiles = Self { active, profiles };
+        if profiles.profiles.is_empty() {
+            profiles.profiles = Self::default().profiles;
+        }
+        profiles.select(active);
+        profiles
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active as usize]
+    }
+
+    pub fn active_index(&self) -> u8 {
+        self.active
+    }
+
+    pub fn list(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn select(&mut self, index: u8) {
+        self.active = if (index as usize) < self.profiles.len() {
+            index
+        } else {
+            0
+        }
+    }
+
+    pub fn cycle(&mut self, forward: bool) -> u8 {
+        let len = self.profiles.len() as u8;
+        self.active = if forward {
+            (self.active + 1) % len
+        } else {
+            (self.active + len - 1) % len
+        };
+        self.active
+    }
+
+    pub fn encode<'a>(&self, buf: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        postcard::to_slice(&(PROFILE_FORMAT_VERSION, &self.profiles), buf).ok()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<heapless::Vec<Profile, MAX_PROFILES>> {
+        let (version, rest) = postcard::take_from_bytes::<u8>(bytes).ok()?;
+        if version != PROFILE_FORMAT_VERSION {
+            warn!("ignoring profiles with format version {}", version);
+            return None;
+        }
+        let mut profiles: heapless::Vec<Profile, MAX_PROFILES> = postcard::from_bytes(rest).ok()?;
+        profiles.iter_mut().for_each(Profile::validate);
+        Some(profiles)
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        let mut profiles = heapless::Vec::new();
+        let _ = profiles.push(Profile::new("positional", Mapping::POSITIONAL));
+        let _ = profiles.push(Profile::new("by label", Mapping::BY_LABEL));
+        let _ = profiles.push(Profile {
+            controller: ControllerType::Retro(RetroController::N64),
+            ..Profile::new("n64", Mapping::N64)
+        });
+        Self {
+            active: 0,
+            profiles,
+        }
+    }
+}
+
+pub fn load_profiles(flash: &mut FlashStorage) -> Profiles {
+    let mut buf = [0; PROFILES_BYTES];
+    let profiles = match storage::load(flash, PROFILES_SLOT, &mut buf) {
+        Ok(Some(bytes)) => Profiles::decode(bytes).unwrap_or_default(),
+        Ok(None) => heapless::Vec::new(),
+        Err(e) => {
+            warn!("failed to read profiles from flash: {:?}", e);
+            heapless::Vec::new()
+        }
+    };
+    let mut active = [0; 1];
+    let active = match storage::load(flash, ACTIVE_PROFILE_SLOT, &mut active) {
+        Ok(Some(&[index])) => index,
+        _ => 0,
+    };
+    Profiles::new(profiles, active)
+}
+
+#[embassy_executor::task]
+pub async fn profile_storage() -> ! {
+    loop {
+        let index = PROFILE_SELECTED.wait().await;
+        let mut flash = FLASH.get().await.lock().await;
+        match storage::save_yielding(&mut *flash, ACTIVE_PROFILE_SLOT, &[index]).await {
+            Ok(()) => info!("profile {} remembered", index),
+            Err(e) => warn!("failed to save active profile: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use embassy_rp::flash::{Blocking, Flash};
+use embassy_rp::peripherals::FLASH;
+
+pub use adapter_core::storage::{
+    amiibo_slot, clear, load, save_yielding, SaveError, Slot, ACTIVE_AMIIBO_SLOT,
+    ACTIVE_PROFILE_SLOT, AMIIBO_SLOTS, LAST_HOST_SLOT, MACRO_SLOT, PROFILES_SLOT, SECTOR_SIZE,
+};
+
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+pub type FlashStorage = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
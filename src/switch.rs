@@ -1,18 +1,69 @@
+use super::amiibo::{Tag, AMIIBO_WRITTEN};
+use super::controller::ControllerType;
+use super::gamepad::{Button, GamepadState, StickPosition};
+use super::macros::{MacroEngine, MACRO_RECORDED};
+use super::mcu::{
+    conf_reply, Mcu, IR_REQUEST_LEN, MCU_COMMAND_LEN, MCU_REPORT_LEN, NFC_REQUEST_LEN,
+};
+use super::motion::{sensor_calibration, Motion, MotionInput};
+use super::pad::Pad;
+use super::profile::{ControllerColors, Profile};
+use super::report::{self, DeviceInfo, RawReply, IMU_LEN, MCU, REPLY_DATA_LEN};
+use super::ringcon::{
+    RingCon, RingConInput, ACCESSORY, ACCESSORY_COMMAND_LEN, EXT_CONFIGURE, EXT_DEVICE_INFO,
+    EXT_POLLING_OFF, EXT_POLLING_ON,
+};
+use super::stick::factory_calibration;
+use super::turbo::TurboConfig;
 use super::{CONTROLLER_STATE, NOTIFY_SIGNAL};
 use defmt::*;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use joycon_sys::imu::Frame;
 use joycon_sys::input::*;
 use joycon_sys::mcu::*;
 use joycon_sys::output::*;
-use joycon_sys::spi::*;
+use joycon_sys::spi::SPIWriteResult;
 use joycon_sys::U16LE;
 
-pub fn device_info() -> DeviceInfo {
-    DeviceInfo::new(
-        FirmwareVersion([0x03, 0x48]),
-        WhichController::ProController.try_into().unwrap(),
-        MACAddress([0xDC, 0x68, 0xEB, 0xED, 0x5C, 0x79]),
-        UseSPIColors::No.into(),
-    )
+// subcommands answered with data, and the acks those replies carry
+const REQUEST_DEVICE_INFO: u8 = 0x02;
+const SPI_READ: u8 = 0x10;
+const SET_MCU_CONF: u8 = 0x21;
+const ACK_DEVICE_INFO: u8 = 0x82;
+const ACK_SPI_READ: u8 = 0x90;
+const ACK_MCU_CONF: u8 = 0xa0;
+
+const FIRMWARE_VERSION: [u8; 2] = [0x03, 0x48];
+const MAC_ADDRESS: [u8; 6] = [0xDC, 0x68, 0xEB, 0xED, 0x5C, 0x79];
+
+// spi flash regions the switch reads, see `spi_read`
+const SPI_USE_COLORS: u32 = 0x601B;
+const SPI_SENSOR_CALIBRATION: u32 = 0x6020;
+const SPI_STICKS_CALIBRATION: u32 = 0x603D;
+const SPI_COLORS: u32 = 0x6050;
+const SPI_USER_STICKS_CALIBRATION: u32 = 0x8010;
+const SPI_USER_SENSOR_CALIBRATION: u32 = 0x8026;
+// erased, so the switch falls back to the factory calibration
+const NO_USER_STICKS_CALIBRATION: [u8; 22] = [0xFF; 22];
+const NO_USER_SENSOR_CALIBRATION: [u8; 26] = [0xFF; 26];
+// a read reply repeats the address and length before the data
+const SPI_READ_HEADER_LEN: usize = 5;
+
+pub fn device_info(controller: ControllerType) -> RawReply {
+    let info = DeviceInfo {
+        firmware: FIRMWARE_VERSION,
+        kind: controller.kind(),
+        mac: MAC_ADDRESS,
+        colors: controller.spi_colors(),
+    };
+    match controller {
+        ControllerType::ChargingGrip => {
+            RawReply::new(ACK_DEVICE_INFO, REQUEST_DEVICE_INFO, &info.pair())
+        }
+        _ => RawReply::new(ACK_DEVICE_INFO, REQUEST_DEVICE_INFO, &info.bytes()),
+    }
 }
 
 pub fn handshake_response(msg: &[u8]) -> Option<NintendoReportType> {
@@ -38,12 +89,13 @@ pub enum NintendoReportType {
 }
 
 impl NintendoReportType {
-    pub fn resp(&self) -> [u8; 64] {
+    pub fn resp(&self, controller: ControllerType) -> [u8; 64] {
         let mut resp = [0; 64];
         match self {
             NintendoReportType::Status => {
                 resp[..10]
                     .copy_from_slice(&[0x81, 0x1, 0x0, 0x3, 0x79, 0x5c, 0xed, 0xeb, 0x68, 0xdc]);
+                resp[3] = controller.kind() as u8;
                 resp
             }
             NintendoReportType::Handshake => {
@@ -64,27 +116,135 @@ impl NintendoReportType {
     }
 }
 
+// signalled with the pad state the switch sees whenever it changes
+pub static STATE_CHANGED: Signal<CriticalSectionRawMutex, GamepadState> = Signal::new();
+
 #[derive(Debug)]
 pub struct ControllerState {
     timer: u8,
-    buttons: ButtonsStatus,
-    left_stick: Stick,
-    right_stick: Stick,
+    pad: Pad,
     status: DeviceStatus,
+    motion: Motion,
+    // the motion frames of the last report
+    imu: [u8; IMU_LEN],
+    mcu: Mcu,
+    ringcon: RingCon,
+    colors: ControllerColors,
+    controller: ControllerType,
 }
 
 impl ControllerState {
     pub fn new() -> Self {
         Self {
             timer: 0,
-            buttons: Default::default(),
-            left_stick: Stick::new(),
-            right_stick: Stick::new(),
+            pad: Pad::new(),
             status: DeviceStatus(0),
+            motion: Motion::default(),
+            imu: [0; IMU_LEN],
+            mcu: Mcu::default(),
+            ringcon: RingCon::default(),
+            colors: ControllerColors::default(),
+            controller: ControllerType::default(),
+        }
+    }
+
+    pub fn snapshot(&self) -> GamepadState {
+        self.pad.snapshot()
+    }
+
+    pub fn apply(&mut self, state: GamepadState) {
+        self.pad.apply(state);
+        self.notify()
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.pad.set_button(button, pressed);
+        self.notify()
+    }
+
+    pub fn set_left_stick(&mut self, position: StickPosition) {
+        self.pad.set_left_stick(position);
+        self.notify()
+    }
+
+    pub fn set_right_stick(&mut self, position: StickPosition) {
+        self.pad.set_right_stick(position);
+        self.notify()
+    }
+
+    // tells listeners about a changed pad state and flash about a finished recording
+    fn notify(&mut self) {
+        if let Some(state) = self.pad.take_change() {
+            STATE_CHANGED.signal(state)
         }
+        if self.pad.macros.take_recorded() {
+            MACRO_RECORDED.signal(())
+        }
+    }
+
+    pub fn set_turbo(&mut self, config: TurboConfig) {
+        self.pad.turbo.config = config
+    }
+
+    pub fn set_motion(&mut self, mut input: MotionInput) {
+        input.jog &= self.controller.has_left_joycon();
+        self.motion.set_input(input)
+    }
+
+    pub fn set_ringcon(&mut self, input: RingConInput) {
+        self.ringcon.input(input)
+    }
+
+    pub fn ringcon_mut(&mut self) -> &mut RingCon {
+        &mut self.ringcon
+    }
+
+    pub fn place_amiibo(&mut self, tag: Tag) {
+        self.mcu.place_tag(tag)
     }
 
-    pub fn standard(&mut self) -> StandardInputReport {
+    pub fn mcu_mut(&mut self) -> &mut Mcu {
+        &mut self.mcu
+    }
+
+    pub fn set_profile(&mut self, profile: &Profile) {
+        self.pad.turbo.config = profile.turbo;
+        self.pad.macros.config = profile.macros;
+        self.colors = profile.colors;
+        self.ringcon.attached = profile.ringcon.enabled && self.controller.has_right_joycon();
+    }
+
+    // only called at power up, the usb identity can't change while plugged in
+    pub fn set_controller_type(&mut self, controller: ControllerType) {
+        self.controller = controller
+    }
+
+    pub fn controller_type(&self) -> ControllerType {
+        self.controller
+    }
+
+    pub fn colors(&self) -> ControllerColors {
+        self.colors
+    }
+
+    pub fn macros(&self) -> &MacroEngine {
+        &self.pad.macros
+    }
+
+    pub fn macros_mut(&mut self) -> &mut MacroEngine {
+        &mut self.pad.macros
+    }
+
+    // releases every button and centers both sticks, bypassing macros and turbo so
+    // neither can press anything again
+    pub fn neutral(&mut self) {
+        self.pad.neutral();
+        self.notify()
+    }
+
+    // the timer and status of the next report, the pad state is written over the
+    // placeholders by `write_input`
+    fn standard(&mut self) -> StandardInputReport {
         if self.timer == 255 {
             self.timer = 0
         } else {
@@ -96,30 +256,94 @@ impl ControllerState {
         StandardInputReport {
             timer: self.timer,
             info: self.status,
-            buttons: self.buttons,
-            left_stick: self.left_stick,
-            right_stick: self.right_stick,
+            buttons: ButtonsStatus::default(),
+            left_stick: Stick::new(),
+            right_stick: Stick::new(),
             vibrator: 0,
         }
     }
 
+    fn write_input(&self, report: &mut [u8]) {
+        report::write_input(report, &self.controller.layout(self.output()))
+    }
+
+    // the report sent every interval, 0x31 once the switch woke up the mcu or polls the
+    // ring-con and 0x30 otherwise, moving the motion sensors, macros and turbo on by one
+    // report
     pub fn standard_full(&mut self) -> InputReport {
-        InputReportEnum::StandardFull((
+        self.imu = self.motion.frames();
+        let report = self.full_report();
+        self.advance();
+        report
+    }
+
+    // Answers an mcu request right away. The motion frames are the ones of the last
+    // report and nothing is stepped, so macros and turbo keep their pace however often
+    // the switch asks.
+    pub fn mcu_reply(&mut self) -> InputReport {
+        self.full_report()
+    }
+
+    fn full_report(&mut self) -> InputReport {
+        let standard = self.standard();
+        // once the switch woke up the mcu it expects its data in every report
+        let mcu = if self.mcu.is_active() {
+            Some(self.mcu.report(Instant::now()))
+        } else {
+            self.ringcon.report()
+        };
+        let frames = [Frame::default(); 3];
+        let mut report: InputReport = match mcu {
+            Some(_) => {
+                InputReportEnum::StandardFullMCU((standard, frames, MCUReport::new())).into()
+            }
+            None => InputReportEnum::StandardFull((standard, frames)).into(),
+        };
+        let bytes = report.as_bytes_mut();
+        self.write_input(bytes);
+        report::write_imu(bytes, &self.imu);
+        if let Some(mcu) = mcu {
+            bytes[MCU..MCU + MCU_REPORT_LEN].copy_from_slice(&mcu);
+        }
+        report
+    }
+
+    // a 0x21 report answering a subcommand
+    pub fn reply(&mut self, reply: SubcommandReplyEnum) -> InputReport {
+        let mut report = InputReport::from(InputReportEnum::StandardAndSubcmd((
             self.standard(),
-            [
-                joycon_sys::imu::Frame::default(),
-                joycon_sys::imu::Frame::default(),
-                joycon_sys::imu::Frame::default(),
-            ],
-        ))
-        .into()
+            reply.into(),
+        )));
+        self.write_input(report.as_bytes_mut());
+        report
+    }
+
+    // a 0x21 report with a reply the joycon-sys reply types have no room for
+    pub fn raw_reply(&mut self, reply: RawReply) -> InputReport {
+        let mut report = self.reply(SubcommandReplyEnum::GetOnlyControllerState(()));
+        reply.patch(report.as_bytes_mut());
+        report
+    }
+
+    // the full pad state with macro playback and turbo applied
+    pub fn output(&self) -> GamepadState {
+        self.pad.output()
+    }
+
+    // moves macros and turbo on by one report
+    pub fn advance(&mut self) {
+        self.pad.advance();
+        self.notify()
     }
 }
 
-pub async fn handle_request(request: OutputReportEnum) -> Option<InputReport> {
-    let report = match request {
+// `raw` is the output report as it arrived, request arguments are read from it
+pub async fn handle_request(request: OutputReportEnum, raw: &[u8]) -> Option<InputReport> {
+    match request {
         OutputReportEnum::RumbleAndSubcmd(subcommand_request) => {
             if let Ok(cmd) = SubcommandRequestEnum::try_from(subcommand_request) {
+                // replies with data are built as bytes and patched over the typed one
+                let mut raw_reply = None;
                 let reply = match cmd {
                     SubcommandRequestEnum::GetOnlyControllerState(_) => {
                         Some(SubcommandReplyEnum::GetOnlyControllerState(()))
@@ -128,72 +352,91 @@ pub async fn handle_request(request: OutputReportEnum) -> Option<InputReport> {
                         Some(SubcommandReplyEnum::BluetoothManualPairing(()))
                     }
                     SubcommandRequestEnum::RequestDeviceInfo(_) => {
-                        Some(SubcommandReplyEnum::RequestDeviceInfo(device_info()))
+                        let controller =
+                            CONTROLLER_STATE.get().await.lock().await.controller_type();
+                        raw_reply = Some(device_info(controller));
+                        Some(SubcommandReplyEnum::GetOnlyControllerState(()))
                     }
-                    SubcommandRequestEnum::SetInputReportMode(raw_id) => {
+                    SubcommandRequestEnum::SetInputReportMode(_) => {
                         Some(SubcommandReplyEnum::SetInputReportMode(()))
                     }
                     SubcommandRequestEnum::GetTriggerButtonsElapsedTime(_) => Some(
                         SubcommandReplyEnum::GetTriggerButtonsElapsedTime([U16LE::default(); 7]),
                     ),
-                    SubcommandRequestEnum::SetShipmentMode(raw_id) => {
+                    SubcommandRequestEnum::SetShipmentMode(_) => {
                         Some(SubcommandReplyEnum::SetShipmentMode(()))
                     }
-                    SubcommandRequestEnum::SPIRead(spiread_request) => {
-                        match handle_spi_read(spiread_request.range().offset()) {
-                            Some(spi_res) => Some(SubcommandReplyEnum::SPIRead(spi_res)),
-                            None => None,
-                        }
+                    SubcommandRequestEnum::SPIRead(_) => {
+                        let [a, b, c, d, len] = report::args::<5>(raw);
+                        let (colors, controller) = {
+                            let controller = CONTROLLER_STATE.get().await.lock().await;
+                            (controller.colors(), controller.controller_type())
+                        };
+                        raw_reply =
+                            spi_read(u32::from_le_bytes([a, b, c, d]), len, colors, controller);
+                        raw_reply.map(|_| SubcommandReplyEnum::GetOnlyControllerState(()))
                     }
-                    SubcommandRequestEnum::SPIWrite(spiwrite_request) => {
+                    SubcommandRequestEnum::SPIWrite(_) => {
                         Some(SubcommandReplyEnum::SPIWrite(SPIWriteResult::new(0)))
                     }
-                    SubcommandRequestEnum::SetMCUConf(mcucommand) => {
-                        Some(SubcommandReplyEnum::SetMCUConf(MCUReport::new()))
+                    SubcommandRequestEnum::SetMCUConf(_) => {
+                        let command = report::args::<MCU_COMMAND_LEN>(raw);
+                        let mut controller = CONTROLLER_STATE.get().await.lock().await;
+                        let reply = conf_reply(&controller.mcu_mut().configure(&command));
+                        raw_reply = Some(RawReply::new(ACK_MCU_CONF, SET_MCU_CONF, &reply));
+                        Some(SubcommandReplyEnum::GetOnlyControllerState(()))
                     }
-                    SubcommandRequestEnum::SetMCUState(raw_id) => {
+                    SubcommandRequestEnum::SetMCUState(_) => {
+                        let [state] = report::args::<1>(raw);
+                        let mut controller = CONTROLLER_STATE.get().await.lock().await;
+                        controller.mcu_mut().set_state(state);
                         Some(SubcommandReplyEnum::SetMCUState(()))
                     }
                     SubcommandRequestEnum::SetUnknownData(_) => {
                         Some(SubcommandReplyEnum::SetUnknownData(()))
                     }
-                    SubcommandRequestEnum::SetPlayerLights(player_lights) => {
+                    SubcommandRequestEnum::SetPlayerLights(_) => {
                         Some(SubcommandReplyEnum::SetPlayerLights(()))
                     }
-                    SubcommandRequestEnum::SetHomeLight(home_light) => {
+                    SubcommandRequestEnum::SetHomeLight(_) => {
                         Some(SubcommandReplyEnum::SetHomeLight(()))
                     }
-                    SubcommandRequestEnum::SetIMUMode(raw_id) => {
+                    SubcommandRequestEnum::SetIMUMode(_) => {
                         Some(SubcommandReplyEnum::SetIMUMode(()))
                     }
-                    SubcommandRequestEnum::SetIMUSens(sensitivity) => {
+                    SubcommandRequestEnum::SetIMUSens(_) => {
                         Some(SubcommandReplyEnum::SetIMUSens(()))
                     }
-                    SubcommandRequestEnum::EnableVibration(raw_id) => {
+                    SubcommandRequestEnum::EnableVibration(_) => {
                         Some(SubcommandReplyEnum::EnableVibration(()))
                     }
-                    SubcommandRequestEnum::MaybeAccessory(accessory_command) => None,
+                    SubcommandRequestEnum::MaybeAccessory(_) => {
+                        raw_reply = ringcon_subcommand(ACCESSORY, raw).await;
+                        raw_reply.map(|_| SubcommandReplyEnum::Unknown0x59(()))
+                    }
                     SubcommandRequestEnum::Unknown0x59(_) => {
+                        raw_reply = ringcon_subcommand(EXT_DEVICE_INFO, raw).await;
                         Some(SubcommandReplyEnum::Unknown0x59(()))
                     }
                     SubcommandRequestEnum::Unknown0x5a(_) => {
+                        raw_reply = ringcon_subcommand(EXT_POLLING_ON, raw).await;
                         Some(SubcommandReplyEnum::Unknown0x5a(()))
                     }
                     SubcommandRequestEnum::Unknown0x5b(_) => {
+                        raw_reply = ringcon_subcommand(EXT_POLLING_OFF, raw).await;
                         Some(SubcommandReplyEnum::Unknown0x5b(()))
                     }
                     SubcommandRequestEnum::Unknown0x5c(_) => {
+                        raw_reply = ringcon_subcommand(EXT_CONFIGURE, raw).await;
                         Some(SubcommandReplyEnum::Unknown0x5c(()))
                     }
                 };
-                if let Some(reply) = reply {
-                    Some(InputReportEnum::StandardAndSubcmd((
-                        CONTROLLER_STATE.get().await.lock().await.standard(),
-                        reply.into(),
-                    )))
-                } else {
-                    None
-                }
+                let reply = reply?;
+                let mut controller = CONTROLLER_STATE.get().await.lock().await;
+                Some(match raw_reply {
+                    Some(raw_reply) => controller.raw_reply(raw_reply),
+                    None => controller.reply(reply),
+                })
             } else {
                 warn!("could not read subcommand request");
                 None
@@ -203,153 +446,81 @@ pub async fn handle_request(request: OutputReportEnum) -> Option<InputReport> {
         OutputReportEnum::RumbleOnly(_) => None,
         OutputReportEnum::RequestMCUData(mcurequest) => {
             if let Ok(request) = MCURequestEnum::try_from(mcurequest) {
+                let mut controller = CONTROLLER_STATE.get().await.lock().await;
                 match request {
-                    MCURequestEnum::GetMCUStatus(_) => None,
-                    MCURequestEnum::GetNFCData(_) => None,
-                    MCURequestEnum::GetIRData(_irrequest) => None,
+                    MCURequestEnum::GetMCUStatus(_) => controller.mcu_mut().request_status(),
+                    MCURequestEnum::GetNFCData(_) => {
+                        let request = report::args::<NFC_REQUEST_LEN>(raw);
+                        controller.mcu_mut().request_nfc(&request, Instant::now());
+                        if let Some(written) = controller.mcu_mut().take_written() {
+                            AMIIBO_WRITTEN.signal(written)
+                        }
+                    }
+                    MCURequestEnum::GetIRData(_) => {
+                        let request = report::args::<IR_REQUEST_LEN>(raw);
+                        controller.mcu_mut().request_ir(&request)
+                    }
                 }
+                Some(controller.mcu_reply())
             } else {
                 warn!("Failed to read mcu report");
                 None
             }
         }
-    };
-
-    if let Some(report) = report {
-        Some(InputReport::from(report))
-    } else {
-        None
     }
 }
 
-fn spi_in_range(addr: u32, range: SPIRange) -> bool {
-    addr >= range.offset() && addr < range.offset() + range.size() as u32
+async fn ringcon_subcommand(id: u8, raw: &[u8]) -> Option<RawReply> {
+    let args = report::args::<ACCESSORY_COMMAND_LEN>(raw);
+    let mut controller = CONTROLLER_STATE.get().await.lock().await;
+    controller.ringcon_mut().subcommand(id, &args)
 }
 
-fn handle_spi_read(addr: u32) -> Option<SPIReadResult> {
+// Serves `len` bytes of spi flash from `addr`. Reads have to start inside one of the
+// regions below, anything past them reads back erased.
+fn spi_read(
+    addr: u32,
+    len: u8,
+    colors: ControllerColors,
+    controller: ControllerType,
+) -> Option<RawReply> {
     info!("spi read addr: {:x}", addr);
-    if spi_in_range(addr, SensorCalibration::range()) {
-        info!("Sending factory motion calibration");
-        Some(SensorCalibration::default().into())
-    } else if spi_in_range(addr, UserSensorCalibration::range()) {
-        info!("Sending user motion calibration");
-        Some(UserSensorCalibration::default().into())
-    } else if spi_in_range(addr, SticksCalibration::range()) {
-        info!("Sending factory stick calibration");
-        Some(SticksCalibration::default().into())
-    } else if spi_in_range(addr, UserSticksCalibration::range()) {
-        info!("Sending user stick calibration");
-        Some(
-            UserSticksCalibration {
-                left: LeftUserStickCalibration::default(),
-                right: RightUserStickCalibration::default(),
-            }
-            .into(),
-        )
-    } else if spi_in_range(addr, UseSPIColors::range()) {
-        Some(UseSPIColors::No.into())
-    } else if spi_in_range(addr, ControllerColor::range()) {
-        Some(ControllerColor::default().into())
-    } else {
+    let sensor = sensor_calibration();
+    let sticks = factory_calibration();
+    let colors = colors.bytes();
+    let use_colors = [controller.spi_colors() as u8];
+    let regions: [(u32, &[u8]); 6] = [
+        (SPI_USE_COLORS, &use_colors),
+        (SPI_SENSOR_CALIBRATION, &sensor),
+        (SPI_STICKS_CALIBRATION, &sticks),
+        (SPI_COLORS, &colors),
+        (SPI_USER_STICKS_CALIBRATION, &NO_USER_STICKS_CALIBRATION),
+        (SPI_USER_SENSOR_CALIBRATION, &NO_USER_SENSOR_CALIBRATION),
+    ];
+    let byte = |addr: u32| {
+        regions.iter().find_map(|(start, bytes)| {
+            let offset = addr.checked_sub(*start)? as usize;
+            bytes.get(offset).copied()
+        })
+    };
+    if byte(addr).is_none() {
         warn!("Failed to read spi read address: {:x}", addr);
-        None
+        return None;
     }
-}
 
-pub static HID_DESCRIPTOR: [u8; 203] = [
-    0x05, 0x01, // Usage Page (Generic Desktop Ctrls)
-    0x15, 0x00, // Logical Minimum (0)
-    0x09, 0x04, // Usage (Joystick)
-    0xA1, 0x01, // Collection (Application)
-    0x85, 0x30, //   Report ID (48)
-    0x05, 0x01, //   Usage Page (Generic Desktop Ctrls)
-    0x05, 0x09, //   Usage Page (Button)
-    0x19, 0x01, //   Usage Minimum (0x01)
-    0x29, 0x0A, //   Usage Maximum (0x0A)
-    0x15, 0x00, //   Logical Minimum (0)
-    0x25, 0x01, //   Logical Maximum (1)
-    0x75, 0x01, //   Report Size (1)
-    0x95, 0x0A, //   Report Count (10)
-    0x55, 0x00, //   Unit Exponent (0)
-    0x65, 0x00, //   Unit (None)
-    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x05, 0x09, //   Usage Page (Button)
-    0x19, 0x0B, //   Usage Minimum (0x0B)
-    0x29, 0x0E, //   Usage Maximum (0x0E)
-    0x15, 0x00, //   Logical Minimum (0)
-    0x25, 0x01, //   Logical Maximum (1)
-    0x75, 0x01, //   Report Size (1)
-    0x95, 0x04, //   Report Count (4)
-    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x75, 0x01, //   Report Size (1)
-    0x95, 0x02, //   Report Count (2)
-    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x0B, 0x01, 0x00, 0x01, 0x00, //   Usage (0x010001)
-    0xA1, 0x00, //   Collection (Physical)
-    0x0B, 0x30, 0x00, 0x01, 0x00, //     Usage (0x010030)
-    0x0B, 0x31, 0x00, 0x01, 0x00, //     Usage (0x010031)
-    0x0B, 0x32, 0x00, 0x01, 0x00, //     Usage (0x010032)
-    0x0B, 0x35, 0x00, 0x01, 0x00, //     Usage (0x010035)
-    0x15, 0x00, //     Logical Minimum (0)
-    0x27, 0xFF, 0xFF, 0x00, 0x00, //     Logical Maximum (65534)
-    0x75, 0x10, //     Report Size (16)
-    0x95, 0x04, //     Report Count (4)
-    0x81, 0x02, //     Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0xC0, //   End Collection
-    0x0B, 0x39, 0x00, 0x01, 0x00, //   Usage (0x010039)
-    0x15, 0x00, //   Logical Minimum (0)
-    0x25, 0x07, //   Logical Maximum (7)
-    0x35, 0x00, //   Physical Minimum (0)
-    0x46, 0x3B, 0x01, //   Physical Maximum (315)
-    0x65, 0x14, //   Unit (System: English Rotation, Length: Centimeter)
-    0x75, 0x04, //   Report Size (4)
-    0x95, 0x01, //   Report Count (1)
-    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x05, 0x09, //   Usage Page (Button)
-    0x19, 0x0F, //   Usage Minimum (0x0F)
-    0x29, 0x12, //   Usage Maximum (0x12)
-    0x15, 0x00, //   Logical Minimum (0)
-    0x25, 0x01, //   Logical Maximum (1)
-    0x75, 0x01, //   Report Size (1)
-    0x95, 0x04, //   Report Count (4)
-    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x34, //   Report Count (52)
-    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x06, 0x00, 0xFF, //   Usage Page (Vendor Defined 0xFF00)
-    0x85, 0x21, //   Report ID (33)
-    0x09, 0x01, //   Usage (0x01)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x3F, //   Report Count (63)
-    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x85, 0x81, //   Report ID (-127)
-    0x09, 0x02, //   Usage (0x02)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x3F, //   Report Count (63)
-    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    0x85, 0x01, //   Report ID (1)
-    0x09, 0x03, //   Usage (0x03)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x3F, //   Report Count (63)
-    0x91,
-    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
-    0x85, 0x10, //   Report ID (16)
-    0x09, 0x04, //   Usage (0x04)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x3F, //   Report Count (63)
-    0x91,
-    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
-    0x85, 0x80, //   Report ID (-128)
-    0x09, 0x05, //   Usage (0x05)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x3F, //   Report Count (63)
-    0x91,
-    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
-    0x85, 0x82, //   Report ID (-126)
-    0x09, 0x06, //   Usage (0x06)
-    0x75, 0x08, //   Report Size (8)
-    0x95, 0x3F, //   Report Count (63)
-    0x91,
-    0x83, //   Output (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Volatile)
-    0xC0, // End Collection
-];
+    let len = (len as usize).min(REPLY_DATA_LEN - SPI_READ_HEADER_LEN);
+    let mut data = [0; REPLY_DATA_LEN];
+    data[..4].copy_from_slice(&addr.to_le_bytes());
+    data[4] = len as u8;
+    for (idx, value) in data[SPI_READ_HEADER_LEN..SPI_READ_HEADER_LEN + len]
+        .iter_mut()
+        .enumerate()
+    {
+        *value = byte(addr + idx as u32).unwrap_or(0xFF);
+    }
+    Some(RawReply::new(
+        ACK_SPI_READ,
+        SPI_READ,
+        &data[..SPI_READ_HEADER_LEN + len],
+    ))
+}
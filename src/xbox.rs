@@ -1,17 +1,36 @@
 use bt_hci::controller::ExternalController;
 use defmt::*;
 use embassy_futures::join::join3;
-use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+use embassy_sync::channel::Channel;
 use embassy_time::{Duration, Timer};
 use static_cell::StaticCell;
 use trouble_host::advertise::{
     AdStructure, Advertisement, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE,
 };
-use trouble_host::attribute::{AttributeTable, CharacteristicProp, Service, Uuid};
-use trouble_host::gatt::GattEvent;
+use trouble_host::attribute::{AttributeTable, Characteristic, CharacteristicProp, Service, Uuid};
+use trouble_host::connection::Connection;
+use trouble_host::gatt::{GattEvent, GattServer};
 use trouble_host::{Address, BleHost, BleHostResources, PacketQos};
+
+use super::feedback::XBOX_OUTPUT_LEN;
+use super::input::XBOX_REPORT_LEN;
+use super::link::{run, LinkConfig, Transport};
 use {defmt_rtt as _, panic_probe as _};
 
+const RAW_REPORT_CHANNEL_SIZE: usize = 4;
+// four services, five characteristics and the client configuration of the two notifying
+// ones
+const ATTRIBUTES: usize = 16;
+
+// input reports the controller wrote, handed from the gatt event loop to `BleLink::poll`
+static RAW_REPORTS: Channel<
+    CriticalSectionRawMutex,
+    [u8; XBOX_REPORT_LEN],
+    RAW_REPORT_CHANNEL_SIZE,
+> = Channel::new();
+
 pub async fn bluetooth_setup(bt_device: cyw43::bluetooth::BtDriver<'static>) {
     let controller: ExternalController<_, 10> = ExternalController::new(bt_device);
     static HOST_RESOURCES: StaticCell<BleHostResources<4, 32, 27>> = StaticCell::new();
@@ -20,13 +39,15 @@ pub async fn bluetooth_setup(bt_device: cyw43::bluetooth::BtDriver<'static>) {
     let mut ble: BleHost<'_, _> = BleHost::new(controller, host_resources);
 
     ble.set_random_address(Address::random([0xff, 0x9f, 0x1a, 0x05, 0xe4, 0xff]));
-    let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+    let mut table: AttributeTable<'_, NoopRawMutex, ATTRIBUTES> = AttributeTable::new();
 
     // Generic Access Service (mandatory)
     let id = b"Pico W Bluetooth";
     let appearance = [0x80, 0x07];
     let mut bat_level = [0; 1];
-    let handle = {
+    let mut input_report = [0; XBOX_REPORT_LEN];
+    let mut output_report = [0; XBOX_OUTPUT_LEN];
+    let (handle, report_handle, output_handle) = {
         let mut svc = table.add_service(Service::new(0x1800));
         let _ = svc.add_characteristic_ro(0x2a00, id);
         let _ = svc.add_characteristic_ro(0x2a01, &appearance[..]);
@@ -38,12 +59,37 @@ pub async fn bluetooth_setup(bt_device: cyw43::bluetooth::BtDriver<'static>) {
         // Battery service
         let mut svc = table.add_service(Service::new(0x180f));
 
-        svc.add_characteristic(
-            0x2a19,
-            &[CharacteristicProp::Read, CharacteristicProp::Notify],
-            &mut bat_level,
-        )
-        .build()
+        let handle = svc
+            .add_characteristic(
+                0x2a19,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                &mut bat_level,
+            )
+            .build();
+        svc.build();
+
+        // the controller writes its input reports here
+        let mut svc = table.add_service(Service::new(0x1812));
+        let report_handle = svc
+            .add_characteristic(
+                0x2a4d,
+                &[
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                ],
+                &mut input_report,
+            )
+            .build();
+        // and is notified of rumble here
+        let output_handle = svc
+            .add_characteristic(
+                0x2a4d,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                &mut output_report,
+            )
+            .build();
+        svc.build();
+        (handle, report_handle, output_handle)
     };
 
     let mut adv_data = [0; 31];
@@ -65,6 +111,19 @@ pub async fn bluetooth_setup(bt_device: cyw43::bluetooth::BtDriver<'static>) {
         async {
             loop {
                 match server.next().await {
+                    Ok(GattEvent::Write {
+                        handle,
+                        connection: _,
+                    }) if handle == report_handle => {
+                        let _ = table.get(handle, |value| {
+                            let mut report = [0; XBOX_REPORT_LEN];
+                            let len = value.len().min(XBOX_REPORT_LEN);
+                            report[..len].copy_from_slice(&value[..len]);
+                            if RAW_REPORTS.try_send(report).is_err() {
+                                warn!("dropping controller report, input task is behind");
+                            }
+                        });
+                    }
                     Ok(GattEvent::Write {
                         handle,
                         connection: _,
@@ -82,26 +141,85 @@ pub async fn bluetooth_setup(bt_device: cyw43::bluetooth::BtDriver<'static>) {
                 }
             }
         },
-        async {
-            let mut advertiser = ble
-                .advertise(
-                    &Default::default(),
-                    Advertisement::ConnectableScannableUndirected {
-                        adv_data: &adv_data[..],
-                        scan_data: &[],
-                    },
-                )
-                .await
-                .unwrap();
-            let conn = advertiser.accept().await.unwrap();
-            // Keep connection alive
-            let mut tick: u8 = 0;
-            loop {
-                Timer::after(Duration::from_secs(10)).await;
-                tick += 1;
-                server.notify(handle, &conn, &[tick]).await.unwrap();
-            }
-        },
+        run(
+            BleLink {
+                ble: &ble,
+                server: &server,
+                handle,
+                output_handle,
+                adv_data: &adv_data[..],
+                conn: None,
+                tick: 0,
+            },
+            LinkConfig::default(),
+        ),
     )
     .await;
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum LinkError {
+    Advertise,
+    Accept,
+    Dropped,
+}
+
+// the controller connection as driven by `link::run`
+struct BleLink<'a, 'd> {
+    ble: &'a BleHost<'d, ExternalController<cyw43::bluetooth::BtDriver<'static>, 10>>,
+    server: &'a GattServer<'a, 'a, NoopRawMutex, ATTRIBUTES>,
+    handle: Characteristic,
+    output_handle: Characteristic,
+    adv_data: &'a [u8],
+    conn: Option<Connection<'a>>,
+    tick: u8,
+}
+
+impl Transport for BleLink<'_, '_> {
+    type Error = LinkError;
+
+    async fn connect(&mut self) -> Result<(), LinkError> {
+        self.conn = None;
+        // whatever was left over belongs to the last connection
+        RAW_REPORTS.clear();
+        let mut advertiser = self
+            .ble
+            .advertise(
+                &Default::default(),
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: self.adv_data,
+                    scan_data: &[],
+                },
+            )
+            .await
+            .map_err(|_| LinkError::Advertise)?;
+        self.conn = Some(advertiser.accept().await.map_err(|_| LinkError::Accept)?);
+        Ok(())
+    }
+
+    async fn poll(
+        &mut self,
+        keepalive: Duration,
+    ) -> Result<Option<[u8; XBOX_REPORT_LEN]>, LinkError> {
+        let conn = self.conn.as_ref().ok_or(LinkError::Dropped)?;
+        if let Either::First(report) = select(RAW_REPORTS.receive(), Timer::after(keepalive)).await
+        {
+            return Ok(Some(report));
+        }
+        // notifying fails as soon as the connection is gone
+        self.tick = self.tick.wrapping_add(1);
+        self.server
+            .notify(self.handle, conn, &[self.tick])
+            .await
+            .map(|_| None)
+            .map_err(|_| LinkError::Dropped)
+    }
+
+    async fn send(&mut self, report: &[u8; XBOX_OUTPUT_LEN]) -> Result<(), LinkError> {
+        let conn = self.conn.as_ref().ok_or(LinkError::Dropped)?;
+        self.server
+            .notify(self.output_handle, conn, report)
+            .await
+            .map_err(|_| LinkError::Dropped)
+    }
+}
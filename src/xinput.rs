@@ -0,0 +1,115 @@
+use super::feedback::{Rumble, XBOX_OUTPUT};
+use super::personality::{send_event, HostEvent};
+use super::{CONTROLLER_STATE, REPORT_INTERVAL_MS};
+use adapter_core::xinput::{self, rumble_levels, LED};
+use defmt::*;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::Driver;
+use embassy_time::Timer;
+use embassy_usb::driver::{Driver as UsbDriver, Endpoint, EndpointIn, EndpointOut};
+use embassy_usb::Builder;
+
+// A wired Xbox 360 controller, which pcs drive natively through xinput
+pub const XINPUT_VENDOR_ID: u16 = 0x045e;
+pub const XINPUT_PRODUCT_ID: u16 = 0x028e;
+pub const XINPUT_MANUFACTURER: &str = "©Microsoft Corporation";
+pub const XINPUT_PRODUCT: &str = "Controller";
+pub const XINPUT_DEVICE_RELEASE: u16 = 0x0114;
+// the device reports vendor specific class, subclass and protocol
+pub const XINPUT_DEVICE_CLASS: u8 = 0xff;
+
+const XINPUT_CLASS: u8 = 0xff;
+const XINPUT_SUBCLASS: u8 = 0x5d;
+const XINPUT_PROTOCOL: u8 = 0x01;
+// undocumented class descriptor the xinput driver expects after the interface, it names
+// the interrupt endpoints 0x81 and 0x01 and their report sizes
+const XINPUT_DESCRIPTOR_TYPE: u8 = 0x21;
+const XINPUT_DESCRIPTOR: [u8; 15] = [
+    0x00, 0x01, 0x01, 0x25, 0x81, 0x14, 0x00, 0x00, 0x00, 0x00, 0x13, 0x01, 0x08, 0x00, 0x00,
+];
+const XINPUT_PACKET_SIZE: u16 = 32;
+const XINPUT_IN_INTERVAL_MS: u8 = 4;
+const XINPUT_OUT_INTERVAL_MS: u8 = 8;
+
+// keeps the motors running until the host sends the next level, the xbox pad repeats
+// its longest pulse while waiting
+const RUMBLE_HOLD_MS: u16 = 2550;
+
+pub type XInputIn = <Driver<'static, USB> as UsbDriver<'static>>::EndpointIn;
+pub type XInputOut = <Driver<'static, USB> as UsbDriver<'static>>::EndpointOut;
+
+// adds the xinput interface in place of the hid one
+pub fn xinput_endpoints<D: UsbDriver<'static>>(
+    builder: &mut Builder<'static, D>,
+) -> (D::EndpointIn, D::EndpointOut) {
+    let mut function = builder.function(XINPUT_CLASS, XINPUT_SUBCLASS, XINPUT_PROTOCOL);
+    let mut interface = function.interface();
+    let mut alt = interface.alt_setting(XINPUT_CLASS, XINPUT_SUBCLASS, XINPUT_PROTOCOL, None);
+    alt.descriptor(XINPUT_DESCRIPTOR_TYPE, &XINPUT_DESCRIPTOR);
+    let ep_in = alt.endpoint_interrupt_in(XINPUT_PACKET_SIZE, XINPUT_IN_INTERVAL_MS);
+    let ep_out = alt.endpoint_interrupt_out(XINPUT_PACKET_SIZE, XINPUT_OUT_INTERVAL_MS);
+    (ep_in, ep_out)
+}
+
+// the rumble of a host message, if it is a rumble message
+pub fn parse_rumble(message: &[u8]) -> Option<Rumble> {
+    let (strong, weak) = rumble_levels(message)?;
+    let on = strong != 0 || weak != 0;
+    Some(Rumble {
+        strong,
+        weak,
+        on_ms: if on { RUMBLE_HOLD_MS } else { 0 },
+        off_ms: 0,
+        pulses: if on { u8::MAX } else { 1 },
+    })
+}
+
+#[embassy_executor::task]
+pub async fn xinput_writer(mut ep_in: XInputIn) -> ! {
+    ep_in.wait_enabled().await;
+    info!("running as an xbox 360 controller");
+    let mut polled = false;
+    loop {
+        Timer::after_millis(REPORT_INTERVAL_MS).await;
+        let state = {
+            let mut controller = CONTROLLER_STATE.get().await.lock().await;
+            let state = controller.output();
+            controller.advance();
+            state
+        };
+        match ep_in.write(&xinput::report(&state)).await {
+            Ok(()) if !polled => {
+                polled = true;
+                send_event(HostEvent::Polled)
+            }
+            Ok(()) => (),
+            Err(e) => {
+                warn!("usb write error: {}", e);
+                ep_in.wait_enabled().await;
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn xinput_reader(mut ep_out: XInputOut) -> ! {
+    ep_out.wait_enabled().await;
+    let mut buf = [0; XINPUT_PACKET_SIZE as usize];
+    loop {
+        match ep_out.read(&mut buf).await {
+            Ok(len) => match parse_rumble(&buf[..len]) {
+                Some(rumble) => {
+                    if XBOX_OUTPUT.try_send(rumble.report()).is_err() {
+                        warn!("xbox output full, dropping rumble");
+                    }
+                }
+                None if buf[0] == LED => debug!("xinput led pattern {}", buf[2]),
+                None => warn!("unknown xinput message: {:x}", &buf[..len]),
+            },
+            Err(e) => {
+                warn!("usb read error: {}", e);
+                ep_out.wait_enabled().await;
+            }
+        }
+    }
+}